@@ -2,24 +2,37 @@
 use clap::Parser;
 
 mod rtbox{
+    pub mod archive;
     pub mod cli;
     pub mod config;
+    pub mod control;
+    pub mod distro;
+    pub mod distrobox;
     pub mod engine;
     pub mod error;
+    pub mod export;
     pub mod formatters;
+    pub mod hooks;
+    pub mod host_commands;
+    pub mod host_exec;
+    pub mod host_sync;
     pub mod init;
+    pub mod labels;
+    pub mod os_release;
+    pub mod packages;
     pub mod podman;
+    pub mod profile;
+    pub mod services;
+    pub mod toolbx;
+    pub mod tty;
+    pub mod volume;
 }
 
-#[cfg(test)]
-mod tests {
-    pub mod engine;
-}
-
-use rtbox::cli::{TboxCli, TboxCliOutputFormat, TboxCommands, Output};
+use rtbox::cli::{multiplexed_args, TboxCli, TboxCliOutputFormat, TboxCommands, TboxServiceCommands, TboxVolumeCommands, Output};
 use rtbox::config::RtBoxConfig;
-use rtbox::engine::{RtBoxEngine, RtBox};
+use rtbox::engine::{RtBoxEngine, RtBox, RtBoxCreateOpts, RtBoxRunOpts};
 use rtbox::error::RtBoxError;
+use rtbox::init::RtBoxInitState;
 use rtbox::podman::PodmanEngine;
 use rtbox::formatters::{HumanFormatter, JsonFormatter, OutputFormatter};
 
@@ -29,8 +42,8 @@ async fn main() {
 
     env_logger::init();
 
-    let args = TboxCli::parse();
     let rtbox_config = RtBoxConfig::new("/etc/rtbox.json");
+    let args = TboxCli::parse_from(multiplexed_args(std::env::args().collect(), &rtbox_config));
     let podman_engine = PodmanEngine::new(&rtbox_config.socket_path);
     let rtbox_engine = RtBoxEngine::new(
         &rtbox_config,
@@ -38,17 +51,54 @@ async fn main() {
     );
 
     let output = match args.command {
-        TboxCommands::Create { name, image, home } => {
-            debug!("rtbox-create - name: {:?}, image: {:?}, home: {:?}",
+        TboxCommands::Create {
+            name,
+            image,
+            distro,
+            release,
+            home,
+            profile,
+            additional_packages,
+            pre_init_hook,
+            init_hook,
+            hook_timeout,
+            hook_failure_policy,
+            init,
+            ttl,
+            volume,
+            shared_volume,
+        } => {
+            debug!("rtbox-create - name: {:?}, image: {:?}, distro: {:?}, release: {:?}, home: {:?}, profile: {:?}, additional_packages: {:?}",
                 name,
                 image,
-                home.unwrap_or("<no home>".to_string())
+                distro,
+                release,
+                home,
+                profile,
+                additional_packages
             );
 
 
-            let image = image.unwrap_or("fedora-toolbox:38".to_string());
+            let create_opts = RtBoxCreateOpts {
+                profile,
+                home,
+                additional_packages: additional_packages.into_iter().filter(|it| !it.is_empty()).collect(),
+                pre_init_hooks: pre_init_hook,
+                init_hooks: init_hook,
+                hook_timeout,
+                hook_failure_policy,
+                init_mode: init,
+                ttl,
+                volumes: volume.into_iter().chain(shared_volume).collect(),
+                ..RtBoxCreateOpts::default()
+            };
+
+            let create_result = match rtbox_engine.resolve_image(image, distro, release) {
+                Ok(image) => rtbox_engine.create(&name, &image, &create_opts).await,
+                Err(error) => Err(error),
+            };
 
-            match rtbox_engine.create(&name, &image).await {
+            match create_result {
                 Ok(tbox) => Output::Create(tbox),
                 Err(error) => Output::Error(RtBoxError{
                     command: Some("create".to_string()),
@@ -64,13 +114,12 @@ async fn main() {
                 all
             );
 
-            if let Ok(tbox_rm_response) = rtbox_engine.rm(name[0].clone(), force, all).await {
-                Output::Rm(tbox_rm_response)
-            } else {
-                Output::Error(RtBoxError{
+            match rtbox_engine.rm(name[0].clone(), force, all).await {
+                Ok(tbox_rm_response) => Output::Rm(tbox_rm_response),
+                Err(e) => Output::Error(RtBoxError{
                     command: Some("rm".to_string()),
-                    message: Some("error creating container".to_string()),
-                    root_cause: Some("not implemented".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
                 })
             }
         }
@@ -86,26 +135,153 @@ async fn main() {
                 })
             }
         }
-        TboxCommands::Run { container, cmd } => {
-            debug!("rtbox-run - container: {:?}, cmd: {:?}", container, cmd);
+        TboxCommands::Run { container, image, rm, workdir, user, env, tty, no_tty, detach, timeout, cmd } => {
+            debug!(
+                "rtbox-run - container: {:?}, image: {:?}, rm: {:?}, workdir: {:?}, user: {:?}, cmd: {:?}",
+                container,
+                image,
+                rm,
+                workdir,
+                user,
+                cmd
+            );
+
+            let run_opts = RtBoxRunOpts {
+                user,
+                workdir,
+                env,
+                tty: if tty { Some(true) } else if no_tty { Some(false) } else { None },
+                detach,
+                timeout: timeout.map(std::time::Duration::from_secs),
+            };
 
-            match rtbox_engine.run(container, cmd).await {
+            let run_result = match (image, rtbox_engine.box_name(container)) {
+                (Some(image), _) => rtbox_engine.run_ephemeral(image, cmd, run_opts).await,
+                (None, Ok(container)) => rtbox_engine.run(container, cmd, run_opts).await,
+                (None, Err(error)) => Err(error),
+            };
+
+            match run_result {
                 Ok(rtbox_run_result) => Output::Run(rtbox_run_result),
                 Err(e) => Output::Error(RtBoxError{
                     command: Some("run".to_string()),
-                    message: Some("error running container".to_string()),
+                    message: e.message.or(Some("error running container".to_string())),
                     root_cause: e.root_cause,
                 })
             }
         }
-        TboxCommands::Enter { name } => {
-            debug!("rtbox-enter - container: {:?}", name);
+        TboxCommands::Enter { name, workdir } => {
+            debug!("rtbox-enter - container: {:?}, workdir: {:?}", name, workdir);
 
-            Output::Error(RtBoxError{
-                command: Some("enter".to_string()),
-                message: Some("error creating container".to_string()),
-                root_cause: Some("not implemented".to_string()),
-            })
+            let enter_result = match rtbox_engine.box_name(name) {
+                Ok(name) => rtbox_engine.enter(&name, workdir).await,
+                Err(error) => Err(error),
+            };
+
+            match enter_result {
+                Ok(rtbox_enter_result) => Output::Enter(rtbox_enter_result),
+                Err(e) => Output::Error(RtBoxError{
+                    command: Some("enter".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                })
+            }
+        }
+        TboxCommands::Adopt { name, profile } => {
+            debug!("rtbox-adopt - name: {:?}, profile: {:?}", name, profile);
+
+            match rtbox_engine.adopt(&name, profile).await {
+                Ok(tbox) => Output::Adopt(tbox),
+                Err(e) => Output::Error(RtBoxError{
+                    command: Some("adopt".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                })
+            }
+        }
+        TboxCommands::Upgrade { name, force, preserve } => {
+            debug!("rtbox-upgrade - name: {:?}, force: {:?}, preserve: {:?}", name, force, preserve);
+
+            match rtbox_engine.upgrade(&name, force, &preserve).await {
+                Ok(upgrade) => Output::Upgrade(upgrade),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("upgrade".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Commit { name, image } => {
+            debug!("rtbox-commit - name: {:?}, image: {:?}", name, image);
+
+            match rtbox_engine.commit(&name, &image).await {
+                Ok(image_info) => Output::Commit(image_info),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("commit".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Clone { source, name } => {
+            debug!("rtbox-clone - source: {:?}, name: {:?}", source, name);
+
+            match rtbox_engine.clone_box(&source, &name).await {
+                Ok(tbox) => Output::Clone(tbox),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("clone".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Save { name, output } => {
+            debug!("rtbox-save - name: {:?}, output: {:?}", name, output);
+
+            match rtbox_engine.save(&name, std::path::Path::new(&output)).await {
+                Ok(manifest) => Output::Save(Box::new(manifest)),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("save".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Load { file, name } => {
+            debug!("rtbox-load - file: {:?}, name: {:?}", file, name);
+
+            match rtbox_engine.load(std::path::Path::new(&file), name).await {
+                Ok(tbox) => Output::Load(tbox),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("load".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Prune { unused, dry_run } => {
+            debug!("rtbox-prune - unused: {:?}, dry_run: {:?}", unused, dry_run);
+
+            match rtbox_engine.prune(unused, dry_run).await {
+                Ok(report) => Output::Prune(report),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("prune".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Rename { name, new_name } => {
+            debug!("rtbox-rename - name: {:?}, new_name: {:?}", name, new_name);
+
+            match rtbox_engine.rename(&name, &new_name).await {
+                Ok(tbox) => Output::Rename(tbox),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("rename".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
         }
         TboxCommands::Export { container, binary, service_unit, application } => {
             debug!(
@@ -116,11 +292,35 @@ async fn main() {
                 application,
             );
 
-            Output::Error(RtBoxError{
-                command: Some("export".to_string()),
-                message: Some("error creating container".to_string()),
-                root_cause: Some("not implemented".to_string()),
-            })
+            match rtbox_engine.export(&container, binary, service_unit, application).await {
+                Ok(exports) => Output::Export(exports),
+                Err(e) => Output::Error(RtBoxError{
+                    command: Some("export".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                })
+            }
+        }
+        TboxCommands::Assemble { file, name } => {
+            debug!("rtbox-assemble - file: {:?}, name: {:?}", file, name);
+
+            let assemble_result = match std::fs::read_to_string(&file) {
+                Ok(contents) => rtbox_engine.assemble(&contents, name.as_deref()).await,
+                Err(e) => Err(RtBoxError{
+                    command: None,
+                    message: Some(format!("could not read {}", file)),
+                    root_cause: Some(e.to_string()),
+                }),
+            };
+
+            match assemble_result {
+                Ok(tbox_list) => Output::Assemble(tbox_list),
+                Err(e) => Output::Error(RtBoxError{
+                    command: Some("assemble".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                })
+            }
         }
         TboxCommands::Rmi { all, force, image_name } => {
             debug!(
@@ -136,7 +336,132 @@ async fn main() {
                 root_cause: Some("not implemented".to_string()),
             })
         }
-        TboxCommands::Init { uid, gid, username, home, shell } => {
+        TboxCommands::Inspect { name, field } => {
+            debug!("rtbox-inspect - name: {:?}, field: {:?}", name, field);
+
+            match rtbox_engine.inspect(&name).await {
+                Ok(inspect) => match field {
+                    Some(field) => Output::InspectField(inspect.field(&field).unwrap_or_default()),
+                    None => Output::Inspect(Box::new(inspect)),
+                },
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("inspect".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Stop { name } => {
+            debug!("rtbox-stop - name: {:?}", name);
+
+            match rtbox_engine.stop(&name).await {
+                Ok(()) => Output::Stop(()),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("stop".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Service { command } => {
+            debug!("rtbox-service - command: {:?}", command);
+
+            let service_result = match command {
+                TboxServiceCommands::List { container } => rtbox_engine
+                    .service_list(&container)
+                    .await
+                    .map(Output::Service),
+                TboxServiceCommands::Start { container, service } => rtbox_engine
+                    .service_start(&container, &service)
+                    .await
+                    .map(|status| Output::Service(vec![status])),
+                TboxServiceCommands::Stop { container, service } => rtbox_engine
+                    .service_stop(&container, &service)
+                    .await
+                    .map(|status| Output::Service(vec![status])),
+                TboxServiceCommands::Logs { container, service, lines } => rtbox_engine
+                    .service_logs(&container, &service, lines)
+                    .await
+                    .map(Output::ServiceLogs),
+            };
+
+            match service_result {
+                Ok(output) => output,
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("service".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Volume { command } => {
+            debug!("rtbox-volume - command: {:?}", command);
+
+            let volume_result = match command {
+                TboxVolumeCommands::List => rtbox_engine
+                    .volume_list()
+                    .await
+                    .map(Output::Volume),
+                TboxVolumeCommands::Create { name, container } => rtbox_engine
+                    .volume_create(&name, container.as_deref())
+                    .await
+                    .map(|volume| Output::Volume(vec![volume])),
+                TboxVolumeCommands::Rm { name, container, force } => rtbox_engine
+                    .volume_rm(&name, container.as_deref(), force)
+                    .await
+                    .map(Output::Rm),
+            };
+
+            match volume_result {
+                Ok(output) => output,
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("volume".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::HostExec { no_pty, forwarded, command } => {
+            debug!("rtbox-host-exec - no_pty: {:?}, forwarded: {:?}, command: {:?}", no_pty, forwarded, command);
+
+            match rtbox_engine.host_exec(command, !no_pty) {
+                Ok(exec_output) => Output::HostExec(exec_output),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("host-exec".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::HostHelper { name } => {
+            debug!("rtbox-host-helper - name: {:?}", name);
+
+            match rtbox_engine.host_helper(&name).await {
+                Ok(()) => Output::HostHelper(()),
+                Err(e) => Output::Error(RtBoxError {
+                    command: Some("host-helper".to_string()),
+                    message: e.message,
+                    root_cause: e.root_cause,
+                }),
+            }
+        }
+        TboxCommands::Init {
+            uid,
+            gid,
+            username,
+            home,
+            shell,
+            additional_packages,
+            pre_init_hook,
+            init_hook,
+            hook_timeout,
+            hook_failure_policy,
+            service,
+            init_mode,
+            oneshot,
+            host_command,
+            command_not_found,
+        } => {
             debug!(
                 "rtbox-init - gid: {:?}, home: {:?}, shell: {:?}",
                 gid,
@@ -144,14 +469,43 @@ async fn main() {
                 shell
             );
 
-            if std::process::id() != 1 {
+            let services: Result<Vec<_>, _> = service.iter()
+                .map(|service| serde_json::from_str(service))
+                .collect();
+
+            /* the systemd setup unit runs us as a regular process */
+            if std::process::id() != 1 && !oneshot {
                 Output::Error(RtBoxError {
                     command: Some("init".to_string()),
                     message: Some("this is only supposed to be run as the init system of a container".to_string()),
                     root_cause:Some("we are not running as PID 1".to_string()),
                 })
+            } else if let Err(err) = &services {
+                Output::Error(RtBoxError {
+                    command: Some("init".to_string()),
+                    message: Some("invalid service definition".to_string()),
+                    root_cause: Some(err.to_string()),
+                })
             } else {
-                match rtbox_engine.init(uid, gid, &username, &home, &shell).await {
+                let init_state = RtBoxInitState {
+                    uid,
+                    gid,
+                    username: &username,
+                    home: &home,
+                    shell: &shell,
+                    additional_packages: &additional_packages,
+                    pre_init_hooks: &pre_init_hook,
+                    init_hooks: &init_hook,
+                    hook_timeout,
+                    hook_failure_policy,
+                    services: services.as_deref().unwrap_or_default(),
+                    mode: init_mode,
+                    oneshot,
+                    host_commands: &host_command,
+                    command_not_found,
+                };
+
+                match rtbox_engine.init(&init_state).await {
                     Some(e) => Output::Error(RtBoxError {
                         command: Some("init".to_string()),
                         message: Some("container init system crashed".to_string()),
@@ -168,7 +522,35 @@ async fn main() {
         TboxCliOutputFormat::Json => Box::new(JsonFormatter{}),
     };
 
-    if let Some(output) = formatter.format(&output) {
-        println!("{}", output);
+    if let Some(formatted_output) = formatter.format(&output) {
+        println!("{}", formatted_output);
     }
+
+    let exit_code = match output {
+        Output::Run(exec_output)
+            | Output::Enter(exec_output)
+            | Output::HostExec(exec_output) => exec_output.return_code as i32,
+        Output::Error(_) => 1,
+        _ => 0,
+    };
+    std::process::exit(exit_code);
+}
+
+#[cfg(test)]
+mod tests {
+    pub mod archive;
+    pub mod cli;
+    pub mod control;
+    pub mod distro;
+    pub mod distrobox;
+    pub mod engine;
+    pub mod export;
+    pub mod hooks;
+    pub mod host_commands;
+    pub mod host_exec;
+    pub mod host_sync;
+    pub mod init;
+    pub mod packages;
+    pub mod services;
+    pub mod volume;
 }
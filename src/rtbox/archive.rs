@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::rtbox::engine::RtBoxContainerInfo;
+use crate::rtbox::export::RtBoxExport;
+use crate::rtbox::hooks::RtBoxHookPhase;
+use crate::rtbox::labels::RtBoxLabels;
+
+/// Bumped whenever the layout of `rtbox save` archives changes
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "rtbox.json";
+const IMAGE_ENTRY: &str = "image.tar";
+
+/// Name of the pre-init hook fixing file ownership when a box is loaded by another uid
+pub const OWNERSHIP_HOOK: &str = "00-rtbox-load-ownership";
+
+/// Everything besides the image needed to recreate a saved box on another host
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxArchiveManifest {
+    pub archive_version: u32,
+    pub name: String,
+    /// Name the committed image was saved under
+    pub image: String,
+    pub container: RtBoxContainerInfo,
+    /// HOME of the user who saved the box, paths below it follow the HOME of whoever loads it
+    pub home: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub exports: Vec<RtBoxExport>,
+}
+
+/// Move `path` from below `old_home` to below `new_home`, other paths are returned as they are
+pub fn rebase_path(path: &str, old_home: &str, new_home: &str) -> String {
+    match Path::new(path).strip_prefix(old_home) {
+        Ok(relative) if !old_home.is_empty() => Path::new(new_home).join(relative).display().to_string(),
+        _ => path.to_string(),
+    }
+}
+
+impl RtBoxArchiveManifest {
+    /// The saved container with its mounts, volumes and custom home moved to `new_home`
+    pub fn rebased_container(&self, new_home: &str) -> RtBoxContainerInfo {
+        let mut container = self.container.clone();
+
+        for mount in container.mounts.iter_mut() {
+            mount.source = rebase_path(&mount.source, &self.home, new_home);
+        }
+        for volume in container.volumes.iter_mut() {
+            volume.destination = rebase_path(&volume.destination, &self.home, new_home);
+        }
+        if let Some(mut recorded) = RtBoxLabels::from_labels(&container.labels) {
+            recorded.home = recorded.home.map(|home| rebase_path(&home, &self.home, new_home));
+            container.labels.extend(recorded.to_labels());
+        }
+
+        container
+    }
+}
+
+fn entry_header(size: u64, mode: u32) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(mode);
+    header.set_cksum();
+    header
+}
+
+fn append_entry(builder: &mut tar::Builder<impl io::Write>, path: &str, contents: &[u8], mode: u32) -> io::Result<()> {
+    builder.append_data(&mut entry_header(contents.len() as u64, mode), path, contents)
+}
+
+/// Write `manifest` and the image archive at `image_archive` to `path`, the image is copied over as it is read
+pub fn write(path: &Path, manifest: &RtBoxArchiveManifest, image_archive: &Path) -> io::Result<()> {
+    let mut builder = tar::Builder::new(File::create(path)?);
+    let image_file = File::open(image_archive)?;
+
+    append_entry(&mut builder, MANIFEST_ENTRY, &serde_json::to_vec_pretty(manifest)?, 0o644)?;
+    let mut header = entry_header(image_file.metadata()?.len(), 0o644);
+    builder.append_data(&mut header, IMAGE_ENTRY, image_file)?;
+
+    builder.into_inner()?.sync_all()
+}
+
+/// The manifest stored by `write` and where in `path` the image archive is, so it can be read without unpacking it
+pub fn read(path: &Path) -> io::Result<(RtBoxArchiveManifest, Range<u64>)> {
+    let mut archive = tar::Archive::new(File::open(path)?);
+    let mut manifest = None;
+    let mut image_archive = None;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.display().to_string();
+
+        match entry_path.as_str() {
+            MANIFEST_ENTRY => {
+                let mut contents = vec![];
+                entry.read_to_end(&mut contents)?;
+                manifest = Some(serde_json::from_slice::<RtBoxArchiveManifest>(&contents)?);
+            },
+            IMAGE_ENTRY => {
+                let start = entry.raw_file_position();
+                image_archive = Some(start..start + entry.size());
+            },
+            _ => debug!("ignoring {} in {}", entry_path, path.display()),
+        }
+    }
+
+    match (manifest, image_archive) {
+        (Some(manifest), Some(image_archive)) => Ok((manifest, image_archive)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "not a rtbox archive")),
+    }
+}
+
+/// Tar archive with a one shot pre-init hook handing files owned by the saving user over to the loading one,
+/// to be extracted at `/`, it carries the hook directory so images without one get it
+pub fn ownership_hook(old_uid: u32, old_gid: u32, new_uid: u32, new_gid: u32) -> io::Result<Vec<u8>> {
+    let script = format!("\
+        #!/bin/sh\n\
+        find / -xdev -uid {old_uid} -exec chown -h {new_uid} {{}} +\n\
+        find / -xdev -gid {old_gid} -exec chgrp -h {new_gid} {{}} +\n\
+        rm -f \"$0\"\n",
+    );
+
+    let hook_dir = Path::new("etc/rtbox").join(RtBoxHookPhase::PreInit.dir_name());
+    let mut builder = tar::Builder::new(vec![]);
+    for dir in hook_dir.ancestors().filter(|dir| !dir.as_os_str().is_empty()).collect::<Vec<_>>().into_iter().rev() {
+        let mut header = entry_header(0, 0o755);
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_cksum();
+        builder.append_data(&mut header, format!("{}/", dir.display()), io::empty())?;
+    }
+    append_entry(&mut builder, &hook_dir.join(OWNERSHIP_HOOK).display().to_string(), script.as_bytes(), 0o755)?;
+    builder.into_inner()
+}
@@ -1,16 +1,26 @@
 use std::vec::Vec;
 use clap::{Parser, Subcommand, ValueEnum};
+use clap::builder::PossibleValuesParser;
 use serde::{Serialize, Deserialize};
 
 use crate::RtBox;
 use crate::RtBoxError;
-use crate::rtbox::engine::RtBoxExecOutput;
+use crate::rtbox::archive::RtBoxArchiveManifest;
+use crate::rtbox::config::RtBoxConfig;
+use crate::rtbox::engine::{RtBoxExecOutput, RtBoxImageInfo, RtBoxInspect, RtBoxPruneReport, RtBoxUpgrade};
+use crate::rtbox::export::RtBoxExport;
+use crate::rtbox::hooks::RtBoxHookFailurePolicy;
+use crate::rtbox::init::RtBoxInitMode;
+use crate::rtbox::profile::RtBoxProfile;
+use crate::rtbox::services::RtBoxServiceStatus;
+use crate::rtbox::volume::{RtBoxVolumeConfig, RtBoxVolumeInfo};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum TboxCliOutputFormat {
     Json,
     Human,
 }
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum TboxLogLevel {
     INFO,
@@ -59,11 +69,57 @@ pub enum TboxCommands {
         name: String,
         /// Image to use as base for the container
         #[clap(short, long)]
+        #[arg(conflicts_with_all = ["distro", "release"])]
         image: Option<String>,
+        /// Distro to create the container from, defaults to the host distro
+        #[clap(short, long)]
+        distro: Option<String>,
+        /// Release of the distro to use, defaults to the host release
+        #[clap(short, long)]
+        release: Option<String>,
         /// Set a custom HOME directory for the container
         #[clap(short, long)]
         #[arg(short = 'H')]
         home: Option<String>,
+        /// Security profile used to integrate the container with the host
+        #[clap(short, long)]
+        #[arg(value_enum)]
+        #[arg(default_value_t = RtBoxProfile::Default)]
+        profile: RtBoxProfile,
+        /// Packages to install when the container first starts, may be repeated or space separated
+        #[clap(short, long)]
+        #[arg(value_delimiter = ' ')]
+        additional_packages: Vec<String>,
+        /// Shell command run before packages are installed on first start, may be repeated
+        #[clap(long)]
+        pre_init_hook: Vec<String>,
+        /// Shell command run at the end of initialization, may be repeated
+        #[clap(long)]
+        init_hook: Vec<String>,
+        /// Seconds each hook may run before it is killed
+        #[clap(long)]
+        hook_timeout: Option<u64>,
+        /// What to do when a hook fails
+        #[clap(long)]
+        #[arg(value_enum)]
+        hook_failure_policy: Option<RtBoxHookFailurePolicy>,
+        /// What runs as PID 1 inside the container
+        #[clap(long)]
+        #[arg(value_enum)]
+        #[arg(default_value_t = RtBoxInitMode::Rtbox)]
+        init: RtBoxInitMode,
+        /// Let prune remove the container after this long, e.g. 12h or 7d
+        #[clap(long)]
+        #[arg(value_parser = parse_duration)]
+        ttl: Option<u64>,
+        /// Volume of this container as NAME:PATH, kept when the container is recreated, may be repeated
+        #[clap(long)]
+        #[arg(value_parser = |spec: &str| parse_volume(spec, false))]
+        volume: Vec<RtBoxVolumeConfig>,
+        /// Volume shared with every container using the same NAME as NAME:PATH, may be repeated
+        #[clap(long)]
+        #[arg(value_parser = |spec: &str| parse_volume(spec, true))]
+        shared_volume: Vec<RtBoxVolumeConfig>,
     },
     /// Remove a rtbox container
     #[command(arg_required_else_help = true)]
@@ -94,17 +150,46 @@ pub enum TboxCommands {
     /// Execute a command inside a rtbox container
     #[command(arg_required_else_help = true)]
     Run {
-        /// Container name
+        /// Container name, defaults to the configured default box
         #[clap(short, long)]
-        container: String,
+        container: Option<String>,
+        /// Run in a temporary container created from this image instead
+        #[clap(short, long, conflicts_with = "container", requires = "rm")]
+        image: Option<String>,
+        /// Remove the temporary container once the command exits
+        #[clap(long, requires = "image", conflicts_with = "detach")]
+        rm: bool,
+        /// Directory to run the command in, defaults to the current one when the container can see it
+        #[clap(short, long)]
+        workdir: Option<String>,
+        /// User to run the command as, defaults to the current one
+        #[clap(short, long)]
+        user: Option<String>,
+        /// Set an environment variable as KEY=VALUE, a bare KEY takes the value from the host
+        #[clap(short, long)]
+        env: Vec<String>,
+        /// Allocate a terminal even if stdin is not one
+        #[clap(short, long, conflicts_with = "no_tty")]
+        tty: bool,
+        /// Never allocate a terminal
+        #[clap(short = 'T', long)]
+        no_tty: bool,
+        /// Run the command in the background and print its exec ID
+        #[clap(short, long)]
+        detach: bool,
+        /// Seconds after which the command and everything it started are killed
+        #[clap(long, conflicts_with = "detach")]
+        timeout: Option<u64>,
         /// Command to execute
         cmd: Vec<String>,
     },
     /// Enter into a new shell session inside a rtbox container
-    #[command(arg_required_else_help = true)]
     Enter {
-        /// Container to enter into
-        name: String,
+        /// Container to enter into, defaults to the configured default box
+        name: Option<String>,
+        /// Directory to start in, defaults to the current one when the container can see it
+        #[clap(short, long)]
+        workdir: Option<String>,
     },
     /// List all rtbox containers
     List {
@@ -112,6 +197,16 @@ pub enum TboxCommands {
         #[clap(short, long)]
         all: Option<bool>,
     },
+    /// Show how a rtbox container was set up and its current state
+    #[command(arg_required_else_help = true)]
+    Inspect {
+        /// Container to inspect
+        name: String,
+        /// Only show this field
+        #[clap(short, long)]
+        #[arg(value_parser = PossibleValuesParser::new(RtBoxInspect::FIELDS))]
+        field: Option<String>,
+    },
     /// Export an application, service or binary from a rtbox container to the host
     #[command(arg_required_else_help = true)]
     Export {
@@ -128,6 +223,125 @@ pub enum TboxCommands {
         #[clap(short, long)]
         application: Option<String>,
     },
+    /// Recreate a container created by toolbx so it is managed by rtbox
+    #[command(arg_required_else_help = true)]
+    Adopt {
+        /// Toolbx container to adopt, its image, name and mounts are preserved
+        name: String,
+        /// Security profile used to integrate the container with the host
+        #[clap(short, long)]
+        #[arg(value_enum)]
+        #[arg(default_value_t = RtBoxProfile::Default)]
+        profile: RtBoxProfile,
+    },
+    /// Recreate a rtbox container on the latest version of its image, keeping its parameters
+    #[command(arg_required_else_help = true)]
+    Upgrade {
+        /// Container to upgrade
+        name: String,
+        /// Recreate the container even if its image did not change
+        #[clap(short, long)]
+        force: bool,
+        /// Container path to keep in a volume from now on, can be repeated
+        #[clap(short, long)]
+        preserve: Vec<String>,
+    },
+    /// Save a container as an image others can create rtbox containers from
+    #[command(arg_required_else_help = true)]
+    Commit {
+        /// Container to commit, it keeps running while it is snapshotted
+        name: String,
+        /// Image name and optional tag to create
+        image: String,
+    },
+    /// Copy a rtbox container into a new one with the same options
+    #[command(arg_required_else_help = true)]
+    Clone {
+        /// Container to copy, it keeps running while it is snapshotted
+        source: String,
+        /// Name of the new container
+        name: String,
+    },
+    /// Save a rtbox container with its options and exports into an archive for another host
+    #[command(arg_required_else_help = true)]
+    Save {
+        /// Container to save, what its per box volumes hold is left out
+        name: String,
+        /// Archive to write
+        #[clap(short, long)]
+        output: String,
+    },
+    /// Recreate a rtbox container from an archive written by save
+    #[command(arg_required_else_help = true)]
+    Load {
+        /// Archive to read
+        file: String,
+        /// Name of the new container, defaults to the saved name
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+    /// Remove expired and unused containers, dangling toolbox images and orphaned exports
+    Prune {
+        /// Also remove stopped containers without a session for this long, e.g. 30d
+        #[clap(long)]
+        #[arg(value_parser = parse_duration)]
+        unused: Option<u64>,
+        /// Only show what would be removed
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Rename a rtbox container along with its hostname and exports
+    #[command(arg_required_else_help = true)]
+    Rename {
+        /// Container to rename
+        name: String,
+        /// New name of the container
+        new_name: String,
+    },
+    /// Create rtbox containers from a distrobox-assemble ini file
+    #[command(arg_required_else_help = true)]
+    Assemble {
+        /// Path to the distrobox-assemble file
+        file: String,
+        /// Only create the container defined in this section
+        #[clap(short, long)]
+        name: Option<String>,
+    },
+    /// Stop a rtbox container, letting init stop its services first
+    #[command(arg_required_else_help = true)]
+    Stop {
+        /// Container to stop
+        name: String,
+    },
+    /// Manage the services supervised inside a rtbox container
+    #[command(arg_required_else_help = true)]
+    Service {
+        #[command(subcommand)]
+        command: TboxServiceCommands,
+    },
+    /// Manage the volumes rtbox containers keep package caches and toolchains in
+    #[command(arg_required_else_help = true)]
+    Volume {
+        #[command(subcommand)]
+        command: TboxVolumeCommands,
+    },
+    /// Run a command on the host from inside a rtbox container
+    HostExec {
+        /// Run the command without a terminal even if we have one
+        #[clap(long)]
+        no_pty: bool,
+        /// Passed by shims and the command_not_found hook, the host checks every command either way
+        #[clap(long, hide = true)]
+        forwarded: bool,
+        /// Command to execute, the host shell when empty and the host command policy allows it
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Serves host-exec requests of a rtbox container
+    #[command(arg_required_else_help = true, hide = true)]
+    HostHelper {
+        name: String,
+    },
     /// Used to initialize rtbox containers
     #[command(arg_required_else_help = true, hide = true)]
     Init {
@@ -141,15 +355,174 @@ pub enum TboxCommands {
         home: String,
         #[clap(long)]
         shell: String,
+        #[clap(long)]
+        additional_packages: Vec<String>,
+        #[clap(long)]
+        pre_init_hook: Vec<String>,
+        #[clap(long)]
+        init_hook: Vec<String>,
+        #[clap(long)]
+        hook_timeout: u64,
+        #[clap(long)]
+        #[arg(value_enum)]
+        hook_failure_policy: RtBoxHookFailurePolicy,
+        #[clap(long)]
+        service: Vec<String>,
+        #[clap(long)]
+        #[arg(value_enum)]
+        #[arg(default_value_t = RtBoxInitMode::Rtbox)]
+        init_mode: RtBoxInitMode,
+        #[clap(long)]
+        oneshot: bool,
+        #[clap(long)]
+        host_command: Vec<String>,
+        #[clap(long)]
+        command_not_found: bool,
+    },
+}
+
+/// Name under which we behave like host-spawn, e.g. through a symlink inside a box
+pub const HOST_SPAWN_NAME: &str = "host-spawn";
+
+/// Command line arguments with invocations through a symlink mapped to the rtbox command they stand for,
+/// names of boxes in the configuration, the default box included, enter or run the arguments in that box
+pub fn multiplexed_args(args: Vec<String>, config: &RtBoxConfig) -> Vec<String> {
+    let program = args.first()
+        .and_then(|arg0| std::path::Path::new(arg0).file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let rest = args.iter().skip(1).cloned();
+
+    if program == HOST_SPAWN_NAME {
+        ["rtbox".to_string(), "host-exec".to_string()].into_iter()
+            .chain(rest)
+            .collect()
+    } else if !config.boxes.contains_key(&program) && config.default_box.as_ref() != Some(&program) {
+        /* renamed or copied binaries still behave like rtbox */
+        args
+    } else if args.len() == 1 {
+        vec!["rtbox".to_string(), "enter".to_string(), program]
+    } else {
+        ["rtbox".to_string(), "run".to_string(), "-c".to_string(), program, "--".to_string()].into_iter()
+            .chain(rest)
+            .collect()
+    }
+}
+
+/// Seconds in a duration like `90`, `30m`, `12h`, `7d` or `2w`, a bare number is in seconds
+pub fn parse_duration(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.find(|it: char| !it.is_ascii_digit()).unwrap_or(value.len()));
+    let number: u64 = number.parse().map_err(|_| format!("invalid duration {:?}", value))?;
+
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("invalid duration unit {:?}, expected one of s, m, h, d or w", unit)),
+    };
+
+    number.checked_mul(multiplier).ok_or(format!("duration {:?} is too long", value))
+}
+
+/// A `NAME:PATH` volume for `create`, PATH may start with `~/` for the home of the container user
+pub fn parse_volume(spec: &str, shared: bool) -> Result<RtBoxVolumeConfig, String> {
+    RtBoxVolumeConfig::parse(spec, shared)
+        .ok_or(format!("invalid volume {:?}, expected NAME:PATH with an absolute PATH or one starting with ~/", spec))
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TboxServiceCommands {
+    /// List the services of a container
+    #[command(arg_required_else_help = true)]
+    List {
+        /// Container to list services of
+        container: String,
+    },
+    /// Start a service and the services it depends on
+    #[command(arg_required_else_help = true)]
+    Start {
+        /// Container running the service
+        container: String,
+        /// Service to start
+        service: String,
+    },
+    /// Stop a service
+    #[command(arg_required_else_help = true)]
+    Stop {
+        /// Container running the service
+        container: String,
+        /// Service to stop
+        service: String,
+    },
+    /// Show the latest output of a service
+    #[command(arg_required_else_help = true)]
+    Logs {
+        /// Container running the service
+        container: String,
+        /// Service to show the output of
+        service: String,
+        /// Number of lines to show
+        #[clap(short = 'n', long)]
+        #[arg(default_value_t = 100)]
+        lines: usize,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TboxVolumeCommands {
+    /// List the volumes created by rtbox
+    List,
+    /// Create a volume ahead of the containers using it
+    #[command(arg_required_else_help = true)]
+    Create {
+        /// Volume name as given to create --volume or --shared-volume
+        name: String,
+        /// Container the volume belongs to, the shared volume is created when unset
+        #[clap(short, long)]
+        container: Option<String>,
+    },
+    /// Remove a volume and everything stored in it
+    #[command(arg_required_else_help = true)]
+    Rm {
+        /// Volume name as given to create --volume or --shared-volume
+        name: String,
+        /// Container the volume belongs to, the shared volume is removed when unset
+        #[clap(short, long)]
+        container: Option<String>,
+        /// Remove the volume even if containers are using it
+        #[clap(short, long)]
+        force: bool,
     },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Output {
+    Adopt(RtBox),
+    Commit(RtBoxImageInfo),
     Create(RtBox),
+    Assemble(Vec<RtBox>),
+    Clone(RtBox),
+    Enter(RtBoxExecOutput),
+    Export(Vec<RtBoxExport>),
+    HostExec(RtBoxExecOutput),
+    HostHelper(()),
     Init(()),
+    Inspect(Box<RtBoxInspect>),
+    InspectField(serde_json::Value),
     List(Vec<RtBox>),
+    Prune(RtBoxPruneReport),
+    Load(RtBox),
     Run(RtBoxExecOutput),
+    Rename(RtBox),
     Rm(()),
+    Save(Box<RtBoxArchiveManifest>),
+    Service(Vec<RtBoxServiceStatus>),
+    Stop(()),
+    ServiceLogs(String),
+    Upgrade(RtBoxUpgrade),
+    Volume(Vec<RtBoxVolumeInfo>),
     Error(RtBoxError),
 }
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::File;
 use std::io::BufReader;
@@ -5,10 +6,63 @@ use std::path::Path;
 
 use serde::{Serialize, Deserialize};
 
+use crate::rtbox::distro::RtBoxDistro;
+use crate::rtbox::host_commands::RtBoxHostCommandsConfig;
+use crate::rtbox::services::RtBoxService;
+use crate::rtbox::hooks::{RtBoxHookFailurePolicy, DEFAULT_HOOK_TIMEOUT};
+use crate::rtbox::init::RtBoxInitMode;
+use crate::rtbox::profile::RtBoxProfile;
+use crate::rtbox::volume::RtBoxVolumeConfig;
+
+/// Settings that only apply to the box with the same name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RtBoxBoxConfig {
+    /// Long running processes init supervises inside the box
+    pub services: Vec<RtBoxService>,
+    /// Used when enter or run create the box on first use, like the `create` options of the same name
+    pub image: Option<String>,
+    pub distro: Option<String>,
+    pub release: Option<String>,
+    pub profile: RtBoxProfile,
+    pub home: Option<String>,
+    pub additional_packages: Vec<String>,
+    pub pre_init_hooks: Vec<String>,
+    pub init_hooks: Vec<String>,
+    pub init: RtBoxInitMode,
+    /// Container paths kept in per box volumes so upgrade carries them over, on top of the global ones
+    pub preserve_paths: Vec<String>,
+    /// Volumes mounted into the box on top of the global ones
+    pub volumes: Vec<RtBoxVolumeConfig>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RtBoxConfig {
-    pub default_image: String,
+    /// Image used when create gets no image, distro or release, the host distro is used when unset
+    pub default_image: Option<String>,
+    /// Box used by enter and run when they get no name
+    pub default_box: Option<String>,
     pub socket_path: String,
+    /// Extra distros for `--distro`, entries with a built-in name replace the built-in one
+    pub distros: BTreeMap<String, RtBoxDistro>,
+    /// Host paths mounted into boxes using the restricted profile, as `SOURCE[:DESTINATION[:OPTIONS]]`
+    pub restricted_mounts: Vec<String>,
+    /// Seccomp profile applied to boxes using the restricted profile, the engine default is used when unset
+    pub seccomp_profile: Option<String>,
+    /// Seconds each init hook may run before it is killed, boxes can override it at create time
+    pub hook_timeout: u64,
+    /// What init does when a hook fails, boxes can override it at create time
+    pub hook_failure_policy: RtBoxHookFailurePolicy,
+    /// Per box settings keyed by box name, read when the box is created
+    pub boxes: BTreeMap<String, RtBoxBoxConfig>,
+    /// Host commands boxes reach through shims and the command_not_found hook
+    pub host_commands: RtBoxHostCommandsConfig,
+    /// Container paths every box keeps in a volume of its own so upgrade carries them over, e.g. `/var/lib/dnf`
+    pub preserve_paths: Vec<String>,
+    /// Volumes mounted into every box, e.g. a shared `cargo-registry` at `~/.cargo/registry`,
+    /// the ones below `~/` are skipped for boxes sharing the host home
+    pub volumes: Vec<RtBoxVolumeConfig>,
 }
 
 impl Default for RtBoxConfig {
@@ -16,8 +70,20 @@ impl Default for RtBoxConfig {
         let rtbox_podman_socket_path = env::var("RTBOX_PODMAN_SOCKET")
             .unwrap_or("unix:///var/run/docker.sock".to_string());
         Self {
-            default_image: "fedora:latest".to_string(),
+            default_image: None,
+            default_box: None,
             socket_path: rtbox_podman_socket_path,
+            distros: BTreeMap::new(),
+            restricted_mounts: vec![
+                "/etc/machine-id:/etc/machine-id:ro".to_string(),
+            ],
+            seccomp_profile: None,
+            hook_timeout: DEFAULT_HOOK_TIMEOUT,
+            hook_failure_policy: RtBoxHookFailurePolicy::Abort,
+            boxes: BTreeMap::new(),
+            host_commands: RtBoxHostCommandsConfig::default(),
+            preserve_paths: vec![],
+            volumes: vec![],
         }
     }
 }
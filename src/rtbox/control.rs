@@ -0,0 +1,272 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::rtbox::engine::Result;
+use crate::rtbox::error::RtBoxError;
+
+/// Where init listens inside the container, the directory is bind mounted from the host
+pub const CONTROL_DIR: &str = "/run/rtbox";
+pub const CONTROL_SOCKET: &str = "control.sock";
+
+const JSONRPC_VERSION: &str = "2.0";
+
+/// Error codes defined by JSON-RPC 2.0
+pub const PARSE_ERROR: i64 = -32700;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// Used for everything that goes wrong while handling a valid request
+pub const SERVER_ERROR: i64 = -32000;
+
+fn jsonrpc_version() -> String {
+    JSONRPC_VERSION.to_string()
+}
+
+/// JSON-RPC 2.0 requests and responses, one per line
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxControlRequest {
+    #[serde(default = "jsonrpc_version")]
+    pub jsonrpc: String,
+    pub id: u64,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxControlError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RtBoxControlError {
+    pub fn method_not_found(method: &str) -> Self {
+        Self { code: METHOD_NOT_FOUND, message: format!("unknown method {}", method) }
+    }
+
+    pub fn invalid_params(message: &str) -> Self {
+        Self { code: INVALID_PARAMS, message: message.to_string() }
+    }
+}
+
+impl From<String> for RtBoxControlError {
+    fn from(message: String) -> Self {
+        Self { code: SERVER_ERROR, message }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxControlResponse {
+    pub jsonrpc: String,
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RtBoxControlError>,
+}
+
+/// Implemented by init to answer requests coming from the host
+pub trait RtBoxControlHandler: Send + Sync {
+    fn handle(&self, method: &str, params: Value) -> std::result::Result<Value, RtBoxControlError>;
+}
+
+/// Per user runtime directory, a predictable one in the temp dir when the session has none
+fn runtime_dir() -> (PathBuf, bool) {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(runtime_dir) => (PathBuf::from(runtime_dir), false),
+        Err(_) => (std::env::temp_dir().join(format!("rtbox-{}", unsafe { libc::getuid() })), true),
+    }
+}
+
+/// The runtime directory, making sure nobody else got to the fallback one first since
+/// everything below it is trusted to be ours
+pub fn ensure_runtime_dir() -> io::Result<PathBuf> {
+    let (runtime_dir, fallback) = runtime_dir();
+    if !fallback {
+        return Ok(runtime_dir);
+    }
+
+    match fs::DirBuilder::new().mode(0o700).create(&runtime_dir) {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err),
+        _ => {},
+    }
+    let metadata = fs::symlink_metadata(&runtime_dir)?;
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory owned by us, set XDG_RUNTIME_DIR", runtime_dir.display()),
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(&runtime_dir, fs::Permissions::from_mode(0o700))?;
+    }
+
+    Ok(runtime_dir)
+}
+
+/// Host directory mounted at `CONTROL_DIR` inside `container`
+pub fn host_control_dir(container: &str) -> PathBuf {
+    runtime_dir().0.join("rtbox").join(container)
+}
+
+/// Create the control directory of a new `container`
+pub fn create_control_dir(container: &str) -> io::Result<PathBuf> {
+    let control_dir = ensure_runtime_dir()?.join("rtbox").join(container);
+    fs::create_dir_all(&control_dir)?;
+    Ok(control_dir)
+}
+
+/// Make the control directory `container` was created with reachable under its current name,
+/// podman keeps mounting `mount_source` after a rename so that one stays the real directory
+pub fn link_control_dir(container: &str, mount_source: &Path) -> io::Result<()> {
+    let control_dir = host_control_dir(container);
+    ensure_runtime_dir()?;
+    fs::create_dir_all(mount_source)?;
+    if control_dir == mount_source {
+        return Ok(());
+    }
+
+    match fs::symlink_metadata(&control_dir) {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs::remove_file(&control_dir)?,
+        Ok(_) => return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} is used by another container", control_dir.display()),
+        )),
+        Err(_) => {},
+    }
+    std::os::unix::fs::symlink(mount_source, &control_dir)
+}
+
+/// Renamed containers still using the control directory created for `container`
+pub fn renamed_from(container: &str) -> Vec<String> {
+    let control_dir = host_control_dir(container);
+    let Some(Ok(entries)) = control_dir.parent().map(fs::read_dir) else {
+        return vec![];
+    };
+
+    entries.flatten()
+        .filter(|entry| fs::read_link(entry.path()).map(|target| target == control_dir).unwrap_or(false))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect()
+}
+
+pub fn host_control_socket(container: &str) -> PathBuf {
+    host_control_dir(container).join(CONTROL_SOCKET)
+}
+
+fn handle_connection(stream: UnixStream, handler: &dyn RtBoxControlHandler) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<RtBoxControlRequest>(&line) {
+            Ok(request) => {
+                debug!("control request: {:?}", request);
+                let (result, error) = match handler.handle(&request.method, request.params) {
+                    Ok(result) => (Some(result), None),
+                    Err(error) => (None, Some(error)),
+                };
+                RtBoxControlResponse { jsonrpc: jsonrpc_version(), id: request.id, result, error }
+            },
+            Err(err) => RtBoxControlResponse {
+                jsonrpc: jsonrpc_version(),
+                id: 0,
+                result: None,
+                error: Some(RtBoxControlError { code: PARSE_ERROR, message: format!("invalid request: {}", err) }),
+            },
+        };
+
+        writeln!(writer, "{}", serde_json::to_string(&response).unwrap())?;
+    }
+
+    Ok(())
+}
+
+/// Listen on `socket_path` answering each connection on its own thread, only `owner` may connect
+pub fn serve(socket_path: &Path, owner: Option<(u32, u32)>, handler: Arc<dyn RtBoxControlHandler>) -> io::Result<()> {
+    if let Some(socket_dir) = socket_path.parent() {
+        fs::create_dir_all(socket_dir)?;
+    }
+    if fs::symlink_metadata(socket_path).is_ok() {
+        fs::remove_file(socket_path)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+    /* the host user is not root inside the container, it connects as the box user */
+    if let Some((uid, gid)) = owner {
+        std::os::unix::fs::chown(socket_path, Some(uid), Some(gid))?;
+    }
+    info!("listening for control requests on {}", socket_path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let handler = handler.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = handle_connection(stream, handler.as_ref()) {
+                            debug!("control connection closed: {}", err);
+                        }
+                    });
+                },
+                Err(err) => warn!("could not accept control connection: {}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn control_error<E: ToString>(socket_path: &Path) -> impl Fn(E) -> RtBoxError + '_ {
+    move |err| RtBoxError {
+        command: None,
+        message: Some(format!("could not talk to init over {}", socket_path.display())),
+        root_cause: Some(err.to_string()),
+    }
+}
+
+/// Send a single request to init and wait for its answer
+pub async fn call(socket_path: &Path, method: &str, params: Value) -> Result<Value> {
+    let request = RtBoxControlRequest {
+        jsonrpc: jsonrpc_version(),
+        id: 1,
+        method: method.to_string(),
+        params,
+    };
+
+    let mut stream = tokio::net::UnixStream::connect(socket_path)
+        .await
+        .map_err(control_error(socket_path))?;
+    stream.write_all(format!("{}\n", serde_json::to_string(&request).unwrap()).as_bytes())
+        .await
+        .map_err(control_error(socket_path))?;
+
+    let mut line = String::new();
+    tokio::io::BufReader::new(stream)
+        .read_line(&mut line)
+        .await
+        .map_err(control_error(socket_path))?;
+    let response: RtBoxControlResponse = serde_json::from_str(&line).map_err(control_error(socket_path))?;
+
+    match response {
+        RtBoxControlResponse { error: Some(error), .. } => Err(RtBoxError {
+            command: None,
+            message: Some(error.message),
+            root_cause: Some(format!("{} failed inside the container", method)),
+        }),
+        RtBoxControlResponse { result, .. } => Ok(result.unwrap_or(Value::Null)),
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::rtbox::config::RtBoxConfig;
+use crate::rtbox::engine::Result;
+use crate::rtbox::error::RtBoxError;
+
+/// Used when neither the flags nor the host tell us which distro to use
+const FALLBACK_DISTRO: &str = "fedora";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RtBoxReleaseFormat {
+    /// A plain version number e.g. `38`
+    Number,
+    /// A dotted version e.g. `9.1` or `22.04`
+    MajorMinor,
+    /// Rolling distros that only publish `latest`
+    Latest,
+    /// Accept any release string as is
+    Any,
+}
+
+impl RtBoxReleaseFormat {
+    /// Normalize `release` according to this format, toolbx accepts an `f` prefix for Fedora
+    fn normalize(&self, release: &str) -> Option<String> {
+        let is_number = |it: &str| !it.is_empty() && it.chars().all(|c| c.is_ascii_digit());
+
+        match self {
+            RtBoxReleaseFormat::Number => {
+                let release = release.strip_prefix('f').unwrap_or(release);
+                is_number(release).then(|| release.to_string())
+            },
+            RtBoxReleaseFormat::MajorMinor => {
+                let (major, minor) = release.split_once('.')?;
+                (is_number(major) && is_number(minor)).then(|| release.to_string())
+            },
+            RtBoxReleaseFormat::Latest => (release == "latest").then(|| release.to_string()),
+            RtBoxReleaseFormat::Any => (!release.is_empty()).then(|| release.to_string()),
+        }
+    }
+
+    fn example(&self) -> &str {
+        match self {
+            RtBoxReleaseFormat::Number => "a number like 38",
+            RtBoxReleaseFormat::MajorMinor => "a version like 9.1",
+            RtBoxReleaseFormat::Latest => "latest",
+            RtBoxReleaseFormat::Any => "any release",
+        }
+    }
+}
+
+/// How to build an image reference for a distro, `{release}` and `{major}`
+/// in the image are replaced with the requested release
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxDistro {
+    pub image: String,
+    pub release_format: RtBoxReleaseFormat,
+    pub default_release: String,
+}
+
+impl RtBoxDistro {
+    fn new(image: &str, release_format: RtBoxReleaseFormat, default_release: &str) -> Self {
+        Self {
+            image: image.to_string(),
+            release_format,
+            default_release: default_release.to_string(),
+        }
+    }
+}
+
+/// Distros known out of the box, mirrors what toolbx supports
+pub fn builtin_catalog() -> BTreeMap<String, RtBoxDistro> {
+    BTreeMap::from([
+        ("arch".to_string(), RtBoxDistro::new(
+            "quay.io/toolbx/arch-toolbox:{release}",
+            RtBoxReleaseFormat::Latest,
+            "latest",
+        )),
+        ("fedora".to_string(), RtBoxDistro::new(
+            "registry.fedoraproject.org/fedora-toolbox:{release}",
+            RtBoxReleaseFormat::Number,
+            "38",
+        )),
+        ("rhel".to_string(), RtBoxDistro::new(
+            "registry.access.redhat.com/ubi{major}/toolbox:{release}",
+            RtBoxReleaseFormat::MajorMinor,
+            "9.2",
+        )),
+        ("ubuntu".to_string(), RtBoxDistro::new(
+            "quay.io/toolbx/ubuntu-toolbox:{release}",
+            RtBoxReleaseFormat::MajorMinor,
+            "22.04",
+        )),
+    ])
+}
+
+/// The built-in catalog with the distros from the configuration file on top
+pub fn catalog(config: &RtBoxConfig) -> BTreeMap<String, RtBoxDistro> {
+    let mut catalog = builtin_catalog();
+    catalog.extend(config.distros.clone());
+    catalog
+}
+
+/// Resolve `--distro` and `--release` into an image reference, missing values
+/// default to the host distro described by `host_os_release` when we support it
+pub fn resolve_image(
+    config: &RtBoxConfig,
+    distro: Option<&str>,
+    release: Option<&str>,
+    host_os_release: Option<&BTreeMap<String, String>>,
+) -> Result<String> {
+    let catalog = catalog(config);
+    let host_distro = host_os_release.and_then(|it| it.get("ID")).map(|it| it.as_str());
+    let host_release = host_os_release.and_then(|it| it.get("VERSION_ID")).map(|it| it.as_str());
+
+    if distro.is_none() && release.is_none() {
+        if let Some(default_image) = &config.default_image {
+            return Ok(default_image.clone());
+        }
+    }
+
+    let distro_name = distro
+        .or(host_distro.filter(|it| catalog.contains_key(*it)))
+        .unwrap_or(FALLBACK_DISTRO);
+
+    let catalog_distro = catalog.get(distro_name).ok_or_else(|| RtBoxError {
+        command: None,
+        message: Some(format!("distro '{}' is not supported", distro_name)),
+        root_cause: Some(format!(
+            "supported distros are: {}",
+            catalog.keys().cloned().collect::<Vec<String>>().join(", ")
+        )),
+    })?;
+
+    /* only use the host release if we are also using the host distro */
+    let host_release = host_release.filter(|_| release.is_none() && host_distro == Some(distro_name));
+    let release = release
+        .or(host_release)
+        .unwrap_or(&catalog_distro.default_release);
+
+    let normalized_release = match catalog_distro.release_format.normalize(release) {
+        Some(normalized_release) => normalized_release,
+        /* a host release the catalog does not know about is not the user's fault */
+        None if host_release.is_some() => catalog_distro.default_release.clone(),
+        None => return Err(RtBoxError {
+            command: None,
+            message: Some(format!("release '{}' is not supported for distro '{}'", release, distro_name)),
+            root_cause: Some(format!("expected {}", catalog_distro.release_format.example())),
+        }),
+    };
+
+    let major = normalized_release
+        .split('.')
+        .next()
+        .unwrap_or(&normalized_release)
+        .to_string();
+
+    Ok(catalog_distro.image
+        .replace("{release}", &normalized_release)
+        .replace("{major}", &major))
+}
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+use crate::rtbox::engine::{RtBoxCreateOpts, RtBoxMount, Result};
+use crate::rtbox::error::RtBoxError;
+
+/// Keys that can appear multiple times in a section, their values accumulate
+const LIST_KEYS: [&str; 7] = [
+    "additional_packages",
+    "exported_apps",
+    "exported_bins",
+    "include",
+    "init_hooks",
+    "pre_init_hooks",
+    "volume",
+];
+
+/// Keys we understand but have no rtbox equivalent for
+const IGNORED_KEYS: [&str; 4] = [
+    "nvidia",
+    "pull",
+    "replace",
+    "start_now",
+];
+
+/// A single box definition from a `distrobox-assemble` ini file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DistroboxDefinition {
+    pub name: String,
+    pub image: Option<String>,
+    pub home: Option<String>,
+    pub additional_packages: Vec<String>,
+    pub init_hooks: Vec<String>,
+    pub pre_init_hooks: Vec<String>,
+    pub volumes: Vec<String>,
+    pub exported_apps: Vec<String>,
+    pub exported_bins: Vec<String>,
+}
+
+type IniSection = BTreeMap<String, Vec<String>>;
+
+fn parse_error(line_number: usize, message: &str) -> RtBoxError {
+    RtBoxError {
+        command: Some("assemble".to_string()),
+        message: Some(format!("line {}: {}", line_number, message)),
+        root_cause: Some("invalid distrobox-assemble file".to_string()),
+    }
+}
+
+/// Strip one level of matching quotes, distrobox accepts both quoted and bare values
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+fn parse_sections(contents: &str) -> Result<Vec<(String, IniSection)>> {
+    let mut sections: Vec<(String, IniSection)> = vec![];
+
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let name = line.strip_prefix('[')
+                .and_then(|it| it.strip_suffix(']'))
+                .map(|it| it.trim())
+                .filter(|it| !it.is_empty())
+                .ok_or_else(|| parse_error(line_number, "malformed section header"))?;
+            sections.push((name.to_string(), IniSection::new()));
+            continue;
+        }
+
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| parse_error(line_number, "expected key=value"))?;
+        let (_, section) = sections.last_mut()
+            .ok_or_else(|| parse_error(line_number, "key outside of a section"))?;
+
+        let key = key.trim().to_string();
+        let value = unquote(value).to_string();
+        let values = section.entry(key.clone()).or_default();
+        if LIST_KEYS.contains(&key.as_str()) {
+            values.push(value);
+        } else {
+            *values = vec![value];
+        }
+    }
+
+    Ok(sections)
+}
+
+/// Merge the keys of every section referenced through `include=` into `section`,
+/// values set in the section itself take precedence over included ones
+fn resolve_includes(
+    name: &str,
+    sections: &[(String, IniSection)],
+    visited: &mut Vec<String>,
+) -> Result<IniSection> {
+    if visited.iter().any(|it| it == name) {
+        return Err(RtBoxError {
+            command: Some("assemble".to_string()),
+            message: Some(format!("include loop detected at section {}", name)),
+            root_cause: Some("invalid distrobox-assemble file".to_string()),
+        });
+    }
+    visited.push(name.to_string());
+
+    let section = sections.iter()
+        .find(|(section_name, _)| section_name == name)
+        .map(|(_, section)| section)
+        .ok_or_else(|| RtBoxError {
+            command: Some("assemble".to_string()),
+            message: Some(format!("included section {} does not exist", name)),
+            root_cause: Some("invalid distrobox-assemble file".to_string()),
+        })?;
+
+    let mut resolved = IniSection::new();
+    for include in section.get("include").cloned().unwrap_or_default() {
+        for (key, values) in resolve_includes(&include, sections, visited)? {
+            let resolved_values = resolved.entry(key.clone()).or_default();
+            if LIST_KEYS.contains(&key.as_str()) {
+                resolved_values.extend(values);
+            } else {
+                *resolved_values = values;
+            }
+        }
+    }
+
+    for (key, values) in section {
+        if key == "include" {
+            continue;
+        }
+
+        let resolved_values = resolved.entry(key.clone()).or_default();
+        if LIST_KEYS.contains(&key.as_str()) {
+            resolved_values.extend(values.iter().cloned());
+        } else {
+            *resolved_values = values.clone();
+        }
+    }
+
+    visited.pop();
+    Ok(resolved)
+}
+
+fn split_words(values: Option<&Vec<String>>) -> Vec<String> {
+    values
+        .map(|values| values.iter()
+            .flat_map(|value| value.split_whitespace())
+            .map(|word| word.to_string())
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Parse the contents of a `distrobox-assemble` ini file into box definitions
+pub fn parse_assemble(contents: &str) -> Result<Vec<DistroboxDefinition>> {
+    let sections = parse_sections(contents)?;
+
+    sections.iter()
+        .map(|(name, _)| {
+            let section = resolve_includes(name, &sections, &mut vec![])?;
+
+            for key in section.keys() {
+                if IGNORED_KEYS.contains(&key.as_str()) {
+                    debug!("[{}] ignoring distrobox option {}", name, key);
+                } else if !matches!(key.as_str(),
+                    "image" | "home" | "additional_packages" | "init_hooks" | "pre_init_hooks"
+                    | "volume" | "exported_apps" | "exported_bins"
+                ) {
+                    warn!("[{}] unsupported distrobox option {} will be ignored", name, key);
+                }
+            }
+
+            let single_value = |key: &str| section.get(key)
+                .and_then(|values| values.last())
+                .filter(|value| !value.is_empty())
+                .cloned();
+
+            Ok(DistroboxDefinition {
+                name: name.clone(),
+                image: single_value("image"),
+                home: single_value("home"),
+                additional_packages: split_words(section.get("additional_packages")),
+                init_hooks: section.get("init_hooks").cloned().unwrap_or_default(),
+                pre_init_hooks: section.get("pre_init_hooks").cloned().unwrap_or_default(),
+                volumes: split_words(section.get("volume")),
+                exported_apps: split_words(section.get("exported_apps")),
+                exported_bins: split_words(section.get("exported_bins")),
+            })
+        })
+        .collect()
+}
+
+impl DistroboxDefinition {
+    pub fn create_opts(&self) -> RtBoxCreateOpts {
+        let mounts = self.volumes
+            .iter()
+            .filter_map(|volume| {
+                let mount = RtBoxMount::parse(volume);
+                if mount.is_none() {
+                    warn!("[{}] only host path volumes are supported, ignoring {}", self.name, volume);
+                }
+                mount
+            })
+            .collect();
+
+        RtBoxCreateOpts {
+            mounts,
+            home: self.home.clone(),
+            additional_packages: self.additional_packages.clone(),
+            init_hooks: self.init_hooks.clone(),
+            pre_init_hooks: self.pre_init_hooks.clone(),
+            ..RtBoxCreateOpts::default()
+        }
+    }
+}
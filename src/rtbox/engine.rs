@@ -1,6 +1,9 @@
 use async_trait::async_trait;
-use std::char;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::time;
 
 #[cfg(test)]
 use mockall::automock;
@@ -8,10 +11,38 @@ use mockall::automock;
 use podman_api::api::{Container};
 use podman_api::models::ListContainer;
 use serde::{Serialize, Deserialize};
+use tokio::signal::unix::{signal, SignalKind};
 
-use crate::rtbox::init::{RtBoxInit, RtBoxInitState, RtBoxInitSystem};
+use crate::rtbox::archive::{self, RtBoxArchiveManifest, ARCHIVE_VERSION};
+use crate::rtbox::distro;
+use crate::rtbox::distrobox;
+use crate::rtbox::control;
+use crate::rtbox::export::{self, RtBoxExport, RtBoxExportKind};
+use crate::rtbox::hooks::RtBoxHookFailurePolicy;
+use crate::rtbox::host_exec;
+use crate::rtbox::host_sync;
+use crate::rtbox::labels::{self, RtBoxLabels, LABELS_VERSION};
+use crate::rtbox::init::{
+    self,
+    RtBoxInit,
+    RtBoxInitReport,
+    RtBoxInitMode,
+    RtBoxInitState,
+    RtBoxInitStatus,
+    RtBoxInitSystem,
+    RtBoxReadiness,
+    RtBoxSession,
+    LAST_SESSION_PATH,
+    RTBOX_READINESS_PATH,
+};
 use crate::rtbox::error::RtBoxError;
-use crate::rtbox::config::RtBoxConfig;
+use crate::rtbox::config::{RtBoxBoxConfig, RtBoxConfig};
+use crate::rtbox::os_release;
+use crate::rtbox::profile::RtBoxProfile;
+use crate::rtbox::services::{self, RtBoxServiceStatus};
+use crate::rtbox::toolbx;
+use crate::rtbox::tty;
+use crate::rtbox::volume::{self, RtBoxNamedVolume, RtBoxVolumeConfig, RtBoxVolumeInfo};
 
 extern "C" {
     fn geteuid() -> u32;
@@ -20,6 +51,14 @@ extern "C" {
 
 pub type Result<T> = std::result::Result<T, RtBoxError>;
 
+/// How long we wait for `rtbox init` to finish bootstrapping a container,
+/// installing packages on first boot can take a while
+const INIT_TIMEOUT: time::Duration = time::Duration::from_secs(600);
+/// How often the host helper checks whether its box is still running
+const HOST_HELPER_POLL_INTERVAL: time::Duration = time::Duration::from_secs(30);
+/// How long `stop` waits for init to shut the box down on its own
+const SHUTDOWN_TIMEOUT: time::Duration = time::Duration::from_secs(15);
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct RtBox {
     pub id: String,
@@ -28,29 +67,457 @@ pub struct RtBox {
     pub status: String,
 }
 
+/// Outcome of `rtbox upgrade`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxUpgrade {
+    pub name: String,
+    pub image: String,
+    pub old_digest: Option<String>,
+    pub new_digest: Option<String>,
+    /// False when the box already used the latest image
+    pub upgraded: bool,
+    pub preserved_paths: Vec<String>,
+}
+
+/// A container prune removed or would remove
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxPrunedBox {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Outcome of `rtbox prune`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RtBoxPruneReport {
+    /// Nothing was removed, the report lists what would be
+    pub dry_run: bool,
+    pub boxes: Vec<RtBoxPrunedBox>,
+    pub images: Vec<RtBoxImageInfo>,
+    pub exports: Vec<RtBoxExport>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RtBoxExecOutput {
-    stdout: Vec<char>,
-    stderr: Vec<char>,
-    return_code: u64,
+    pub return_code: i64,
+    /// Set when the command was started in the background
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exec_id: Option<String>,
+}
+
+/// What a command run through `ContainerEngine::exec_output` printed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RtBoxCapturedOutput {
+    pub return_code: i64,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Host environment variables forwarded into rtbox containers
+const PRESERVED_ENV: [&str; 27] = [
+    "DBUS_SESSION_BUS_ADDRESS",
+    "DESKTOP_SESSION",
+    "DISPLAY",
+    "GDMSESSION",
+    "GDM_LANG",
+    "HOME",
+    "HOSTNAME",
+    "LANG",
+    "LOGNAME",
+    "LSCOLORS",
+    "LS_COLORS",
+    "QT_IM_MODULE",
+    "QT_WAYLAND_DECORATION",
+    "SESSION_MANAGER",
+    "TERM",
+    "USER",
+    "USERNAME",
+    "WAYLAND_DISPLAY",
+    "XCURSOR_SIZE",
+    "XCURSOR_THEME",
+    "XDG_CURRENT_DESKTOP",
+    "XDG_DATA_DIRS",
+    "XDG_MENU_PREFIX",
+    "XDG_RUNTIME_DIR",
+    "XDG_SESSION_CLASS",
+    "XDG_SESSION_DESKTOP",
+    "XDG_SESSION_TYPE",
+];
+
+/// Name of the host user `uid`, the box user is created with the same name
+fn host_username(uid: u32) -> Option<String> {
+    if let Ok(username) = std::env::var("USER") {
+        return Some(username);
+    }
+
+    unsafe {
+        let passwd = libc::getpwuid(uid);
+        if passwd.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr((*passwd).pw_name).to_string_lossy().to_string())
+    }
+}
+
+fn host_env() -> Vec<(String, String)> {
+    let container_env: Vec<_> = PRESERVED_ENV
+        .iter()
+        .filter_map(|env_var| std::env::var(env_var).ok().map(|value| (env_var.to_string(), value)))
+        .collect();
+    info!("detected {}/{} environment variables to preserve", container_env.len(), PRESERVED_ENV.len());
+
+    container_env
+}
+
+/// A host path bind mounted into a container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxMount {
+    pub source: String,
+    pub destination: String,
+    pub options: Vec<String>,
+}
+
+impl RtBoxMount {
+    /// Create a mount from colon separated options e.g. `rbind:ro`
+    pub fn new(source: &str, destination: &str, options: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            options: options.split(':').map(|it| it.to_string()).collect(),
+        }
+    }
+
+    /// Parse a podman style `SOURCE[:DESTINATION[:OPTIONS]]` volume specification,
+    /// options are comma separated and mounts are always recursive binds
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut parts = spec.splitn(3, ':');
+        let source = parts.next().filter(|it| it.starts_with('/'))?;
+        let destination = parts.next().filter(|it| !it.is_empty()).unwrap_or(source);
+        if !destination.starts_with('/') {
+            return None;
+        }
+
+        let mut options = vec!["rbind".to_string()];
+        options.extend(
+            parts.next()
+                .unwrap_or("")
+                .split(',')
+                .filter(|it| !it.is_empty() && *it != "rbind")
+                .map(|it| it.to_string())
+        );
+
+        Some(Self {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            options,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RtBoxSecurityOpts {
+    pub selinux_disabled: bool,
+    pub drop_capabilities: Vec<String>,
+    pub add_capabilities: Vec<String>,
+    pub seccomp_profile: Option<String>,
+}
+
+/// Everything a container engine needs to create a rtbox container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxContainerSpec {
+    pub name: String,
+    pub image: String,
+    pub entrypoint: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub mounts: Vec<RtBoxMount>,
+    /// Engine volumes, created beforehand through `create_volume`
+    pub volumes: Vec<RtBoxNamedVolume>,
+    pub security: RtBoxSecurityOpts,
+    /// Let the engine prepare the container for running systemd as PID 1
+    pub systemd: bool,
+    /// Where commands start when no working directory is given
+    pub workdir: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Engine independent view of a local image
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RtBoxImageInfo {
+    pub id: String,
+    pub digest: String,
+    pub names: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// User facing options for `rtbox create`
+#[derive(Debug, Clone, Default)]
+pub struct RtBoxCreateOpts {
+    pub profile: RtBoxProfile,
+    /// Extra mounts on top of the ones defined by the profile
+    pub mounts: Vec<RtBoxMount>,
+    /// Custom HOME for the container user instead of the host one
+    pub home: Option<String>,
+    pub additional_packages: Vec<String>,
+    pub pre_init_hooks: Vec<String>,
+    pub init_hooks: Vec<String>,
+    /// Seconds each hook may run, the configured default is used when unset
+    pub hook_timeout: Option<u64>,
+    pub hook_failure_policy: Option<RtBoxHookFailurePolicy>,
+    pub init_mode: RtBoxInitMode,
+    /// Seconds the box is kept before prune removes it
+    pub ttl: Option<u64>,
+    /// Volumes on top of the configured ones, a configured volume at the same path is replaced
+    pub volumes: Vec<RtBoxVolumeConfig>,
+}
+
+/// User facing options for `rtbox run` and `rtbox enter`
+#[derive(Debug, Clone, Default)]
+pub struct RtBoxRunOpts {
+    /// Runs as the current user when unset
+    pub user: Option<String>,
+    /// Starts in the current directory when unset and the container can see it
+    pub workdir: Option<String>,
+    /// `KEY=VALUE` pairs, a bare `KEY` takes its value from our environment
+    pub env: Vec<String>,
+    /// Allocate a terminal, decided by whether stdin is one when unset
+    pub tty: Option<bool>,
+    pub detach: bool,
+    /// Kill the command and everything it started after this long
+    pub timeout: Option<time::Duration>,
+}
+
+/// Everything `rtbox inspect` reports about a box
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxInspect {
+    pub id: String,
+    pub name: String,
+    pub kind: RtBoxKind,
+    pub status: String,
+    pub created: String,
+    pub image: String,
+    pub image_digest: String,
+    pub entrypoint: Vec<String>,
+    /// Options init was created with, see `RtBoxContainerInfo::init_args`
+    pub creation: BTreeMap<String, Vec<String>>,
+    pub mounts: Vec<RtBoxMount>,
+    pub env: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+    pub security: RtBoxSecurityOpts,
+    /// Creation parameters read back from the rtbox labels
+    pub recorded: Option<RtBoxLabels>,
+    pub exports: Vec<RtBoxExport>,
+    pub readiness: Option<RtBoxReadiness>,
+}
+
+impl RtBoxInspect {
+    pub const FIELDS: [&'static str; 16] = [
+        "id",
+        "name",
+        "kind",
+        "status",
+        "created",
+        "image",
+        "image_digest",
+        "entrypoint",
+        "creation",
+        "mounts",
+        "env",
+        "labels",
+        "security",
+        "recorded",
+        "exports",
+        "readiness",
+    ];
+
+    /// A single top level field by its serialized name
+    pub fn field(&self, name: &str) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()?.get(name).cloned()
+    }
+}
+
+/// Which runtime set up a container and therefore how we need to talk to it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtBoxKind {
+    Rtbox,
+    Toolbx,
+    Unknown,
+}
+
+/// Engine independent view of an existing container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RtBoxContainerInfo {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub image_digest: String,
+    pub entrypoint: Vec<String>,
+    pub labels: BTreeMap<String, String>,
+    pub mounts: Vec<RtBoxMount>,
+    #[serde(default)]
+    pub volumes: Vec<RtBoxNamedVolume>,
+    /// As `KEY=VALUE`
+    pub env: Vec<String>,
+    pub security: RtBoxSecurityOpts,
+    /// RFC 3339 timestamp
+    pub created: String,
+    pub status: String,
+    pub running: bool,
+    pub pid: i64,
+}
+
+impl RtBoxContainerInfo {
+    pub fn kind(&self) -> RtBoxKind {
+        if toolbx::is_toolbx_entrypoint(&self.entrypoint) {
+            RtBoxKind::Toolbx
+        } else if self.entrypoint.get(1).map(|arg| arg == "init").unwrap_or(false) {
+            RtBoxKind::Rtbox
+        } else {
+            RtBoxKind::Unknown
+        }
+    }
+
+    /// Host directory mounted at the control directory, named after the container when it was created
+    pub fn control_dir(&self) -> Option<PathBuf> {
+        self.mounts
+            .iter()
+            .find(|mount| mount.destination == control::CONTROL_DIR)
+            .map(|mount| PathBuf::from(&mount.source))
+    }
+
+    /// The profile the box was created with, boxes from before the labels are restricted unless the host root is mounted
+    pub fn profile(&self) -> RtBoxProfile {
+        match RtBoxLabels::from_labels(&self.labels) {
+            Some(recorded) => recorded.profile,
+            None if self.mounts.iter().any(|mount| mount.destination == host_sync::HOST_ROOT) => RtBoxProfile::Default,
+            None => RtBoxProfile::Restricted,
+        }
+    }
+
+    /// Volumes holding data of this box alone, committing it leaves what they hold out of the image
+    pub fn box_volumes(&self) -> Vec<&RtBoxNamedVolume> {
+        self.volumes
+            .iter()
+            .filter(|named_volume| {
+                RtBoxVolumeConfig::from_named_volume(named_volume, &self.name)
+                    .map(|volume| !volume.shared)
+                    .unwrap_or(false)
+            })
+            .collect()
+    }
+
+    /// The user init or toolbx set up inside the box
+    pub fn username(&self) -> Option<String> {
+        match self.kind() {
+            RtBoxKind::Rtbox => self.init_args().get("username").and_then(|username| username.first()).cloned(),
+            RtBoxKind::Toolbx => self.entrypoint
+                .windows(2)
+                .find(|args| args[0] == "--user")
+                .map(|args| args[1].clone()),
+            RtBoxKind::Unknown => None,
+        }
+    }
+
+    /// What runs as PID 1 in a rtbox container, read back from the init arguments
+    pub fn init_mode(&self) -> RtBoxInitMode {
+        let is_systemd = self.entrypoint
+            .windows(2)
+            .any(|args| args[0] == "--init-mode" && args[1] == RtBoxInitMode::Systemd.name());
+
+        if is_systemd {
+            RtBoxInitMode::Systemd
+        } else {
+            RtBoxInitMode::Rtbox
+        }
+    }
+
+    /// The options rtbox init was started with, flags without a value map to an empty list
+    pub fn init_args(&self) -> BTreeMap<String, Vec<String>> {
+        let mut init_args: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        if self.kind() != RtBoxKind::Rtbox {
+            return init_args;
+        }
+
+        let mut args = self.entrypoint.iter().skip(2).peekable();
+        while let Some(arg) = args.next() {
+            let Some(option) = arg.strip_prefix("--") else {
+                continue;
+            };
+            let values = init_args.entry(option.to_string()).or_default();
+            if let Some(value) = args.next_if(|value| !value.starts_with("--")) {
+                values.push(value.clone());
+            }
+        }
+
+        init_args
+    }
+
+    /// Where the host path `cwd` shows up inside the container, the most specific mount wins
+    pub fn container_path(&self, cwd: &Path) -> Option<PathBuf> {
+        self.mounts
+            .iter()
+            .filter_map(|mount| {
+                cwd.strip_prefix(&mount.source)
+                    .ok()
+                    .map(|relative| (mount.source.len(), Path::new(&mount.destination).join(relative)))
+            })
+            .max_by_key(|(source_length, _)| *source_length)
+            .map(|(_, path)| path)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RtBoxExecOpts {
+    pub user: Option<String>,
+    pub workdir: Option<String>,
+    pub env: Vec<(String, String)>,
+    pub tty: bool,
+    /// Forward our stdin to the command, with or without a terminal
+    pub interactive: bool,
+    /// Kill the process tree of the command after this long
+    pub timeout: Option<time::Duration>,
 }
 
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait ContainerEngine {
-    async fn create(
-        &self,
-        name: &str,
-        image: &str,
-        entrypoint: Vec<String>,
-        env: Vec<(&str, String)>,
-        mounts: Vec<&(&str, &str, &str)>,
-    ) -> Result<Container>;
+    async fn create(&self, spec: &RtBoxContainerSpec) -> Result<Container>;
     async fn list(&self, all: bool) -> Result<Vec<ListContainer>>;
     async fn rm(&self, name: String, force: bool) -> Result<()>;
-    async fn exec(&self, name: String, command: Vec<String>, tty: bool, interactive: bool);
-    async fn start(&self, name: String) -> Result<Container>;
-    async fn inspect(&self, name: String) -> Result<Container>;
+    /// Run a command inside a container attached to our stdio, returns its exit code
+    async fn exec(&self, name: String, command: Vec<String>, opts: RtBoxExecOpts) -> Result<i64>;
+    /// Start a command inside a container without waiting for it, returns the exec ID
+    async fn exec_detached(&self, name: String, command: Vec<String>, opts: RtBoxExecOpts) -> Result<String>;
+    /// Run a command inside a container and collect what it prints instead of passing it through
+    async fn exec_output(&self, name: String, command: Vec<String>, opts: RtBoxExecOpts) -> Result<RtBoxCapturedOutput>;
+    async fn start(&self, name: String) -> Result<()>;
+    async fn stop(&self, name: String) -> Result<()>;
+    async fn rename(&self, name: String, new_name: String) -> Result<()>;
+    async fn inspect(&self, name: String) -> Result<RtBoxContainerInfo>;
+    /// Read a single file from the container filesystem
+    async fn read_file(&self, name: String, path: String) -> Result<Vec<u8>>;
+    async fn inspect_image(&self, image: String) -> Result<RtBoxImageInfo>;
+    /// Pull the latest version of `image` from its registry
+    async fn pull_image(&self, image: String) -> Result<()>;
+    /// Extract a tar archive into the directory `path` inside the container
+    async fn import_path(&self, name: String, path: String, archive: Vec<u8>) -> Result<()>;
+    /// Copy `path` of the container `source` to the same place in `target`, volumes mounted there included
+    async fn copy_path(&self, source: String, target: String, path: String) -> Result<()>;
+    /// Snapshot the container into `image`, a non empty `cleanup` runs as root on a scratch copy of
+    /// the snapshot first so the container itself is left untouched, `changes` are Dockerfile instructions
+    async fn commit(&self, name: String, image: String, cleanup: Vec<String>, changes: Vec<String>) -> Result<()>;
+    /// Drop the `image` name, the image itself stays around while containers use it
+    async fn untag_image(&self, image: String) -> Result<()>;
+    /// Write the image archive `load_image` accepts to `output`, streamed so large images are never held in memory
+    async fn save_image(&self, image: String, output: PathBuf) -> Result<()>;
+    /// Load the image archive stored at `range` of `input`, returns the names of the loaded images
+    async fn load_image(&self, input: PathBuf, range: Range<u64>) -> Result<Vec<String>>;
+    /// Untagged toolbox images no container uses
+    async fn dangling_images(&self) -> Result<Vec<RtBoxImageInfo>>;
+    async fn remove_image(&self, image: String) -> Result<()>;
+    /// Create a volume unless one with the same name exists
+    async fn create_volume(&self, name: String, labels: BTreeMap<String, String>) -> Result<()>;
+    /// Volumes created by rtbox
+    async fn list_volumes(&self) -> Result<Vec<RtBoxVolumeInfo>>;
+    async fn remove_volume(&self, name: String, force: bool) -> Result<()>;
 }
 
 pub struct RtBoxEngine<'a, T: ContainerEngine> {
@@ -58,129 +525,189 @@ pub struct RtBoxEngine<'a, T: ContainerEngine> {
     pub container_engine: &'a T,
 }
 
-#[async_trait]
-pub trait ToolbxEngine {
-    async fn create(&self, name: &str, image: &str) -> Result<RtBox>;
-    async fn rm(
-        self,
-        name: String,
-        force: Option<bool>,
-        all: Option<bool>
-    ) -> Result<()>;
-    async fn list(&self, all: Option<bool>) -> Result<Vec<RtBox>>;
-    async fn exec(
-        self,
-        container: String,
-        command: Vec<String>
-    ) -> Result<RtBoxExecOutput>;
-    async fn init(gid: i32, home: String, shell: String);
-}
-
 impl<'a, T: ContainerEngine> RtBoxEngine<'a, T> {
     pub fn new(rtbox_config: &'a RtBoxConfig, container_engine: &'a T) -> Self {
         Self {
-            container_engine:  container_engine,
+            container_engine,
             config: rtbox_config,
         }
     }
-    pub async fn create(&self, name: &str, image: &str) -> Result<RtBox> {
-        info!("creating toolbox {} based on {}", name, image);
+    /// Pick the image for a new box, an explicit image wins over `--distro` and `--release`
+    pub fn resolve_image(
+        &self,
+        image: Option<String>,
+        distro: Option<String>,
+        release: Option<String>,
+    ) -> Result<String> {
+        match image {
+            Some(image) => Ok(image),
+            None => distro::resolve_image(
+                self.config,
+                distro.as_deref(),
+                release.as_deref(),
+                os_release::read("/").as_ref(),
+            ),
+        }
+    }
+    pub async fn create(&self, name: &str, image: &str, opts: &RtBoxCreateOpts) -> Result<RtBox> {
+        self.create_copy(name, image, None, opts).await
+    }
+    /// Create a box from `image`, recording the image of `origin` instead when the box is a copy of another one
+    /// so upgrades keep following the image it was originally created from
+    async fn create_copy(&self, name: &str, image: &str, origin: Option<&RtBoxLabels>, opts: &RtBoxCreateOpts) -> Result<RtBox> {
+        info!("creating toolbox {} based on {} with {:?} profile", name, image, opts.profile);
 
-        let home_dir = std::env::var("HOME").unwrap();
-        let host_mounts = [
-            /* (source, destination, options) */
-            ("/", "/run/host", "rbind"),
-            ("/etc/machine-id", "/etc/machine-id", "rbind:ro"),
-            ("/run/libvirt", "/run/libvirt", "rbind"),
-            ("/run/systemd/journal", "/run/systemd/journal", "rbind"),
-            ("/run/systemd/resolve", "/run/systemd/resolve", "rbind"),
-            ("/run/systemd/sessions", "/run/systemd/sessions", "rbind"),
-            ("/run/systemd/system", "/run/systemd/system", "rbind"),
-            ("/run/systemd/users", "/run/systemd/users", "rbind"),
-            ("/run/udev/data", "/run/udev/data", "rbind"),
-            ("/run/udev/tags", "/run/udev/tags", "rbind"),
-            ("/tmp", "/tmp", "rbind"),
-            ("/var/lib/flatpak", "/var/lib/flatpak", "rbind:ro"),
-            ("/var/lib/libvirt", "/var/lib/libvirt", "rbind"),
-            ("/var/lib/systemd/coredump", "/var/lib/systemd/coredump", "rbind:ro"),
-            ("/var/log/journal", "/var/log/journal", "rbind:ro"),
-            ("/var/mnt", "/var/mnt", "rbind:rslave"),
-            (&home_dir, &home_dir, "rbind")
-        ];
-        let available_host_mounts = host_mounts.len();
-        debug!("host_mounts: {:?}", host_mounts);
+        let expires_at = match opts.ttl {
+            Some(ttl) => Some(init::unix_time().checked_add(ttl).ok_or(RtBoxError {
+                command: None,
+                message: Some(format!("cannot expire {} in {} seconds", name, ttl)),
+                root_cause: Some("the ttl is too long".to_string()),
+            })?),
+            None => None,
+        };
+        let home_dir = match &opts.home {
+            Some(home_dir) => {
+                std::fs::create_dir_all(home_dir).map_err(|err| RtBoxError {
+                    command: None,
+                    message: Some(format!("could not create home directory {}", home_dir)),
+                    root_cause: Some(err.to_string()),
+                })?;
+                home_dir.clone()
+            },
+            None => std::env::var("HOME").unwrap(),
+        };
+        let services = self.config.boxes
+            .get(name)
+            .map(|box_config| box_config.services.clone())
+            .unwrap_or_default();
+        services::start_order(&services).map_err(|message| RtBoxError {
+            command: None,
+            message: Some(format!("invalid services for {}", name)),
+            root_cause: Some(message),
+        })?;
 
-        let host_mounts: Vec<_> = host_mounts.iter()
-            .filter(|mount| Path::new(mount.0).exists())
-            .collect();
-        debug!("active mounts: {:?}", host_mounts);
-        info!("detected {}/{} active host mounts", host_mounts.len(), available_host_mounts);
+        let control_dir = control::host_control_dir(name);
+        if let Some(renamed) = control::renamed_from(name).first() {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("{} was renamed to {} which still uses its control directory", name, renamed)),
+                root_cause: Some("pick another name".to_string()),
+            });
+        }
+        control::create_control_dir(name).map_err(|err| RtBoxError {
+            command: None,
+            message: Some(format!("could not create control directory {}", control_dir.display())),
+            root_cause: Some(err.to_string()),
+        })?;
+
+        let rtbox_exe = std::env::current_exe().unwrap().display().to_string();
+        let mut host_mounts = opts.profile.mounts(self.config, &home_dir, &rtbox_exe, opts.init_mode);
+        host_mounts.extend(opts.mounts.iter().cloned());
+        host_mounts.push(RtBoxMount::new(&control_dir.display().to_string(), control::CONTROL_DIR, "rbind"));
+
+        let volumes = self.box_volumes(name, &home_dir, opts);
+        for (volume, named_volume) in &volumes {
+            let owner = Some(name).filter(|_| !volume.shared);
+            self.container_engine.create_volume(
+                named_volume.name.clone(),
+                volume::volume_labels(&volume.name, owner),
+            ).await?;
+        }
 
         let euid = unsafe {
             geteuid()
         };
+        let username = host_username(euid).ok_or(RtBoxError {
+            command: None,
+            message: Some("could not tell which user to create inside the container".to_string()),
+            root_cause: Some(format!("USER is not set and uid {} has no passwd entry", euid)),
+        })?;
         let egid = unsafe {
             getegid()
         };
-        let entrypoint = vec![
-            format!("/run/host{}", std::env::current_exe().unwrap().display()),
+        let mut entrypoint = vec![
+            format!("/run/host{}", rtbox_exe),
             "init".to_string(),
             "--uid".to_string(), euid.to_string(),
             "--gid".to_string(), egid.to_string(),
             "--home".to_string(), home_dir.to_string(),
-            "--username".to_string(), "akdev".to_string(),
+            "--username".to_string(), username,
             "--shell".to_string(), "/bin/bash".to_string(),
         ];
+        for package in &opts.additional_packages {
+            entrypoint.extend(["--additional-packages".to_string(), package.clone()]);
+        }
+        for hook in &opts.pre_init_hooks {
+            entrypoint.extend(["--pre-init-hook".to_string(), hook.clone()]);
+        }
+        for hook in &opts.init_hooks {
+            entrypoint.extend(["--init-hook".to_string(), hook.clone()]);
+        }
+        let hook_timeout = opts.hook_timeout.unwrap_or(self.config.hook_timeout);
+        let hook_failure_policy = opts.hook_failure_policy.unwrap_or(self.config.hook_failure_policy);
+        entrypoint.extend([
+            "--hook-timeout".to_string(), hook_timeout.to_string(),
+            "--hook-failure-policy".to_string(), hook_failure_policy.name().to_string(),
+        ]);
+        for service in &services {
+            entrypoint.extend(["--service".to_string(), serde_json::to_string(service).unwrap()]);
+        }
+        if opts.init_mode != RtBoxInitMode::Rtbox {
+            entrypoint.extend(["--init-mode".to_string(), opts.init_mode.name().to_string()]);
+        }
+        /* restricted boxes get no host helper, shims would only fail */
+        if opts.profile.allows_host_exec() {
+            for command in &self.config.host_commands.shims {
+                entrypoint.extend(["--host-command".to_string(), command.clone()]);
+            }
+            if self.config.host_commands.command_not_found {
+                entrypoint.push("--command-not-found".to_string());
+            }
+        }
         info!("setting entry point to {}", entrypoint[0]);
 
-        let container_env = vec![
-            "DBUS_SESSION_BUS_ADDRESS",
-            "DESKTOP_SESSION",
-            "DISPLAY",
-            "GDMSESSION",
-            "GDM_LANG",
-            "HOME",
-            "HOSTNAME",
-            "LANG",
-            "LOGNAME",
-            "LSCOLORS",
-            "LS_COLORS",
-            "QT_IM_MODULE",
-            "QT_WAYLAND_DECORATION",
-            "SESSION_MANAGER",
-            "TERM",
-            "USER",
-            "USERNAME",
-            "WAYLAND_DISPLAY",
-            "XCURSOR_SIZE",
-            "XCURSOR_THEME",
-            "XDG_CURRENT_DESKTOP",
-            "XDG_DATA_DIRS",
-            "XDG_MENU_PREFIX",
-            "XDG_RUNTIME_DIR",
-            "XDG_SESSION_CLASS",
-            "XDG_SESSION_DESKTOP",
-            "XDG_SESSION_TYPE",
-        ];
-        let total_vars = container_env.len();
+        let mut container_env = host_env();
+        if opts.home.is_some() {
+            container_env.retain(|env_var| env_var.0 != "HOME");
+            container_env.push(("HOME".to_string(), home_dir.clone()));
+        }
 
-        let container_env: Vec<_> = container_env
-            .into_iter()
-            .map(|env_var| (env_var, std::env::var(env_var)))
-            .filter(|env_var| env_var.1.is_ok())
-            .map(|env_var| (env_var.0, env_var.1.unwrap()))
-            .collect();
-        info!("detected {}/{} environment variables to preserve", container_env.len(), total_vars);
+        let (recorded_image, image_digest) = match origin {
+            Some(origin) => (origin.image.clone(), origin.image_digest.clone()),
+            None => match self.container_engine.inspect_image(image.to_string()).await {
+                Ok(image_info) => (image.to_string(), Some(image_info.digest).filter(|digest| !digest.is_empty())),
+                Err(err) => {
+                    debug!("could not resolve the digest of {}: {:?}", image, err);
+                    (image.to_string(), None)
+                },
+            },
+        };
+        let labels = RtBoxLabels {
+            labels_version: LABELS_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            image: recorded_image,
+            image_digest,
+            home: opts.home.clone(),
+            profile: opts.profile,
+            env: container_env.iter().map(|(name, _)| name.clone()).collect(),
+            hooks_hash: labels::hooks_hash(&opts.pre_init_hooks, &opts.init_hooks),
+            expires_at,
+        };
 
-        let container_create = self.container_engine.create(
-            name,
-            image,
+        let container_spec = RtBoxContainerSpec {
+            name: name.to_string(),
+            image: image.to_string(),
             entrypoint,
-            container_env,
-            host_mounts,
-        );
+            env: container_env,
+            mounts: host_mounts,
+            volumes: volumes.into_iter().map(|(_, named_volume)| named_volume).collect(),
+            security: opts.profile.security_opts(self.config),
+            systemd: opts.init_mode == RtBoxInitMode::Systemd,
+            workdir: home_dir.clone(),
+            labels: labels.to_labels(),
+        };
 
-        match container_create.await {
+        match self.container_engine.create(&container_spec).await {
             Ok(container) => Ok(RtBox{
                 name: name.to_string(),
                 image: image.to_string(),
@@ -190,10 +717,44 @@ impl<'a, T: ContainerEngine> RtBoxEngine<'a, T> {
             Err(err) => Err(err),
         }
     }
+    /// Volumes mounted into `name`, the global ones below `~/` only apply to boxes with their own home
+    /// so they do not hide what the host keeps there
+    fn box_volumes(&self, name: &str, home_dir: &str, opts: &RtBoxCreateOpts) -> Vec<(RtBoxVolumeConfig, RtBoxNamedVolume)> {
+        let box_volumes = self.config.boxes
+            .get(name)
+            .map(|box_config| box_config.volumes.clone())
+            .unwrap_or_default();
+        let preserved_volumes: Vec<RtBoxVolumeConfig> = self.preserve_paths(name)
+            .iter()
+            .map(|path| volume::preserved_volume(path))
+            .collect();
+        let requested = preserved_volumes
+            .iter()
+            .chain(self.config.volumes.iter().filter(|volume| opts.home.is_some() || !volume.path.starts_with("~/")))
+            .chain(box_volumes.iter())
+            .chain(opts.volumes.iter());
+
+        let mut volumes: Vec<(RtBoxVolumeConfig, RtBoxNamedVolume)> = vec![];
+        for volume in requested {
+            let named_volume = volume.resolve(name, home_dir);
+            volumes.retain(|(_, it)| it.destination != named_volume.destination);
+            volumes.push((volume.clone(), named_volume));
+        }
+
+        volumes
+    }
+    /// Container paths `name` keeps in volumes so upgrade carries them over
+    fn preserve_paths(&self, name: &str) -> Vec<String> {
+        self.config.preserve_paths
+            .iter()
+            .chain(self.config.boxes.get(name).iter().flat_map(|box_config| box_config.preserve_paths.iter()))
+            .cloned()
+            .collect()
+    }
     pub async fn rm(&self, name: String, force: Option<bool>, all: Option<bool>) -> Result<()> {
         debug!("rtbox-rm - name: {:?}, force: {:?}, all: {:?}", name, force, all);
 
-        Ok(())
+        self.container_engine.rm(name, force.unwrap_or(false)).await
     }
     pub async fn list(&self, all: Option<bool>) -> Result<Vec<RtBox>> {
         debug!("rtbox-list - all: {:?}", all);
@@ -220,37 +781,1193 @@ impl<'a, T: ContainerEngine> RtBoxEngine<'a, T> {
             })
         }
     }
-    pub async fn run(&self, container: String, command: Vec<String>) -> Result<RtBoxExecOutput> {
-        debug!("rtbox-run - container: {:?}, command: {:?}", container, command);
+    /// The box enter and run use when they are not given one
+    pub fn box_name(&self, name: Option<String>) -> Result<String> {
+        name.or(self.config.default_box.clone()).ok_or(RtBoxError {
+            command: None,
+            message: Some("no container given".to_string()),
+            root_cause: Some("name a container or set default_box in the configuration".to_string()),
+        })
+    }
+    /// Create `name` from its configuration unless it exists, boxes without configuration are left alone
+    async fn create_from_config(&self, name: &str) -> Result<()> {
+        let box_config = match self.config.boxes.get(name) {
+            Some(box_config) => box_config.clone(),
+            None if self.config.default_box.as_deref() == Some(name) => RtBoxBoxConfig::default(),
+            None => return Ok(()),
+        };
+
+        let exists = self.container_engine.list(true)
+            .await?
+            .iter()
+            .any(|container| container.names.iter().flatten().any(|it| it == name));
+        if exists {
+            return Ok(());
+        }
+
+        info!("{} does not exist yet, creating it from the configuration", name);
+        let image = self.resolve_image(box_config.image, box_config.distro, box_config.release)?;
+        let create_opts = RtBoxCreateOpts {
+            profile: box_config.profile,
+            home: box_config.home,
+            additional_packages: box_config.additional_packages,
+            pre_init_hooks: box_config.pre_init_hooks,
+            init_hooks: box_config.init_hooks,
+            init_mode: box_config.init,
+            ..RtBoxCreateOpts::default()
+        };
+        self.create(name, &image, &create_opts).await.map(|_| ())
+    }
+    pub async fn run(&self, container: String, command: Vec<String>, opts: RtBoxRunOpts) -> Result<RtBoxExecOutput> {
+        debug!("rtbox-run - container: {:?}, command: {:?}, opts: {:?}", container, command, opts);
+
+        self.exec(&container, command, opts).await
+    }
+    /// Run `command` in a throwaway box created from `image`, the box is removed afterwards even when interrupted
+    pub async fn run_ephemeral(&self, image: String, command: Vec<String>, opts: RtBoxRunOpts) -> Result<RtBoxExecOutput> {
+        debug!("rtbox-run-ephemeral - image: {:?}, command: {:?}, opts: {:?}", image, command, opts);
+
+        let image = self.resolve_image(Some(image), None, None)?;
+        if self.container_engine.inspect_image(image.clone()).await.is_err() {
+            info!("pulling {}", image);
+            self.container_engine.pull_image(image.clone()).await?;
+        }
+
+        let name = format!("rtbox-run-{}", std::process::id());
+        self.create(&name, &image, &RtBoxCreateOpts::default()).await?;
+
+        let mut interrupt = signal(SignalKind::interrupt()).ok();
+        let mut terminate = signal(SignalKind::terminate()).ok();
+        let interrupted = async {
+            match (interrupt.as_mut(), terminate.as_mut()) {
+                (Some(interrupt), Some(terminate)) => tokio::select! {
+                    _ = interrupt.recv() => {},
+                    _ = terminate.recv() => {},
+                },
+                _ => std::future::pending().await,
+            }
+        };
+
+        /* ctrl-c only reaches us without a terminal, with one it goes to the command and ends it */
+        let run_result = tokio::select! {
+            run_result = self.exec(&name, command, opts) => run_result,
+            _ = interrupted => {
+                warn!("interrupted, removing {}", name);
+                Ok(RtBoxExecOutput { return_code: 130, exec_id: None })
+            },
+        };
+
+        if let Err(err) = self.container_engine.rm(name.clone(), true).await {
+            warn!("could not remove {}: {:?}", name, err.message);
+        }
+        let _ = std::fs::remove_dir_all(control::host_control_dir(&name));
+
+        run_result
+    }
+    pub async fn enter(&self, name: &str, workdir: Option<String>) -> Result<RtBoxExecOutput> {
+        debug!("rtbox-enter - name: {:?}, workdir: {:?}", name, workdir);
+
+        let shell = std::env::var("SHELL").unwrap_or("/bin/bash".to_string());
+        let opts = RtBoxRunOpts {
+            workdir,
+            tty: Some(true),
+            ..RtBoxRunOpts::default()
+        };
+        self.exec(name, vec![shell, "-l".to_string()], opts).await
+    }
+    /// Our working directory as seen from inside `container`, the container's default when it is not visible there
+    fn caller_workdir(&self, container: &RtBoxContainerInfo) -> Option<String> {
+        let cwd = std::env::current_dir().ok()?;
+
+        match container.container_path(&cwd) {
+            Some(workdir) => Some(workdir.display().to_string()),
+            None => {
+                warn!("{} is not available inside {}, starting in the home directory", cwd.display(), container.name);
+                None
+            },
+        }
+    }
+    async fn exec(&self, name: &str, command: Vec<String>, opts: RtBoxRunOpts) -> Result<RtBoxExecOutput> {
+        self.create_from_config(name).await?;
+
+        let mut env = host_env();
+        for env_var in &opts.env {
+            match env_var.split_once('=') {
+                Some((key, value)) if !key.is_empty() => env.push((key.to_string(), value.to_string())),
+                None if !env_var.is_empty() => {
+                    if let Ok(value) = std::env::var(env_var) {
+                        env.push((env_var.clone(), value));
+                    }
+                },
+                _ => return Err(RtBoxError {
+                    command: None,
+                    message: Some(format!("invalid environment variable {:?}", env_var)),
+                    root_cause: Some("expected KEY=VALUE or KEY".to_string()),
+                }),
+            }
+        }
+
+        let mut container = self.container_engine.inspect(name.to_string()).await?;
+
+        if !container.running {
+            info!("starting container {}", name);
+            /* runtime dirs are gone after a reboot, podman refuses to start without the mount source */
+            if container.kind() == RtBoxKind::Rtbox {
+                let control_dir = container.control_dir().unwrap_or(control::host_control_dir(name));
+                if let Err(err) = control::link_control_dir(name, &control_dir) {
+                    warn!("could not prepare the control directory of {}: {}", name, err);
+                }
+            }
+            self.container_engine.start(name.to_string()).await?;
+            container = self.container_engine.inspect(name.to_string()).await?;
+
+            match container.kind() {
+                RtBoxKind::Toolbx if !toolbx::wait_for_init(&container).await => {
+                    return Err(RtBoxError {
+                        command: None,
+                        message: Some(format!("container {} did not finish initializing", name)),
+                        root_cause: Some("toolbox init-container timed out".to_string()),
+                    });
+                },
+                RtBoxKind::Rtbox if container.init_mode() == RtBoxInitMode::Rtbox => self.wait_for_init(name).await?,
+                _ => {},
+            }
+        }
+
+        /* systemd may still be booting even if someone else started the container */
+        if container.kind() == RtBoxKind::Rtbox && container.init_mode() == RtBoxInitMode::Systemd {
+            self.wait_for_systemd(name).await?;
+        }
+
+        let command = match container.kind() {
+            RtBoxKind::Toolbx => toolbx::wrap_command(command),
+            _ => command,
+        };
+
+        let tty = opts.tty.unwrap_or_else(tty::is_interactive);
+        let exec_opts = RtBoxExecOpts {
+            user: opts.user.or(std::env::var("USER").ok()),
+            workdir: opts.workdir.or_else(|| self.caller_workdir(&container)),
+            env,
+            tty,
+            interactive: !opts.detach,
+            timeout: opts.timeout,
+        };
+
+        let host_exec = container.kind() == RtBoxKind::Rtbox && container.profile().allows_host_exec();
+        if opts.detach {
+            if host_exec {
+                self.ensure_host_helper(name);
+            }
+            return self.container_engine.exec_detached(name.to_string(), command, exec_opts)
+                .await
+                .map(|exec_id| RtBoxExecOutput { return_code: 0, exec_id: Some(exec_id) });
+        }
+
+        if host_exec {
+            self.ensure_host_helper(name);
+        }
+        let session = match container.kind() {
+            RtBoxKind::Rtbox => self.register_session(name).await,
+            _ => None,
+        };
+
+        let exec_result = self.container_engine.exec(name.to_string(), command, exec_opts)
+            .await
+            .map(|return_code| RtBoxExecOutput { return_code, exec_id: None });
+
+        if let Some(session) = session {
+            if let Err(err) = self.control(name, "session.unregister", serde_json::json!({ "id": session })).await {
+                debug!("could not unregister session {} of {}: {:?}", session, name, err);
+            }
+        }
+
+        exec_result
+    }
+
+    /// Wait for systemd to finish booting, which includes our setup unit, then check how the setup went
+    async fn wait_for_systemd(&self, name: &str) -> Result<()> {
+        debug!("waiting for systemd in {} to finish booting", name);
+
+        let exec_opts = RtBoxExecOpts::default();
+        let is_system_running = vec![
+            "systemctl".to_string(),
+            "is-system-running".to_string(),
+            "--wait".to_string(),
+            "--quiet".to_string(),
+        ];
+        if self.container_engine.exec(name.to_string(), is_system_running, exec_opts).await? != 0 {
+            warn!("systemd in {} finished booting in a degraded state", name);
+        }
+
+        self.wait_for_init(name).await
+    }
+
+    /// Start `rtbox host-helper` for `name` in the background unless one is already listening
+    fn ensure_host_helper(&self, name: &str) {
+        if std::os::unix::net::UnixStream::connect(host_exec::host_socket(name)).is_ok() {
+            return;
+        }
 
-        Ok(RtBoxExecOutput {
-            stderr: vec![char::default()],
-            stdout: vec![char::default()],
-            return_code: 0,
+        let helper = std::env::current_exe().and_then(|rtbox_exe| {
+            std::process::Command::new(rtbox_exe)
+                .args(["host-helper", name])
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .process_group(0)
+                .spawn()
+        });
+        if let Err(err) = helper {
+            warn!("could not start the host helper for {}, host-exec will not work: {}", name, err);
+        }
+    }
+
+    /// Serve host-exec requests for `name` for as long as the container is running
+    pub async fn host_helper(&self, name: &str) -> Result<()> {
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        if !container.profile().allows_host_exec() {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("{} does not allow running commands on the host", name)),
+                root_cause: Some(format!("it was created with the {} profile", container.profile().name())),
+            });
+        }
+
+        let socket_path = host_exec::host_socket(name);
+        host_exec::serve(&socket_path, self.config.host_commands.clone()).map_err(|err| RtBoxError {
+            command: None,
+            message: Some(format!("could not listen on {}", socket_path.display())),
+            root_cause: Some(err.to_string()),
+        })?;
+
+        loop {
+            tokio::time::sleep(HOST_HELPER_POLL_INTERVAL).await;
+            match self.container_engine.inspect(name.to_string()).await {
+                Ok(container) if container.running => continue,
+                _ => break,
+            }
+        }
+
+        info!("{} is not running anymore, stopping the host helper", name);
+        let _ = std::fs::remove_file(socket_path);
+        Ok(())
+    }
+
+    /// Run `command` on the host from inside a box through the host helper
+    pub fn host_exec(&self, command: Vec<String>, pty: bool) -> Result<RtBoxExecOutput> {
+        let socket_path = host_exec::container_socket();
+        host_exec::host_exec(&socket_path, command, pty)
+            .map(|return_code| RtBoxExecOutput { return_code: return_code as i64, exec_id: None })
+            .map_err(|err| RtBoxError {
+                command: None,
+                message: Some("could not run the command on the host".to_string()),
+                root_cause: Some(format!("{}: {}", socket_path.display(), err)),
+            })
+    }
+
+    /// Hand init our environment and tell it about the session, boxes without a control socket are fine
+    async fn register_session(&self, name: &str) -> Option<u64> {
+        let env: BTreeMap<String, String> = host_env().into_iter().collect();
+        let session = match self.control(name, "env.refresh", serde_json::json!({ "env": env })).await {
+            Ok(_) => self.control(name, "session.register", serde_json::json!({ "pid": std::process::id() })).await,
+            Err(err) => Err(err),
+        };
+
+        match session.and_then(Self::control_result::<RtBoxSession>) {
+            Ok(session) => Some(session.id),
+            Err(err) => {
+                debug!("not registering a session with {}: {:?}", name, err);
+                None
+            },
+        }
+    }
+
+    /// Ask init to stop its services and exit, the container engine stops the box if that does not work,
+    /// systemd boxes are stopped by the engine right away since it sends systemd its shutdown signal
+    pub async fn stop(&self, name: &str) -> Result<()> {
+        if !self.is_systemd_box(name).await? && self.control(name, "shutdown", serde_json::json!({})).await.is_ok() {
+            let start_time = time::Instant::now();
+            while start_time.elapsed() < SHUTDOWN_TIMEOUT {
+                if !self.container_engine.inspect(name.to_string()).await?.running {
+                    return Ok(());
+                }
+                tokio::time::sleep(time::Duration::from_millis(250)).await;
+            }
+            warn!("init of {} did not shut down in time, stopping the container", name);
+        }
+
+        self.container_engine.stop(name.to_string()).await
+    }
+
+    pub async fn status(&self, name: &str) -> Result<RtBoxInitReport> {
+        let report = self.control(name, "status", serde_json::json!({})).await?;
+        Self::control_result(report)
+    }
+    pub async fn inspect(&self, name: &str) -> Result<RtBoxInspect> {
+        debug!("rtbox-inspect - name: {:?}", name);
+
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        let readiness = match container.kind() {
+            RtBoxKind::Rtbox => self.readiness(name).await,
+            _ => None,
+        };
+
+        Ok(RtBoxInspect {
+            kind: container.kind(),
+            creation: container.init_args(),
+            recorded: RtBoxLabels::from_labels(&container.labels),
+            exports: export::list_exports(Some(&container.name)),
+            readiness,
+            id: container.id,
+            name: container.name,
+            status: container.status,
+            created: container.created,
+            image: container.image,
+            image_digest: container.image_digest,
+            entrypoint: container.entrypoint,
+            mounts: container.mounts,
+            env: container.env,
+            labels: container.labels,
+            security: container.security,
+        })
+    }
+    /// Recreate a container made by toolbx so it runs with rtbox as init,
+    /// the image, name and user mounts of the original container are kept unless the box is restricted
+    pub async fn adopt(&self, name: &str, profile: RtBoxProfile) -> Result<RtBox> {
+        info!("adopting toolbx container {}", name);
+
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        if container.kind() != RtBoxKind::Toolbx {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("{} was not created by toolbx", name)),
+                root_cause: Some(format!("unexpected entrypoint: {:?}", container.entrypoint)),
+            });
+        }
+
+        let home_dir = std::env::var("HOME").unwrap();
+        let rtbox_exe = std::env::current_exe().unwrap().display().to_string();
+        /* toolbx containers are never systemd boxes */
+        let profile_mounts = profile.mounts(self.config, &home_dir, &rtbox_exe, RtBoxInitMode::Rtbox);
+
+        /* toolbx mounts the host root, devices and the home, a restricted box only gets what its profile mounts */
+        let user_mounts: Vec<RtBoxMount> = container.mounts
+            .into_iter()
+            .filter(|mount| !toolbx::is_internal_mount(&mount.destination))
+            .filter(|mount| profile_mounts.iter().all(|it| it.destination != mount.destination))
+            .filter(|mount| {
+                if profile == RtBoxProfile::Restricted {
+                    warn!("not mounting {} into restricted box {}", mount.source, name);
+                }
+                profile != RtBoxProfile::Restricted
+            })
+            .collect();
+        debug!("preserving mounts: {:?}", user_mounts);
+
+        if container.running {
+            self.container_engine.stop(name.to_string()).await?;
+        }
+
+        /* keep the original around until the replacement is created successfully */
+        let backup_name = format!("{}-toolbx", name);
+        self.container_engine.rename(name.to_string(), backup_name.clone()).await?;
+
+        let create_opts = RtBoxCreateOpts {
+            profile,
+            mounts: user_mounts,
+            ..RtBoxCreateOpts::default()
+        };
+
+        match self.create(name, &container.image, &create_opts).await {
+            Ok(rtbox) => {
+                self.container_engine.rm(backup_name, true).await?;
+                Ok(rtbox)
+            },
+            Err(err) => {
+                warn!("could not recreate {}, restoring original container", name);
+                self.container_engine.rename(backup_name, name.to_string()).await?;
+                if container.running {
+                    self.container_engine.start(name.to_string()).await?;
+                }
+                Err(err)
+            },
+        }
+    }
+    /// Options that recreate `container` the way it was created, read back from its init arguments and labels
+    fn recorded_create_opts(&self, container: &RtBoxContainerInfo) -> RtBoxCreateOpts {
+        let recorded = RtBoxLabels::from_labels(&container.labels);
+        let init_args = container.init_args();
+        let init_arg = |name: &str| init_args.get(name).cloned().unwrap_or_default();
+
+        let profile = container.profile();
+        /* a recreated box keeps the expiry of the original one */
+        let ttl = recorded.as_ref()
+            .and_then(|recorded| recorded.expires_at)
+            .map(|expires_at| expires_at.saturating_sub(init::unix_time()));
+        let home = recorded.and_then(|recorded| recorded.home);
+        let home_dir = home.clone().unwrap_or(std::env::var("HOME").unwrap());
+        let rtbox_exe = std::env::current_exe().unwrap().display().to_string();
+        let profile_mounts = profile.mounts(self.config, &home_dir, &rtbox_exe, container.init_mode());
+        let user_mounts = container.mounts
+            .iter()
+            .filter(|mount| mount.destination != control::CONTROL_DIR)
+            .filter(|mount| profile_mounts.iter().all(|it| it.destination != mount.destination))
+            .cloned()
+            .collect();
+        let volumes = container.volumes
+            .iter()
+            .filter_map(|named_volume| {
+                let volume = RtBoxVolumeConfig::from_named_volume(named_volume, &container.name);
+                if volume.is_none() {
+                    warn!("volume {} of {} is not managed by rtbox and will not be carried over", named_volume.name, container.name);
+                }
+                volume
+            })
+            .collect();
+
+        RtBoxCreateOpts {
+            profile,
+            mounts: user_mounts,
+            home,
+            additional_packages: init_arg("additional-packages"),
+            pre_init_hooks: init_arg("pre-init-hook"),
+            init_hooks: init_arg("init-hook"),
+            hook_timeout: init_arg("hook-timeout").first().and_then(|timeout| timeout.parse().ok()),
+            hook_failure_policy: init_arg("hook-failure-policy").first().and_then(|policy| {
+                [RtBoxHookFailurePolicy::Abort, RtBoxHookFailurePolicy::Continue]
+                    .into_iter()
+                    .find(|it| it.name() == policy)
+            }),
+            init_mode: container.init_mode(),
+            ttl,
+            volumes,
+        }
+    }
+    /// Start a freshly created box and wait until init is done with it
+    async fn start_and_wait(&self, name: &str, init_mode: RtBoxInitMode) -> Result<()> {
+        self.container_engine.start(name.to_string()).await?;
+        match init_mode {
+            RtBoxInitMode::Rtbox => self.wait_for_init(name).await,
+            RtBoxInitMode::Systemd => self.wait_for_systemd(name).await,
+        }
+    }
+    /// Recreate `name` on the latest version of its image with the same parameters,
+    /// init runs the hooks again and the old container comes back if it fails
+    pub async fn upgrade(&self, name: &str, force: bool, preserve_paths: &[String]) -> Result<RtBoxUpgrade> {
+        info!("upgrading {}", name);
+
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        if container.kind() != RtBoxKind::Rtbox {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("{} is not a rtbox container", name)),
+                root_cause: Some("only rtbox containers can be upgraded, adopt it first".to_string()),
+            });
+        }
+
+        let recorded = RtBoxLabels::from_labels(&container.labels);
+        let image = recorded.as_ref()
+            .map(|recorded| recorded.image.clone())
+            .filter(|image| !image.is_empty())
+            .unwrap_or(container.image.clone());
+        let old_digest = Some(container.image_digest.clone()).filter(|digest| !digest.is_empty());
+
+        self.container_engine.pull_image(image.clone()).await?;
+        let new_digest = Some(self.container_engine.inspect_image(image.clone()).await?.digest)
+            .filter(|digest| !digest.is_empty());
+
+        if !force && new_digest.is_some() && new_digest == old_digest {
+            info!("{} already uses the latest {}", name, image);
+            return Ok(RtBoxUpgrade {
+                name: name.to_string(),
+                image,
+                old_digest,
+                new_digest,
+                upgraded: false,
+                preserved_paths: vec![],
+            });
+        }
+
+        /* preserved paths live in volumes the new container mounts again, paths that
+           are no volume yet become one and get what the old container has there copied in */
+        let mut create_opts = self.recorded_create_opts(&container);
+        let mut preserved = vec![];
+        let mut copied = vec![];
+        for path in self.preserve_paths(name).iter().chain(preserve_paths) {
+            if preserved.contains(path) {
+                continue;
+            }
+            preserved.push(path.clone());
+            if container.volumes.iter().all(|named_volume| named_volume.destination != *path) {
+                info!("keeping {} in a volume from now on", path);
+                create_opts.volumes.push(volume::preserved_volume(path));
+                copied.push(path.clone());
+            }
+        }
+        if container.running {
+            self.stop(name).await?;
+        }
+
+        /* keep the old container around until the new one initialized successfully */
+        let backup_name = format!("{}-pre-upgrade", name);
+        self.container_engine.rename(name.to_string(), backup_name.clone()).await?;
+
+        let mut upgrade_result = self.create(name, &image, &create_opts).await.map(|_| ());
+        for path in &copied {
+            if upgrade_result.is_err() {
+                break;
+            }
+            upgrade_result = self.container_engine.copy_path(backup_name.clone(), name.to_string(), path.clone()).await;
+        }
+        if upgrade_result.is_ok() {
+            upgrade_result = self.start_and_wait(name, create_opts.init_mode).await;
+        }
+
+        if let Err(err) = upgrade_result {
+            warn!("upgrading {} failed, restoring the old container", name);
+            let _ = self.container_engine.rm(name.to_string(), true).await;
+            /* the old container never had the volumes of paths preserved for the first time */
+            for path in &copied {
+                let volume_name = volume::volume_name(&volume::preserved_volume(path).name, Some(name));
+                if let Err(err) = self.container_engine.remove_volume(volume_name.clone(), false).await {
+                    warn!("could not remove the volume {}: {:?}", volume_name, err.message);
+                }
+            }
+            self.container_engine.rename(backup_name, name.to_string()).await?;
+            if container.running {
+                self.container_engine.start(name.to_string()).await?;
+            }
+            return Err(err);
+        }
+
+        self.container_engine.rm(backup_name, true).await?;
+        if !container.running {
+            self.stop(name).await?;
+        }
+
+        Ok(RtBoxUpgrade {
+            name: name.to_string(),
+            image,
+            old_digest,
+            new_digest,
+            upgraded: true,
+            preserved_paths: preserved,
+        })
+    }
+    /// Turn a box into an image others can create boxes from, without the current user baked in
+    pub async fn commit(&self, name: &str, image: &str) -> Result<RtBoxImageInfo> {
+        info!("committing {} to {}", name, image);
+
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        if container.kind() == RtBoxKind::Unknown {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("{} is not a rtbox container", name)),
+                root_cause: Some("only rtbox and toolbx containers can be committed".to_string()),
+            });
+        }
+
+        let username = Self::box_username(&container)?;
+        let mut changes = vec![
+            format!("LABEL {}=true", toolbx::TOOLBX_LABEL),
+            format!("LABEL {}=true", toolbx::TOOLBX_LEGACY_LABEL),
+            /* the box ran rtbox init from its command, new boxes bring their own */
+            "CMD [\"/bin/sh\"]".to_string(),
+            "WORKDIR /".to_string(),
+        ];
+        changes.extend(labels::clear_instructions(&container.labels));
+
+        self.container_engine.commit(name.to_string(), image.to_string(), init::cleanup_command(&username), changes).await?;
+        self.container_engine.inspect_image(image.to_string()).await
+    }
+    /// The user to remove from a snapshot of `container`, the one it was created for rather than whoever runs us
+    fn box_username(container: &RtBoxContainerInfo) -> Result<String> {
+        container.username().ok_or(RtBoxError {
+            command: None,
+            message: Some(format!("could not tell which user {} was created for", container.name)),
+            root_cause: Some("the container has no --username init argument".to_string()),
+        })
+    }
+    /// Copy `source` into a new box `name` with the same recorded options, through a temporary image
+    pub async fn clone_box(&self, source: &str, name: &str) -> Result<RtBox> {
+        info!("cloning {} into {}", source, name);
+
+        let container = self.container_engine.inspect(source.to_string()).await?;
+        if container.kind() != RtBoxKind::Rtbox {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("{} is not a rtbox container", source)),
+                root_cause: Some("only rtbox containers can be cloned, adopt it first".to_string()),
+            });
+        }
+
+        let clone_image = format!("localhost/rtbox-clone-{}:latest", name);
+        self.container_engine.commit(source.to_string(), clone_image.clone(), vec![], vec![]).await?;
+
+        /* boxes created before labels existed record the clone image, there is nothing better to record */
+        let origin = RtBoxLabels::from_labels(&container.labels);
+        let mut create_opts = self.recorded_create_opts(&container);
+        /* the clone gets per box volumes of its own, even when the source was renamed since */
+        create_opts.volumes.iter_mut().for_each(|volume| volume.volume = None);
+        let cloned = self.create_copy(name, &clone_image, origin.as_ref(), &create_opts).await;
+
+        if let Err(err) = self.container_engine.untag_image(clone_image.clone()).await {
+            warn!("could not remove the temporary image {}: {:?}", clone_image, err.message);
+        }
+        let cloned = cloned?;
+
+        /* commit leaves volumes out, what the per box ones hold is copied into the clone's own */
+        for named_volume in container.box_volumes() {
+            let copied = self.container_engine
+                .copy_path(source.to_string(), name.to_string(), named_volume.destination.clone())
+                .await;
+            if let Err(err) = copied {
+                warn!("could not copy {} of {} into {}, it starts out empty: {:?}", named_volume.destination, source, name, err.message);
+            }
+        }
+
+        /* the temporary image is gone, report the image the clone follows */
+        Ok(RtBox {
+            image: origin.map(|origin| origin.image).unwrap_or(container.image),
+            ..cloned
         })
     }
-    pub async fn init<'b>(
+    /// Write `name` to an archive holding its committed image, how it was created and what it exports
+    pub async fn save(&self, name: &str, output: &Path) -> Result<RtBoxArchiveManifest> {
+        info!("saving {} to {}", name, output.display());
+
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        if container.kind() != RtBoxKind::Rtbox {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("{} is not a rtbox container", name)),
+                root_cause: Some("only rtbox containers can be saved, adopt it first".to_string()),
+            });
+        }
+
+        for named_volume in container.box_volumes() {
+            warn!("{} of {} is kept in a volume and not saved, it starts out empty when the box is loaded", named_volume.destination, name);
+        }
+
+        /* the user is added again for whoever loads the box */
+        let image = format!("localhost/rtbox-save-{}:latest", name);
+        let username = Self::box_username(&container)?;
+        self.container_engine.commit(name.to_string(), image.clone(), init::cleanup_command(&username), vec![]).await?;
+
+        /* staged next to the output, image archives can be far too large for memory or a tmpfs */
+        let image_archive = output.with_file_name(format!(
+            ".{}.image.tar",
+            output.file_name().map(|file_name| file_name.to_string_lossy().to_string()).unwrap_or_default(),
+        ));
+        let saved = self.container_engine.save_image(image.clone(), image_archive.clone()).await;
+        if let Err(err) = self.container_engine.untag_image(image.clone()).await {
+            warn!("could not remove the temporary image {}: {:?}", image, err.message);
+        }
+
+        let manifest = RtBoxArchiveManifest {
+            archive_version: ARCHIVE_VERSION,
+            name: name.to_string(),
+            image,
+            exports: export::list_exports(Some(name)),
+            home: std::env::var("HOME").unwrap_or_default(),
+            uid: unsafe { geteuid() },
+            gid: unsafe { getegid() },
+            container,
+        };
+        let written = saved.and_then(|_| archive::write(output, &manifest, &image_archive).map_err(|err| RtBoxError {
+            command: None,
+            message: Some(format!("could not write {}", output.display())),
+            root_cause: Some(err.to_string()),
+        }));
+        let _ = std::fs::remove_file(&image_archive);
+        written?;
+
+        Ok(manifest)
+    }
+    /// Recreate a box saved with `save`, moving its paths to our HOME and handing its files to our uid
+    pub async fn load(&self, input: &Path, name: Option<String>) -> Result<RtBox> {
+        info!("loading {}", input.display());
+
+        let (manifest, image_archive) = archive::read(input).map_err(|err| RtBoxError {
+            command: None,
+            message: Some(format!("could not read {}", input.display())),
+            root_cause: Some(err.to_string()),
+        })?;
+        if manifest.archive_version > ARCHIVE_VERSION {
+            warn!("{} was written by a newer rtbox (version {}), loading it anyway", input.display(), manifest.archive_version);
+        }
+        let name = name.unwrap_or(manifest.name.clone());
+
+        let image = self.container_engine.load_image(input.to_path_buf(), image_archive).await?
+            .into_iter()
+            .next()
+            .unwrap_or(manifest.image.clone());
+
+        let container = manifest.rebased_container(&std::env::var("HOME").unwrap_or_default());
+        let origin = RtBoxLabels::from_labels(&container.labels);
+        let mut create_opts = self.recorded_create_opts(&container);
+        create_opts.mounts.retain(|mount| {
+            let exists = Path::new(&mount.source).exists();
+            if !exists {
+                warn!("not mounting {} into {}, it does not exist on this host", mount.source, name);
+            }
+            exists
+        });
+        create_opts.volumes.iter_mut().for_each(|volume| volume.volume = None);
+        for named_volume in container.box_volumes() {
+            warn!("{} of {} was kept in a volume and not saved, {} gets an empty one", named_volume.destination, manifest.name, name);
+        }
+
+        let loaded = self.create_copy(&name, &image, origin.as_ref(), &create_opts).await;
+        if let Err(err) = self.container_engine.untag_image(image.clone()).await {
+            warn!("could not remove the temporary image {}: {:?}", image, err.message);
+        }
+        let loaded = loaded?;
+
+        let (uid, gid) = unsafe { (geteuid(), getegid()) };
+        if (uid, gid) != (manifest.uid, manifest.gid) {
+            let hook = archive::ownership_hook(manifest.uid, manifest.gid, uid, gid).map_err(|err| RtBoxError {
+                command: None,
+                message: Some(format!("could not hand the files of {} over to uid {}", name, uid)),
+                root_cause: Some(err.to_string()),
+            });
+            let imported = match hook {
+                Ok(hook) => self.container_engine.import_path(name.clone(), "/".to_string(), hook).await,
+                Err(err) => Err(err),
+            };
+            /* a box whose files belong to somebody else is of no use */
+            if let Err(err) = imported {
+                if let Err(rm_err) = self.container_engine.rm(name.clone(), true).await {
+                    warn!("could not remove {}: {:?}", name, rm_err.message);
+                }
+                return Err(err);
+            }
+        }
+
+        for saved_export in &manifest.exports {
+            let exported = match saved_export.kind {
+                RtBoxExportKind::Binary => self.export(&name, Some(saved_export.name.clone()), None, None).await,
+                RtBoxExportKind::Application => self.export(&name, None, None, Some(saved_export.name.clone())).await,
+                RtBoxExportKind::ServiceUnit => self.export(&name, None, Some(saved_export.name.clone()), None).await,
+            };
+            if let Err(err) = exported {
+                warn!("could not export {} from {}: {:?}", saved_export.name, name, err.message);
+            }
+        }
+
+        Ok(RtBox {
+            image: origin.map(|origin| origin.image).unwrap_or(container.image),
+            ..loaded
+        })
+    }
+    /// When a session last started or ended in `name`, as recorded by init
+    async fn last_session_at(&self, name: &str) -> Option<u64> {
+        /* stopped boxes have no control socket, the file survives in their filesystem */
+        match self.status(name).await {
+            Ok(report) => report.last_session_at,
+            Err(_) => self.container_engine
+                .read_file(name.to_string(), LAST_SESSION_PATH.to_string())
+                .await
+                .ok()
+                .and_then(|contents| String::from_utf8_lossy(&contents).trim().parse().ok()),
+        }
+    }
+    /// Remove boxes past their TTL, stopped boxes without a session for `unused` seconds,
+    /// dangling toolbox images and exports whose box is gone
+    pub async fn prune(&self, unused: Option<u64>, dry_run: bool) -> Result<RtBoxPruneReport> {
+        info!("pruning, unused: {:?}, dry_run: {:?}", unused, dry_run);
+
+        let now = init::unix_time();
+        let containers = self.container_engine.list(true).await?;
+        let mut report = RtBoxPruneReport { dry_run, ..RtBoxPruneReport::default() };
+
+        for container in &containers {
+            let Some(name) = container.names.iter().flatten().next().cloned() else {
+                continue;
+            };
+            let labels: BTreeMap<String, String> = container.labels.clone().unwrap_or_default().into_iter().collect();
+            let running = container.state.as_deref() == Some("running");
+
+            let expired = RtBoxLabels::from_labels(&labels)
+                .and_then(|recorded| recorded.expires_at)
+                .filter(|expires_at| *expires_at <= now);
+            let reason = match (expired, unused) {
+                (Some(_), _) => "expired".to_string(),
+                (None, Some(unused)) if !running => {
+                    /* boxes never entered count from when they last stopped or were created */
+                    let last_used = match self.last_session_at(&name).await {
+                        Some(last_session_at) => last_session_at,
+                        None => container.exited_at
+                            .filter(|exited_at| *exited_at > 0)
+                            .or(container.created.map(|created| created.timestamp()))
+                            .unwrap_or_default() as u64,
+                    };
+                    if now.saturating_sub(last_used) < unused {
+                        continue;
+                    }
+                    format!("unused for {} days", now.saturating_sub(last_used) / (24 * 60 * 60))
+                },
+                _ => continue,
+            };
+
+            if !dry_run {
+                if running {
+                    if let Err(err) = self.stop(&name).await {
+                        warn!("could not stop box {}: {:?}", name, err.message);
+                        continue;
+                    }
+                }
+                if let Err(err) = self.container_engine.rm(name.clone(), true).await {
+                    warn!("could not remove box {}: {:?}", name, err.message);
+                    continue;
+                }
+                let _ = std::fs::remove_dir_all(control::host_control_dir(&name));
+            }
+            report.boxes.push(RtBoxPrunedBox { name, reason });
+        }
+
+        for image in self.container_engine.dangling_images().await? {
+            if !dry_run {
+                if let Err(err) = self.container_engine.remove_image(image.id.clone()).await {
+                    warn!("could not remove image {}: {:?}", image.id, err.message);
+                    continue;
+                }
+            }
+            report.images.push(image);
+        }
+
+        let remaining: Vec<String> = containers.iter()
+            .filter_map(|container| container.names.iter().flatten().next().cloned())
+            .filter(|name| report.boxes.iter().all(|pruned| &pruned.name != name))
+            .collect();
+        for orphan in export::list_exports(None).into_iter().filter(|it| !remaining.contains(&it.container)) {
+            if !dry_run {
+                if let Err(err) = std::fs::remove_file(&orphan.path) {
+                    warn!("could not remove {}: {}", orphan.path, err);
+                    continue;
+                }
+            }
+            report.exports.push(orphan);
+        }
+
+        Ok(report)
+    }
+    /// Rename a box, init picks up the new hostname and exports are pointed at the new name
+    pub async fn rename(&self, name: &str, new_name: &str) -> Result<RtBox> {
+        info!("renaming {} to {}", name, new_name);
+
+        let taken = self.container_engine.list(true).await?
+            .iter()
+            .any(|container| container.names.iter().flatten().any(|it| it == new_name));
+        if taken || name == new_name {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("cannot rename {} to {}", name, new_name)),
+                root_cause: Some(format!("a container named {} already exists", new_name)),
+            });
+        }
+
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        self.container_engine.rename(name.to_string(), new_name.to_string()).await?;
+
+        if container.kind() == RtBoxKind::Rtbox {
+            /* the old name may be an alias left by an earlier rename, the mount source stays where it is */
+            let control_dir = container.control_dir().unwrap_or(control::host_control_dir(name));
+            if let Err(err) = control::link_control_dir(new_name, &control_dir) {
+                warn!("could not link the control directory of {}: {}", new_name, err);
+            }
+            let old_control_dir = control::host_control_dir(name);
+            if old_control_dir != control_dir {
+                let _ = std::fs::remove_file(old_control_dir);
+            }
+
+            /* stopped boxes get the new hostname from init on their next start */
+            if container.running {
+                if let Err(err) = self.control(new_name, "hostname.update", serde_json::json!({ "name": new_name })).await {
+                    warn!("could not update the hostname of {}: {:?}", new_name, err.message);
+                }
+            }
+        }
+
+        for renamed_export in export::list_exports(Some(name)) {
+            if let Err(err) = export::rename_export(&renamed_export, new_name) {
+                warn!("could not move {} over to {}: {}", renamed_export.path, new_name, err);
+            }
+        }
+
+        Ok(RtBox {
+            name: new_name.to_string(),
+            image: container.image,
+            id: container.id,
+            status: container.running.to_string(),
+        })
+    }
+    /// How far init got, asked over the control socket or read from the readiness file
+    async fn readiness(&self, name: &str) -> Option<RtBoxReadiness> {
+        /* boxes without a control socket still have the readiness file */
+        match self.status(name).await {
+            Ok(report) => report.readiness,
+            Err(_) => self.container_engine
+                .read_file(name.to_string(), RTBOX_READINESS_PATH.to_string())
+                .await
+                .ok()
+                .and_then(|contents| serde_json::from_slice::<RtBoxReadiness>(&contents).ok()),
+        }
+    }
+    /// Block until `rtbox init` inside the container reports that it is ready
+    pub async fn wait_for_init(&self, name: &str) -> Result<()> {
+        debug!("waiting for {} to finish initializing", name);
+
+        let start_time = time::Instant::now();
+        while start_time.elapsed() < INIT_TIMEOUT {
+            match self.readiness(name).await {
+                Some(RtBoxReadiness { status: RtBoxInitStatus::Ready, message, .. }) => {
+                    if let Some(message) = message {
+                        warn!("container {} initialized with errors: {}", name, message);
+                    }
+                    return Ok(());
+                },
+                Some(RtBoxReadiness { status: RtBoxInitStatus::Failed, message, .. }) => {
+                    return Err(RtBoxError {
+                        command: None,
+                        message: Some(format!("container {} failed to initialize", name)),
+                        root_cause: message,
+                    });
+                },
+                _ => tokio::time::sleep(time::Duration::from_millis(500)).await,
+            }
+        }
+
+        Err(RtBoxError {
+            command: None,
+            message: Some(format!("container {} did not finish initializing", name)),
+            root_cause: Some("rtbox init timed out".to_string()),
+        })
+    }
+    pub async fn export(
         &self,
-        uid: i32,
-        gid: i32,
-        username: &'b str,
-        home: &'b str,
-        shell: &'b str
-    ) -> Option<RtBoxError> {
-        debug!("rtbox-init - gid: {:?}, home: {:?}, shell: {:?}", gid, home, shell);
-
-        let rtbox_init_state = RtBoxInitState {
-            uid: uid,
-            gid: gid,
-            home: home,
-            username: username,
-            shell: shell,
+        container: &str,
+        binary: Option<String>,
+        service_unit: Option<String>,
+        application: Option<String>,
+    ) -> Result<Vec<RtBoxExport>> {
+        debug!("rtbox-export - container: {:?}", container);
+
+        let rtbox_exe = std::env::current_exe().unwrap().display().to_string();
+        let export_error = |err: std::io::Error| RtBoxError {
+            command: None,
+            message: Some(format!("could not export from {}", container)),
+            root_cause: Some(err.to_string()),
         };
+        let mut exports = vec![];
+
+        if let Some(binary) = binary {
+            exports.push(export::export_binary(container, &binary, &rtbox_exe).map_err(export_error)?);
+        }
+        if let Some(application) = application {
+            let file_name = format!("{}.desktop", application.trim_end_matches(".desktop"));
+            let desktop_entry = self.find_file(container, &export::APPLICATION_DIRS, &file_name).await?;
+            exports.push(
+                export::export_application(container, &application, &desktop_entry, &rtbox_exe)
+                    .map_err(export_error)?
+            );
+        }
+        if let Some(service_unit) = service_unit {
+            let unit = self.find_file(container, &export::SERVICE_UNIT_DIRS, &service_unit).await?;
+            exports.push(
+                export::export_service_unit(container, &service_unit, &unit, &rtbox_exe)
+                    .map_err(export_error)?
+            );
+        }
+
+        Ok(exports)
+    }
+    /// Read the first `file_name` found in `dirs` inside the container
+    async fn find_file(&self, container: &str, dirs: &[&str], file_name: &str) -> Result<String> {
+        for dir in dirs {
+            let path = format!("{}/{}", dir, file_name);
+            if let Ok(contents) = self.container_engine.read_file(container.to_string(), path).await {
+                return Ok(String::from_utf8_lossy(&contents).to_string());
+            }
+        }
+
+        Err(RtBoxError {
+            command: None,
+            message: Some(format!("{} does not exist in {}", file_name, container)),
+            root_cause: Some(format!("searched {:?}", dirs)),
+        })
+    }
+    /// Create every box defined in a `distrobox-assemble` file, or only `only_name`
+    pub async fn assemble(&self, contents: &str, only_name: Option<&str>) -> Result<Vec<RtBox>> {
+        let definitions = distrobox::parse_assemble(contents)?;
+        let mut created = vec![];
+
+        for definition in definitions.iter().filter(|it| only_name.map(|name| name == it.name).unwrap_or(true)) {
+            let image = self.resolve_image(definition.image.clone(), None, None)?;
+            let rtbox = self.create(&definition.name, &image, &definition.create_opts()).await?;
+
+            if !definition.exported_apps.is_empty() || !definition.exported_bins.is_empty() {
+                self.container_engine.start(definition.name.clone()).await?;
+                self.wait_for_init(&definition.name).await?;
+
+                for application in &definition.exported_apps {
+                    self.export(&definition.name, None, None, Some(application.clone())).await?;
+                }
+                for binary in &definition.exported_bins {
+                    self.export(&definition.name, Some(binary.clone()), None, None).await?;
+                }
+            }
+
+            created.push(rtbox);
+        }
+
+        Ok(created)
+    }
+    async fn control(&self, name: &str, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let socket_path = control::host_control_socket(name);
+        if !socket_path.exists() {
+            return Err(RtBoxError {
+                command: None,
+                message: Some(format!("init of {} is not listening on {}", name, socket_path.display())),
+                root_cause: Some("the container is not running or was created without a control socket".to_string()),
+            });
+        }
+
+        control::call(&socket_path, method, params).await
+    }
+
+    fn control_result<R: serde::de::DeserializeOwned>(value: serde_json::Value) -> Result<R> {
+        serde_json::from_value(value).map_err(|err| RtBoxError {
+            command: None,
+            message: Some("unexpected answer from init".to_string()),
+            root_cause: Some(err.to_string()),
+        })
+    }
+
+    /// Whether `name` runs the image's systemd as PID 1, those have no control socket
+    async fn is_systemd_box(&self, name: &str) -> Result<bool> {
+        let container = self.container_engine.inspect(name.to_string()).await?;
+        Ok(container.kind() == RtBoxKind::Rtbox && container.init_mode() == RtBoxInitMode::Systemd)
+    }
+
+    /// Run `command` as root in the systemd box `name`, returns what it printed
+    async fn systemd_command(&self, name: &str, command: Vec<String>) -> Result<String> {
+        let exec_opts = RtBoxExecOpts {
+            user: Some("root".to_string()),
+            ..RtBoxExecOpts::default()
+        };
+        let output = self.container_engine.exec_output(name.to_string(), command.clone(), exec_opts).await?;
+        if output.return_code != 0 {
+            return Err(RtBoxError {
+                command: Some(command.join(" ")),
+                message: Some(format!("command failed in {} with {}", name, output.return_code)),
+                root_cause: Some(output.stderr.trim().to_string()),
+            });
+        }
+
+        Ok(output.stdout)
+    }
+
+    async fn systemd_service_status(&self, name: &str, service: &str) -> Result<RtBoxServiceStatus> {
+        let show = vec![
+            "systemctl".to_string(),
+            "show".to_string(),
+            format!("--property={}", services::SYSTEMD_STATUS_PROPERTIES),
+            "--".to_string(),
+            services::systemd_unit(service),
+        ];
+        let output = self.systemd_command(name, show).await?;
+
+        services::systemd_status(&output).ok_or(RtBoxError {
+            command: None,
+            message: Some(format!("unexpected status of {} from systemd", service)),
+            root_cause: Some(output),
+        })
+    }
+
+    pub async fn service_list(&self, name: &str) -> Result<Vec<RtBoxServiceStatus>> {
+        if self.is_systemd_box(name).await? {
+            let list_units = ["systemctl", "list-units", "--type=service", "--all", "--plain", "--no-legend", "--no-pager"];
+            let output = self.systemd_command(name, list_units.iter().map(|arg| arg.to_string()).collect()).await?;
+            return Ok(services::systemd_services(&output));
+        }
+
+        let services = self.control(name, "service.list", serde_json::json!({})).await?;
+        Self::control_result(services)
+    }
+
+    pub async fn service_start(&self, name: &str, service: &str) -> Result<RtBoxServiceStatus> {
+        if self.is_systemd_box(name).await? {
+            let start = ["systemctl", "start", "--"].iter().map(|arg| arg.to_string());
+            self.systemd_command(name, start.chain([services::systemd_unit(service)]).collect()).await?;
+            return self.systemd_service_status(name, service).await;
+        }
+
+        let status = self.control(name, "service.start", serde_json::json!({ "name": service })).await?;
+        Self::control_result(status)
+    }
+
+    pub async fn service_stop(&self, name: &str, service: &str) -> Result<RtBoxServiceStatus> {
+        if self.is_systemd_box(name).await? {
+            let stop = ["systemctl", "stop", "--"].iter().map(|arg| arg.to_string());
+            self.systemd_command(name, stop.chain([services::systemd_unit(service)]).collect()).await?;
+            return self.systemd_service_status(name, service).await;
+        }
+
+        let status = self.control(name, "service.stop", serde_json::json!({ "name": service })).await?;
+        Self::control_result(status)
+    }
+
+    pub async fn service_logs(&self, name: &str, service: &str, lines: usize) -> Result<String> {
+        if self.is_systemd_box(name).await? {
+            let journalctl = vec![
+                "journalctl".to_string(),
+                format!("--unit={}", services::systemd_unit(service)),
+                format!("--lines={}", lines),
+                "--output=cat".to_string(),
+                "--no-pager".to_string(),
+            ];
+            return self.systemd_command(name, journalctl).await;
+        }
+
+        let logs = self.control(name, "service.logs", serde_json::json!({ "name": service, "lines": lines })).await?;
+        Self::control_result(logs)
+    }
+
+    pub async fn volume_list(&self) -> Result<Vec<RtBoxVolumeInfo>> {
+        self.container_engine.list_volumes().await
+    }
+
+    /// Create the volume boxes declaring `name` get, the one of `owner` or the shared one
+    pub async fn volume_create(&self, name: &str, owner: Option<&str>) -> Result<RtBoxVolumeInfo> {
+        let volume_name = volume::volume_name(name, owner);
+        self.container_engine.create_volume(volume_name.clone(), volume::volume_labels(name, owner)).await?;
+
+        self.volume_list()
+            .await?
+            .into_iter()
+            .find(|volume| volume.volume == volume_name)
+            .ok_or(RtBoxError {
+                command: None,
+                message: Some(format!("volume {} was created but is not listed", volume_name)),
+                root_cause: Some("it may be missing the rtbox labels".to_string()),
+            })
+    }
+
+    pub async fn volume_rm(&self, name: &str, owner: Option<&str>, force: bool) -> Result<()> {
+        self.container_engine.remove_volume(volume::volume_name(name, owner), force).await
+    }
+
+    pub async fn init(&self, init_state: &RtBoxInitState<'_>) -> Option<RtBoxError> {
+        debug!("rtbox-init - gid: {:?}, home: {:?}, shell: {:?}", init_state.gid, init_state.home, init_state.shell);
 
         let rtbox_init: RtBoxInit = RtBoxInit::new();
 
-        rtbox_init.run(&rtbox_init_state);
-        None
+        rtbox_init.run(init_state).err().map(|message| RtBoxError {
+            command: None,
+            message: Some("container init failed".to_string()),
+            root_cause: Some(message),
+        })
     }
 }
-
@@ -0,0 +1,323 @@
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+/// Key added to exported desktop files and service units, shims carry it as a comment
+pub const EXPORT_MARKER: &str = "X-RtBox-Container";
+
+/// Where applications can be found inside a container
+pub const APPLICATION_DIRS: [&str; 2] = [
+    "/usr/share/applications",
+    "/usr/local/share/applications",
+];
+
+/// Where service units can be found inside a container
+pub const SERVICE_UNIT_DIRS: [&str; 3] = [
+    "/etc/systemd/system",
+    "/usr/lib/systemd/system",
+    "/usr/lib/systemd/user",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtBoxExportKind {
+    Binary,
+    Application,
+    ServiceUnit,
+}
+
+/// A file on the host that forwards to something inside a rtbox container
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxExport {
+    pub container: String,
+    pub kind: RtBoxExportKind,
+    pub name: String,
+    pub path: String,
+}
+
+fn home_dir() -> PathBuf {
+    PathBuf::from(std::env::var("HOME").unwrap_or("/".to_string()))
+}
+
+fn xdg_dir(variable: &str, fallback: &str) -> PathBuf {
+    std::env::var(variable)
+        .map(PathBuf::from)
+        .unwrap_or(home_dir().join(fallback))
+}
+
+pub fn binary_dir() -> PathBuf {
+    home_dir().join(".local/bin")
+}
+
+pub fn application_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share").join("applications")
+}
+
+pub fn service_unit_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("systemd/user")
+}
+
+fn run_command(rtbox_exe: &str, container: &str) -> String {
+    format!("{} run --container {}", rtbox_exe, container)
+}
+
+fn write_export(path: &Path, contents: &str, mode: u32) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+/// Name of the container a host file was exported from, if it is one of ours
+pub fn exported_from(path: &Path) -> Option<String> {
+    /* the marker is always near the top, no need to read whole binaries */
+    let mut contents = String::new();
+    fs::File::open(path).ok()?
+        .take(4096)
+        .read_to_string(&mut contents)
+        .ok()?;
+
+    contents.lines()
+        .map(|line| line.trim_start_matches("# ").trim())
+        .find_map(|line| line.strip_prefix(EXPORT_MARKER)?.strip_prefix('='))
+        .map(|container| container.to_string())
+}
+
+/// Find every file exported by rtbox on this host, optionally only the ones for `container`
+pub fn list_exports(container: Option<&str>) -> Vec<RtBoxExport> {
+    let export_dirs = [
+        (RtBoxExportKind::Binary, binary_dir()),
+        (RtBoxExportKind::Application, application_dir()),
+        (RtBoxExportKind::ServiceUnit, service_unit_dir()),
+    ];
+
+    let mut exports = vec![];
+    for (kind, export_dir) in export_dirs {
+        let entries = match fs::read_dir(&export_dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+
+            /* desktop files and units are always prefixed, avoid reading unrelated files */
+            if kind != RtBoxExportKind::Binary && !file_name.starts_with("rtbox-") {
+                continue;
+            }
+
+            let exported_container = match exported_from(&path) {
+                Some(exported_container) => exported_container,
+                None => continue,
+            };
+            if container.map(|it| it != exported_container).unwrap_or(false) {
+                continue;
+            }
+
+            let prefix = format!("rtbox-{}-", exported_container);
+            let name = match kind {
+                RtBoxExportKind::Binary => file_name,
+                RtBoxExportKind::Application => file_name
+                    .trim_start_matches(&prefix)
+                    .trim_end_matches(".desktop")
+                    .to_string(),
+                RtBoxExportKind::ServiceUnit => file_name.trim_start_matches(&prefix).to_string(),
+            };
+
+            exports.push(RtBoxExport {
+                container: exported_container,
+                kind,
+                name,
+                path: path.display().to_string(),
+            });
+        }
+    }
+
+    exports
+}
+
+/// Write a shell shim on the host that runs `binary` inside `container`
+pub fn export_binary(container: &str, binary: &str, rtbox_exe: &str) -> io::Result<RtBoxExport> {
+    let name = Path::new(binary)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "binary has no file name"))?;
+    let path = binary_dir().join(&name);
+
+    let shim = format!(
+        "#!/bin/sh\n# {}={}\nexec {} {} \"$@\"\n",
+        EXPORT_MARKER,
+        container,
+        run_command(rtbox_exe, container),
+        binary,
+    );
+    write_export(&path, &shim, 0o755)?;
+
+    Ok(RtBoxExport {
+        container: container.to_string(),
+        kind: RtBoxExportKind::Binary,
+        name,
+        path: path.display().to_string(),
+    })
+}
+
+/// Rewrite a desktop entry so it launches inside `container`
+pub fn rewrite_desktop_entry(container: &str, desktop_entry: &str, rtbox_exe: &str) -> String {
+    let mut rewritten_lines = vec![];
+    let mut in_desktop_entry = false;
+
+    for line in desktop_entry.lines() {
+        if line.starts_with('[') {
+            in_desktop_entry = line.trim() == "[Desktop Entry]";
+            rewritten_lines.push(line.to_string());
+            if in_desktop_entry {
+                rewritten_lines.push(format!("{}={}", EXPORT_MARKER, container));
+            }
+        } else if let Some(command) = line.strip_prefix("Exec=") {
+            rewritten_lines.push(format!("Exec={} {}", run_command(rtbox_exe, container), command));
+        } else if line.starts_with("TryExec=") || line.starts_with(EXPORT_MARKER) {
+            continue;
+        } else if let Some(name) = line.strip_prefix("Name=").filter(|_| in_desktop_entry) {
+            rewritten_lines.push(format!("Name={} ({})", name, container));
+        } else {
+            rewritten_lines.push(line.to_string());
+        }
+    }
+
+    rewritten_lines.join("\n") + "\n"
+}
+
+pub fn export_application(
+    container: &str,
+    application: &str,
+    desktop_entry: &str,
+    rtbox_exe: &str,
+) -> io::Result<RtBoxExport> {
+    let name = application.trim_end_matches(".desktop").to_string();
+    let path = application_dir().join(format!("rtbox-{}-{}.desktop", container, name));
+
+    write_export(&path, &rewrite_desktop_entry(container, desktop_entry, rtbox_exe), 0o644)?;
+
+    Ok(RtBoxExport {
+        container: container.to_string(),
+        kind: RtBoxExportKind::Application,
+        name,
+        path: path.display().to_string(),
+    })
+}
+
+/// Rewrite a system unit into a user unit whose commands run inside `container`
+pub fn rewrite_service_unit(container: &str, unit: &str, rtbox_exe: &str) -> String {
+    let mut rewritten_lines = vec![];
+    let mut has_unit_section = false;
+
+    for line in unit.lines() {
+        let trimmed_line = line.trim();
+
+        if trimmed_line == "[Unit]" {
+            has_unit_section = true;
+            rewritten_lines.push(line.to_string());
+            rewritten_lines.push(format!("{}={}", EXPORT_MARKER, container));
+        } else if trimmed_line.starts_with(EXPORT_MARKER)
+            || trimmed_line.starts_with("User=")
+            || trimmed_line.starts_with("Group=") {
+            continue;
+        } else if trimmed_line.starts_with("Exec") && trimmed_line.contains('=') {
+            let (key, command) = trimmed_line.split_once('=').unwrap();
+            /* keep systemd's special executable prefixes in front of our command */
+            let prefix_length = command.len() - command.trim_start_matches(['-', '@', ':', '+', '!']).len();
+            let (prefix, command) = command.split_at(prefix_length);
+            rewritten_lines.push(format!("{}={}{} {}", key, prefix, run_command(rtbox_exe, container), command));
+        } else if trimmed_line == "WantedBy=multi-user.target" {
+            rewritten_lines.push("WantedBy=default.target".to_string());
+        } else {
+            rewritten_lines.push(line.to_string());
+        }
+    }
+
+    if !has_unit_section {
+        rewritten_lines.insert(0, format!("{}={}", EXPORT_MARKER, container));
+        rewritten_lines.insert(0, "[Unit]".to_string());
+    }
+
+    rewritten_lines.join("\n") + "\n"
+}
+
+pub fn export_service_unit(
+    container: &str,
+    service_unit: &str,
+    unit: &str,
+    rtbox_exe: &str,
+) -> io::Result<RtBoxExport> {
+    let name = service_unit.to_string();
+    let path = service_unit_dir().join(format!("rtbox-{}-{}", container, name));
+
+    write_export(&path, &rewrite_service_unit(container, unit, rtbox_exe), 0o644)?;
+
+    Ok(RtBoxExport {
+        container: container.to_string(),
+        kind: RtBoxExportKind::ServiceUnit,
+        name,
+        path: path.display().to_string(),
+    })
+}
+
+/// Point the contents of an export of `container` at `new_container` instead
+pub fn rename_export_contents(contents: &str, container: &str, new_container: &str) -> String {
+    let marker = format!("{}={}", EXPORT_MARKER, container);
+    let run_prefix = format!(" run --container {} ", container);
+    let name_suffix = format!(" ({})", container);
+
+    let renamed_lines: Vec<String> = contents.lines()
+        .map(|line| {
+            if line.trim_start_matches("# ") == marker {
+                line.replace(&marker, &format!("{}={}", EXPORT_MARKER, new_container))
+            } else if line.starts_with("Name=") && line.ends_with(&name_suffix) {
+                format!("{} ({})", line.trim_end_matches(&name_suffix), new_container)
+            } else {
+                line.replace(&run_prefix, &format!(" run --container {} ", new_container))
+            }
+        })
+        .collect();
+
+    renamed_lines.join("\n") + "\n"
+}
+
+/// Move an export over to `new_container`, renaming the file when its name carries the container
+pub fn rename_export(export: &RtBoxExport, new_container: &str) -> io::Result<RtBoxExport> {
+    let path = PathBuf::from(&export.path);
+    let contents = fs::read_to_string(&path)?;
+    let mode = fs::metadata(&path)?.permissions().mode();
+
+    let new_path = match export.kind {
+        RtBoxExportKind::Binary => path.clone(),
+        RtBoxExportKind::Application => application_dir().join(format!("rtbox-{}-{}.desktop", new_container, export.name)),
+        RtBoxExportKind::ServiceUnit => service_unit_dir().join(format!("rtbox-{}-{}", new_container, export.name)),
+    };
+
+    write_export(&new_path, &rename_export_contents(&contents, &export.container, new_container), mode)?;
+    if new_path != path {
+        fs::remove_file(&path)?;
+    }
+
+    /* keep enabled units enabled under their new name */
+    if export.kind == RtBoxExportKind::ServiceUnit {
+        let wants_dir = service_unit_dir().join("default.target.wants");
+        let old_link = wants_dir.join(path.file_name().unwrap());
+        if fs::symlink_metadata(&old_link).is_ok() {
+            fs::remove_file(&old_link)?;
+            std::os::unix::fs::symlink(&new_path, wants_dir.join(new_path.file_name().unwrap()))?;
+        }
+    }
+
+    Ok(RtBoxExport {
+        container: new_container.to_string(),
+        kind: export.kind,
+        name: export.name.clone(),
+        path: new_path.display().to_string(),
+    })
+}
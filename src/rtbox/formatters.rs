@@ -1,9 +1,5 @@
-use std::env;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
-
-use crate::rtbox::cli::{TboxCli, TboxCliOutputFormat, TboxCommands, Output};
+use crate::rtbox::cli::Output;
+use crate::rtbox::engine::RtBoxExecOutput;
 
 pub trait OutputFormatter {
     fn format(&self, command_output: &Output) -> Option<String>;
@@ -22,6 +18,74 @@ impl OutputFormatter for HumanFormatter {
 
                 Some(formatted_output)
             },
+            Output::Adopt(rtbox) => {
+                let formatted_output = format!("\
+                    Successfully adopted '{}' using image {}. \
+                    To enter please run:\n\
+                    > rtbox enter {}", rtbox.name, rtbox.image, rtbox.name);
+
+                Some(formatted_output)
+            },
+            Output::Clone(rtbox) => {
+                let formatted_output = format!("\
+                    Successfully cloned '{}' using image {}. \
+                    To enter please run:\n\
+                    > rtbox enter {}", rtbox.name, rtbox.image, rtbox.name);
+
+                Some(formatted_output)
+            },
+            Output::Load(rtbox) => {
+                let formatted_output = format!("\
+                    Successfully loaded '{}' using image {}. \
+                    To enter please run:\n\
+                    > rtbox enter {}", rtbox.name, rtbox.image, rtbox.name);
+
+                Some(formatted_output)
+            },
+            Output::Rename(rtbox) => {
+                let formatted_output = format!("\
+                    Successfully renamed to '{}'. \
+                    To enter please run:\n\
+                    > rtbox enter {}", rtbox.name, rtbox.name);
+
+                Some(formatted_output)
+            },
+            Output::Save(manifest) => {
+                let formatted_output = format!(
+                    "Successfully saved '{}' with {} exports, load it with:\n> rtbox load <archive>",
+                    manifest.name,
+                    manifest.exports.len(),
+                );
+
+                Some(formatted_output)
+            },
+            Output::Commit(image_info) => {
+                let image = image_info.names.first().unwrap_or(&image_info.id);
+                let formatted_output = format!("\
+                    Successfully committed image {}. \
+                    To create a container from it please run:\n\
+                    > rtbox create --image {} <name>", image, image);
+
+                Some(formatted_output)
+            },
+            Output::Assemble(rtbox_list) => {
+                let formatted_output = rtbox_list
+                    .iter()
+                    .map(|rtbox| format!("Successfully created '{}' using image {}.", rtbox.name, rtbox.image))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
+                Some(formatted_output)
+            },
+            Output::Export(exports) => {
+                let formatted_output = exports
+                    .iter()
+                    .map(|export| format!("Exported {:?} '{}' from '{}' to {}", export.kind, export.name, export.container, export.path))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
+                Some(formatted_output)
+            },
             Output::List(rtbox_list) => {
                 let formatted_list = rtbox_list
                     .iter()
@@ -35,6 +99,181 @@ impl OutputFormatter for HumanFormatter {
 
                 Some(formatted_output)
             },
+            Output::Upgrade(upgrade) => {
+                let digest = |digest: &Option<String>| digest.clone().unwrap_or("-".to_string());
+                let formatted_output = match upgrade.upgraded {
+                    true => format!(
+                        "Successfully upgraded '{}' to {} ({} -> {}).{}",
+                        upgrade.name,
+                        upgrade.image,
+                        digest(&upgrade.old_digest),
+                        digest(&upgrade.new_digest),
+                        match upgrade.preserved_paths.is_empty() {
+                            true => "".to_string(),
+                            false => format!(" Preserved: {}", upgrade.preserved_paths.join(", ")),
+                        },
+                    ),
+                    false => format!("'{}' already uses the latest {}.", upgrade.name, upgrade.image),
+                };
+
+                Some(formatted_output)
+            },
+            Output::Prune(report) => {
+                let action = match report.dry_run {
+                    true => "Would remove",
+                    false => "Removed",
+                };
+                let mut lines: Vec<String> = report.boxes
+                    .iter()
+                    .map(|pruned| format!("{} container {} ({})", action, pruned.name, pruned.reason))
+                    .collect();
+                lines.extend(report.images.iter().map(|image| format!("{} image {}", action, image.id)));
+                lines.extend(report.exports.iter().map(|orphan| format!("{} export {}", action, orphan.path)));
+                lines.push(format!(
+                    "{} {} containers, {} images and {} exports.",
+                    action,
+                    report.boxes.len(),
+                    report.images.len(),
+                    report.exports.len(),
+                ));
+
+                Some(lines.join("\n"))
+            },
+            Output::Service(services) => {
+                let formatted_list = services
+                    .iter()
+                    .map(|service| format!(
+                        "{:<20} | {:<8} | {:<8} | {}",
+                        service.name,
+                        format!("{:?}", service.state).to_lowercase(),
+                        service.pid.map(|pid| pid.to_string()).unwrap_or("-".to_string()),
+                        service.restarts,
+                    ))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
+                let header = format!("{:<20} | {:<8} | {:<8} | {}\n", "NAME", "STATE", "PID", "RESTARTS");
+
+                Some(header + &formatted_list)
+            },
+            Output::Volume(volumes) => {
+                let formatted_list = volumes
+                    .iter()
+                    .map(|volume| format!(
+                        "{:<20} | {:<20} | {:<32} | {}",
+                        volume.name,
+                        volume.owner.clone().unwrap_or("(shared)".to_string()),
+                        volume.volume,
+                        volume.mountpoint,
+                    ))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
+                let header = format!("{:<20} | {:<20} | {:<32} | {}\n", "NAME", "CONTAINER", "VOLUME", "MOUNTPOINT");
+
+                Some(header + &formatted_list)
+            },
+            Output::ServiceLogs(logs) => Some(logs.trim_end().to_string()),
+            Output::Inspect(inspect) => {
+                let list = |items: Vec<String>| items.iter()
+                    .map(|item| format!("\n  {}", item))
+                    .collect::<String>();
+
+                let readiness = match &inspect.readiness {
+                    Some(readiness) => format!(
+                        "{}{}",
+                        format!("{:?}", readiness.status).to_lowercase(),
+                        readiness.message.as_ref().map(|message| format!(" ({})", message)).unwrap_or_default(),
+                    ),
+                    None => "-".to_string(),
+                };
+                let creation = inspect.creation
+                    .iter()
+                    .flat_map(|(option, values)| match values.is_empty() {
+                        true => vec![format!("--{}", option)],
+                        false => values.iter().map(|value| format!("--{} {}", option, value)).collect(),
+                    })
+                    .collect();
+                let mounts = inspect.mounts
+                    .iter()
+                    .map(|mount| format!("{} -> {} ({})", mount.source, mount.destination, mount.options.join(",")))
+                    .collect();
+                let labels = inspect.labels
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+                let security = vec![
+                    format!("selinux: {}", if inspect.security.selinux_disabled { "disabled" } else { "enabled" }),
+                    format!("drop capabilities: {}", inspect.security.drop_capabilities.join(",")),
+                    format!("add capabilities: {}", inspect.security.add_capabilities.join(",")),
+                    format!("seccomp profile: {}", inspect.security.seccomp_profile.as_deref().unwrap_or("default")),
+                ];
+                let recorded = inspect.recorded
+                    .iter()
+                    .flat_map(|recorded| vec![
+                        format!("rtbox version: {}", recorded.version),
+                        format!("image: {}", recorded.image),
+                        format!("home: {}", recorded.home.as_deref().unwrap_or("host")),
+                        format!("profile: {}", recorded.profile.name()),
+                        format!("env: {}", recorded.env.join(",")),
+                        format!("hooks hash: {}", recorded.hooks_hash),
+                    ])
+                    .collect();
+                let exports = inspect.exports
+                    .iter()
+                    .map(|export| format!("{:?} '{}' at {}", export.kind, export.name, export.path))
+                    .collect();
+
+                let formatted_output = format!("\
+                    Name:       {}\n\
+                    ID:         {}\n\
+                    Kind:       {:?}\n\
+                    Status:     {}\n\
+                    Created:    {}\n\
+                    Image:      {}\n\
+                    Digest:     {}\n\
+                    Readiness:  {}\n\
+                    Entrypoint: {}\n\
+                    Creation:{}\n\
+                    Mounts:{}\n\
+                    Env:{}\n\
+                    Labels:{}\n\
+                    Security:{}\n\
+                    Recorded:{}\n\
+                    Exports:{}",
+                    inspect.name,
+                    inspect.id,
+                    inspect.kind,
+                    inspect.status,
+                    inspect.created,
+                    inspect.image,
+                    inspect.image_digest,
+                    readiness,
+                    inspect.entrypoint.join(" "),
+                    list(creation),
+                    list(mounts),
+                    list(inspect.env.clone()),
+                    list(labels),
+                    list(security),
+                    list(recorded),
+                    list(exports),
+                );
+
+                Some(formatted_output)
+            },
+            Output::InspectField(value) => {
+                let format_value = |value: &serde_json::Value| match value {
+                    serde_json::Value::String(value) => value.clone(),
+                    serde_json::Value::Null => String::new(),
+                    value => serde_json::to_string_pretty(value).unwrap(),
+                };
+
+                match value {
+                    serde_json::Value::Array(values) => Some(values.iter().map(format_value).collect::<Vec<String>>().join("\n")),
+                    value => Some(format_value(value)),
+                }
+            },
+            Output::Run(RtBoxExecOutput { exec_id: Some(exec_id), .. }) => Some(exec_id.clone()),
             Output::Error(error) => Some(format!("{:?}", error)),
             _ => None,
         }
@@ -0,0 +1,281 @@
+use std::fs::{self, File};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+/// Where each hook writes its output inside the container
+pub const HOOK_LOG_DIR: &str = "/var/log/rtbox/hooks";
+
+/// Bootstrap script of the image, run before any other hook like a classic init would
+pub const RC_SCRIPT: &str = "/etc/rc";
+
+/// Hooks taking longer than this are killed unless the box sets its own timeout
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 300;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RtBoxHookPhase {
+    PreInit,
+    Init,
+}
+
+impl RtBoxHookPhase {
+    pub fn name(&self) -> &str {
+        match self {
+            RtBoxHookPhase::PreInit => "pre-init",
+            RtBoxHookPhase::Init => "init",
+        }
+    }
+
+    /// Directory holding the scripts for this phase below an `rtbox` config dir
+    pub fn dir_name(&self) -> String {
+        format!("{}.d", self.name())
+    }
+}
+
+/// Hooks run in this order within a phase
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RtBoxHookSource {
+    Image,
+    Box,
+    User,
+}
+
+impl RtBoxHookSource {
+    pub fn name(&self) -> &str {
+        match self {
+            RtBoxHookSource::Image => "image",
+            RtBoxHookSource::Box => "box",
+            RtBoxHookSource::User => "user",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RtBoxHookFailurePolicy {
+    /// Stop initializing and mark the container as failed
+    Abort,
+    /// Record the failure and keep running the remaining hooks
+    Continue,
+}
+
+impl RtBoxHookFailurePolicy {
+    pub fn name(&self) -> &str {
+        match self {
+            RtBoxHookFailurePolicy::Abort => "abort",
+            RtBoxHookFailurePolicy::Continue => "continue",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtBoxHook {
+    pub phase: RtBoxHookPhase,
+    pub source: RtBoxHookSource,
+    pub name: String,
+    pub command: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RtBoxHookStatus {
+    Succeeded,
+    Failed,
+    TimedOut,
+}
+
+/// Reported through the readiness file so the host can tell which hook broke
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxHookResult {
+    pub phase: RtBoxHookPhase,
+    pub source: RtBoxHookSource,
+    pub name: String,
+    pub status: RtBoxHookStatus,
+    pub message: Option<String>,
+    pub log_path: Option<String>,
+}
+
+/// Executable files in `dir` sorted by name, the way run-parts picks them
+pub fn script_hooks(phase: RtBoxHookPhase, source: RtBoxHookSource, dir: &Path) -> Vec<RtBoxHook> {
+    let mut scripts: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    scripts.sort();
+
+    scripts.into_iter()
+        .filter(|path| {
+            let is_executable = fs::metadata(path)
+                .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false);
+            if !is_executable {
+                debug!("skipping {} hook {}, it is not an executable file", phase.name(), path.display());
+            }
+            is_executable
+        })
+        .map(|path| RtBoxHook {
+            phase,
+            source,
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            command: vec![path.display().to_string()],
+        })
+        .collect()
+}
+
+/// The image's rc script, run through sh when it is not executable
+pub fn rc_hook(path: &Path) -> Option<RtBoxHook> {
+    let metadata = fs::metadata(path).ok().filter(|metadata| metadata.is_file())?;
+    let mut command = vec![path.display().to_string()];
+    if metadata.permissions().mode() & 0o111 == 0 {
+        command.insert(0, "/bin/sh".to_string());
+    }
+
+    Some(RtBoxHook {
+        phase: RtBoxHookPhase::PreInit,
+        source: RtBoxHookSource::Image,
+        name: "rc".to_string(),
+        command,
+    })
+}
+
+/// Shell snippets given to create with `--pre-init-hook` and `--init-hook`
+pub fn box_hooks(phase: RtBoxHookPhase, hooks: &[String]) -> Vec<RtBoxHook> {
+    hooks.iter()
+        .enumerate()
+        .map(|(index, hook)| RtBoxHook {
+            phase,
+            source: RtBoxHookSource::Box,
+            name: format!("{}-hook-{}", phase.name(), index + 1),
+            command: vec!["/bin/sh".to_string(), "-c".to_string(), hook.clone()],
+        })
+        .collect()
+}
+
+/// All hooks for `phase`: the image's `/etc/rc` and `/etc/rtbox`, then the box, then the user's `~/.config/rtbox`
+pub fn collect(phase: RtBoxHookPhase, hooks: &[String], home: &str) -> Vec<RtBoxHook> {
+    let mut collected: Vec<RtBoxHook> = match phase {
+        RtBoxHookPhase::PreInit => rc_hook(Path::new(RC_SCRIPT)).into_iter().collect(),
+        RtBoxHookPhase::Init => vec![],
+    };
+    collected.extend(script_hooks(phase, RtBoxHookSource::Image, &Path::new("/etc/rtbox").join(phase.dir_name())));
+    collected.extend(box_hooks(phase, hooks));
+    collected.extend(script_hooks(
+        phase,
+        RtBoxHookSource::User,
+        &Path::new(home).join(".config/rtbox").join(phase.dir_name()),
+    ));
+    collected
+}
+
+pub struct RtBoxHookRunner {
+    pub timeout: Duration,
+    pub failure_policy: RtBoxHookFailurePolicy,
+    pub log_dir: PathBuf,
+}
+
+impl RtBoxHookRunner {
+    pub fn new(timeout: Duration, failure_policy: RtBoxHookFailurePolicy) -> Self {
+        Self {
+            timeout,
+            failure_policy,
+            log_dir: PathBuf::from(HOOK_LOG_DIR),
+        }
+    }
+
+    /// Run a single hook with its output in its own log file, killing it once it exceeds the timeout
+    pub fn run(&self, hook: &RtBoxHook) -> RtBoxHookResult {
+        let result = |status: RtBoxHookStatus, message: Option<String>, log_path: Option<&Path>| RtBoxHookResult {
+            phase: hook.phase,
+            source: hook.source,
+            name: hook.name.clone(),
+            status,
+            message,
+            log_path: log_path.map(|path| path.display().to_string()),
+        };
+
+        /* image and user scripts may share a name */
+        let log_path = self.log_dir.join(format!("{}-{}-{}.log", hook.phase.name(), hook.source.name(), hook.name));
+        let log_file = fs::create_dir_all(&self.log_dir)
+            .and_then(|_| File::create(&log_path))
+            .and_then(|file| Ok((file.try_clone()?, file)));
+        let (stdout, stderr, log_path) = match log_file {
+            Ok((stdout, stderr)) => (Stdio::from(stdout), Stdio::from(stderr), Some(log_path.as_path())),
+            Err(err) => {
+                warn!("could not create log {} for {} hook {}: {}", log_path.display(), hook.phase.name(), hook.name, err);
+                (Stdio::inherit(), Stdio::inherit(), None)
+            },
+        };
+
+        /* own process group so a timeout takes down everything the hook started */
+        let child = Command::new(&hook.command[0])
+            .args(&hook.command[1..])
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .process_group(0)
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => return result(
+                RtBoxHookStatus::Failed,
+                Some(format!("{} hook {} could not be started: {}", hook.phase.name(), hook.name, err)),
+                log_path,
+            ),
+        };
+
+        let start_time = Instant::now();
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => return result(RtBoxHookStatus::Succeeded, None, log_path),
+                Ok(Some(status)) => return result(
+                    RtBoxHookStatus::Failed,
+                    Some(format!("{} hook {} failed with {}", hook.phase.name(), hook.name, status)),
+                    log_path,
+                ),
+                Ok(None) if start_time.elapsed() >= self.timeout => {
+                    unsafe {
+                        libc::kill(-(child.id() as i32), libc::SIGKILL);
+                    }
+                    let _ = child.wait();
+                    return result(
+                        RtBoxHookStatus::TimedOut,
+                        Some(format!("{} hook {} timed out after {}s", hook.phase.name(), hook.name, self.timeout.as_secs())),
+                        log_path,
+                    );
+                },
+                Ok(None) => sleep(Duration::from_millis(100)),
+                Err(err) => return result(
+                    RtBoxHookStatus::Failed,
+                    Some(format!("could not wait for {} hook {}: {}", hook.phase.name(), hook.name, err)),
+                    log_path,
+                ),
+            }
+        }
+    }
+
+    /// Run `hooks` in order adding their results to `results`, only errors when the policy is to abort
+    pub fn run_all(&self, hooks: &[RtBoxHook], results: &mut Vec<RtBoxHookResult>) -> Result<(), String> {
+        for (index, hook) in hooks.iter().enumerate() {
+            info!("running {} hook {}/{}: {}", hook.phase.name(), index + 1, hooks.len(), hook.name);
+
+            let hook_result = self.run(hook);
+            let message = hook_result.message.clone();
+            results.push(hook_result);
+
+            match (message, self.failure_policy) {
+                (None, _) => {},
+                (Some(message), RtBoxHookFailurePolicy::Abort) => return Err(message),
+                (Some(message), RtBoxHookFailurePolicy::Continue) => warn!("{}, continuing", message),
+            }
+        }
+
+        Ok(())
+    }
+}
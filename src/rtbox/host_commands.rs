@@ -0,0 +1,176 @@
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+/// Marks files written by init so we never overwrite anything else
+pub const SHIM_MARKER: &str = "# X-RtBox-Host-Command";
+
+/// Installed into the box's profile.d so shells forward unknown commands
+pub const COMMAND_NOT_FOUND_SCRIPT: &str = "rtbox-command-not-found.sh";
+
+/// Where a shim is not needed because the image ships the command itself
+const SYSTEM_BIN_DIRS: [&str; 4] = [
+    "/usr/bin",
+    "/bin",
+    "/usr/sbin",
+    "/sbin",
+];
+
+/// Commands that run whatever they are given, `*` does not cover them since
+/// `env sudo` or `sh -c sudo` would get past `deny`, they have to be allowed by name
+const WRAPPER_COMMANDS: [&str; 27] = [
+    "bash",
+    "busybox",
+    "chroot",
+    "dash",
+    "doas",
+    "env",
+    "fish",
+    "flatpak-spawn",
+    "flock",
+    "ionice",
+    "ksh",
+    "nice",
+    "nohup",
+    "nsenter",
+    "pkexec",
+    "runuser",
+    "setsid",
+    "sh",
+    "stdbuf",
+    "su",
+    "sudo",
+    "systemd-run",
+    "taskset",
+    "timeout",
+    "unshare",
+    "xargs",
+    "zsh",
+];
+
+/// Which host commands boxes may run, checked by the host helper for every request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RtBoxHostCommandsConfig {
+    /// Commands that get a shim on the box's PATH forwarding to the host
+    pub shims: Vec<String>,
+    /// Commands boxes may run on the host, `*` allows all of them except shells and other wrappers
+    pub allow: Vec<String>,
+    /// Commands never forwarded, wins over `allow` and `shims`
+    pub deny: Vec<String>,
+    /// Install the command_not_found hook into new boxes
+    pub command_not_found: bool,
+}
+
+impl Default for RtBoxHostCommandsConfig {
+    fn default() -> Self {
+        Self {
+            shims: vec![
+                "flatpak".to_string(),
+                "podman".to_string(),
+                "xdg-open".to_string(),
+            ],
+            allow: vec!["*".to_string()],
+            deny: vec![
+                "pkexec".to_string(),
+                "su".to_string(),
+                "sudo".to_string(),
+            ],
+            command_not_found: true,
+        }
+    }
+}
+
+impl RtBoxHostCommandsConfig {
+    /// Whether `command` may run on the host, only its file name is considered
+    pub fn is_allowed(&self, command: &str) -> bool {
+        let name = Path::new(command)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let listed = |list: &[String]| list.iter().any(|it| it == &name);
+        let wildcard = self.allow.iter().any(|it| it == "*") && !WRAPPER_COMMANDS.contains(&name.as_str());
+
+        !name.is_empty()
+            && !listed(&self.deny)
+            && (wildcard || listed(&self.allow) || listed(&self.shims))
+    }
+}
+
+/// Shell functions bash and zsh call for unknown commands, forwarding them to the host helper
+pub fn command_not_found_script(rtbox_exe: &str, host_socket: &str) -> String {
+    format!("\
+        {marker}\n\
+        command_not_found_handle() {{\n\
+        \x20   if [ -S {socket} ] && [ -z \"$RTBOX_NO_HOST_FALLBACK\" ]; then\n\
+        \x20       {rtbox} host-exec --forwarded -- \"$@\"\n\
+        \x20       return $?\n\
+        \x20   fi\n\
+        \x20   printf '%s: command not found\\n' \"$1\" >&2\n\
+        \x20   return 127\n\
+        }}\n\
+        command_not_found_handler() {{\n\
+        \x20   command_not_found_handle \"$@\"\n\
+        }}\n",
+        marker = SHIM_MARKER,
+        socket = host_socket,
+        rtbox = rtbox_exe,
+    )
+}
+
+pub fn shim_script(rtbox_exe: &str, command: &str) -> String {
+    format!("#!/bin/sh\n{}\nexec {} host-exec --forwarded -- {} \"$@\"\n", SHIM_MARKER, rtbox_exe, command)
+}
+
+/// Write `contents` unless `path` exists and was not written by us
+fn write_owned(path: &Path, contents: &str, mode: u32) -> io::Result<bool> {
+    if let Ok(existing) = fs::read_to_string(path) {
+        if !existing.contains(SHIM_MARKER) {
+            return Ok(false);
+        }
+    } else if fs::symlink_metadata(path).is_ok() {
+        return Ok(false);
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(true)
+}
+
+/// Install a shim in `bin_dir` for each of `shims` the image does not ship, returns the installed ones
+pub fn install_shims(bin_dir: &Path, system_root: &Path, rtbox_exe: &str, shims: &[String]) -> Vec<String> {
+    shims.iter()
+        .filter(|command| {
+            let shipped = SYSTEM_BIN_DIRS.iter()
+                .any(|dir| system_root.join(dir.trim_start_matches('/')).join(command).exists());
+            if shipped {
+                debug!("not shimming {}, the image ships it", command);
+            }
+            !shipped
+        })
+        .filter(|command| {
+            match write_owned(&bin_dir.join(command), &shim_script(rtbox_exe, command), 0o755) {
+                Ok(written) => written,
+                Err(err) => {
+                    warn!("could not install host command shim for {}: {}", command, err);
+                    false
+                },
+            }
+        })
+        .cloned()
+        .collect()
+}
+
+pub fn install_command_not_found(profile_dir: &Path, rtbox_exe: &str, host_socket: &str) -> io::Result<bool> {
+    write_owned(
+        &profile_dir.join(COMMAND_NOT_FOUND_SCRIPT),
+        &command_not_found_script(rtbox_exe, host_socket),
+        0o644,
+    )
+}
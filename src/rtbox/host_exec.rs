@@ -0,0 +1,393 @@
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Serialize, Deserialize};
+
+use crate::rtbox::control;
+use crate::rtbox::host_commands::RtBoxHostCommandsConfig;
+use crate::rtbox::tty::{self, RawTerminal};
+
+/// Socket of the host helper, it lives next to the control socket so boxes see it under `CONTROL_DIR`
+pub const HOST_EXEC_SOCKET: &str = "host.sock";
+
+/// Variables from inside the box that the host command gets as well
+const FORWARDED_ENV: [&str; 4] = [
+    "TERM",
+    "COLORTERM",
+    "LANG",
+    "LC_ALL",
+];
+
+/// Largest frame either side accepts, requests are small and output is sent in 4 KiB pieces
+const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/* every frame is a kind byte and a big endian length followed by the data */
+const FRAME_REQUEST: u8 = 0;
+const FRAME_STDIN: u8 = 1;
+const FRAME_STDIN_CLOSED: u8 = 2;
+const FRAME_STDOUT: u8 = 3;
+const FRAME_STDERR: u8 = 4;
+const FRAME_RESIZE: u8 = 5;
+const FRAME_EXIT: u8 = 6;
+
+/// Sent by `rtbox host-exec` inside a box to ask the host to run a command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxHostExecRequest {
+    pub command: Vec<String>,
+    pub cwd: String,
+    pub env: Vec<(String, String)>,
+    pub tty: bool,
+    /// Terminal size as (width, height)
+    pub size: Option<(usize, usize)>,
+}
+
+pub fn host_socket(container: &str) -> PathBuf {
+    control::host_control_dir(container).join(HOST_EXEC_SOCKET)
+}
+
+pub fn container_socket() -> PathBuf {
+    Path::new(control::CONTROL_DIR).join(HOST_EXEC_SOCKET)
+}
+
+fn write_frame(writer: &mut impl Write, kind: u8, data: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(data.len() + 5);
+    frame.push(kind);
+    frame.extend((data.len() as u32).to_be_bytes());
+    frame.extend(data);
+    writer.write_all(&frame)
+}
+
+/// Returns None once the other side closed the connection
+fn read_frame(reader: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {},
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes is too large", length)));
+    }
+    let mut data = vec![0u8; length];
+    reader.read_exact(&mut data)?;
+    Ok(Some((header[0], data)))
+}
+
+fn send_frame(writer: &Mutex<UnixStream>, kind: u8, data: &[u8]) -> io::Result<()> {
+    write_frame(&mut *writer.lock().unwrap(), kind, data)
+}
+
+/// Where a path inside the box is on the host, falling back to the host home when it does not exist there
+pub fn host_cwd(cwd: &str) -> PathBuf {
+    let translated = match cwd.strip_prefix("/run/host") {
+        Some("") => PathBuf::from("/"),
+        Some(path) if path.starts_with('/') => PathBuf::from(path),
+        _ => PathBuf::from(cwd),
+    };
+
+    if translated.is_dir() {
+        translated
+    } else {
+        PathBuf::from(std::env::var("HOME").unwrap_or("/".to_string()))
+    }
+}
+
+fn set_terminal_size(terminal: &File, size: (usize, usize)) {
+    let size = libc::winsize {
+        ws_col: size.0 as u16,
+        ws_row: size.1 as u16,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    unsafe {
+        libc::ioctl(terminal.as_raw_fd(), libc::TIOCSWINSZ, &size);
+    }
+}
+
+/// Open a new pseudo terminal returning the (master, slave) pair
+fn open_pty() -> io::Result<(File, File)> {
+    unsafe {
+        let master_fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC);
+        if master_fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let master = File::from_raw_fd(master_fd);
+        if libc::grantpt(master_fd) != 0 || libc::unlockpt(master_fd) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut slave_name = [0 as libc::c_char; 128];
+        if libc::ptsname_r(master_fd, slave_name.as_mut_ptr(), slave_name.len()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let slave_path = CStr::from_ptr(slave_name.as_ptr()).to_string_lossy().to_string();
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(slave_path)?;
+
+        Ok((master, slave))
+    }
+}
+
+fn forward_output(mut output: impl Read + Send + 'static, kind: u8, writer: Arc<Mutex<UnixStream>>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        loop {
+            match output.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(length) => {
+                    if send_frame(&writer, kind, &buffer[..length]).is_err() {
+                        break;
+                    }
+                },
+            }
+        }
+    })
+}
+
+/// Answer a request we are not running anything for with `message` on stderr and exit code 127
+fn refuse(writer: &Mutex<UnixStream>, message: &str) -> io::Result<()> {
+    send_frame(writer, FRAME_STDERR, format!("{}\n", message).as_bytes())?;
+    send_frame(writer, FRAME_EXIT, &127i32.to_be_bytes())
+}
+
+/// Run the command of a single host-exec request, streaming its output back until it exits
+fn handle_connection(stream: UnixStream, policy: &RtBoxHostCommandsConfig) -> io::Result<()> {
+    let mut reader = stream.try_clone()?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+
+    let request: RtBoxHostExecRequest = match read_frame(&mut reader)? {
+        Some((FRAME_REQUEST, data)) => serde_json::from_slice(&data)?,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a host-exec request")),
+    };
+    debug!("host-exec request: {:?}", request);
+
+    let shell = std::env::var("SHELL").unwrap_or("/bin/sh".to_string());
+    let program = request.command.first().cloned().unwrap_or(shell);
+    if !policy.is_allowed(&program) {
+        info!("refusing to run {} on the host", program);
+        return refuse(&writer, &format!("{}: command not found", program));
+    }
+
+    let mut command = Command::new(&program);
+    command.args(request.command.iter().skip(1))
+        .current_dir(host_cwd(&request.cwd))
+        .envs(request.env.iter().map(|(name, value)| (name, value)));
+
+    let (mut child, mut input, terminal, outputs): (_, Option<Box<dyn Write + Send>>, _, _) = if request.tty {
+        let (master, slave) = open_pty()?;
+        if let Some(size) = request.size {
+            set_terminal_size(&master, size);
+        }
+
+        command.stdin(slave.try_clone()?)
+            .stdout(slave.try_clone()?)
+            .stderr(slave);
+        unsafe {
+            command.pre_exec(|| {
+                /* new session with the pty as controlling terminal, like a login would */
+                libc::setsid();
+                libc::ioctl(0, libc::TIOCSCTTY, 0);
+                Ok(())
+            });
+        }
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => return refuse(&writer, &format!("{}: {}", program, err)),
+        };
+        drop(command);
+
+        let outputs = vec![forward_output(master.try_clone()?, FRAME_STDOUT, writer.clone())];
+        (child, Some(Box::new(master.try_clone()?)), Some(master), outputs)
+    } else {
+        command.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0);
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => return refuse(&writer, &format!("{}: {}", program, err)),
+        };
+
+        let outputs = vec![
+            forward_output(child.stdout.take().unwrap(), FRAME_STDOUT, writer.clone()),
+            forward_output(child.stderr.take().unwrap(), FRAME_STDERR, writer.clone()),
+        ];
+        let stdin = child.stdin.take().map(|stdin| Box::new(stdin) as Box<dyn Write + Send>);
+        (child, stdin, None, outputs)
+    };
+
+    let pid = child.id() as i32;
+    let exited = Arc::new(AtomicBool::new(false));
+    let waiter = {
+        let exited = exited.clone();
+        let writer = writer.clone();
+        thread::spawn(move || {
+            let exit_code = match child.wait() {
+                Ok(status) => status.code().unwrap_or(128 + status.signal().unwrap_or(0)),
+                Err(_) => 1,
+            };
+            for output in outputs {
+                let _ = output.join();
+            }
+
+            exited.store(true, Ordering::SeqCst);
+            let _ = send_frame(&writer, FRAME_EXIT, &exit_code.to_be_bytes());
+            let _ = writer.lock().unwrap().shutdown(std::net::Shutdown::Both);
+        })
+    };
+
+    loop {
+        match read_frame(&mut reader) {
+            Ok(Some((FRAME_STDIN, data))) => {
+                if let Some(input) = input.as_mut() {
+                    let _ = input.write_all(&data).and_then(|_| input.flush());
+                }
+            },
+            Ok(Some((FRAME_STDIN_CLOSED, _))) if terminal.is_none() => input = None,
+            Ok(Some((FRAME_RESIZE, data))) => {
+                if let (Some(terminal), Ok(size)) = (&terminal, serde_json::from_slice(&data)) {
+                    set_terminal_size(terminal, size);
+                }
+            },
+            Ok(Some(_)) => {},
+            Ok(None) | Err(_) => break,
+        }
+    }
+
+    /* the box side went away before the command finished */
+    if !exited.load(Ordering::SeqCst) {
+        unsafe {
+            libc::kill(-pid, libc::SIGHUP);
+        }
+    }
+    drop(input);
+    let _ = waiter.join();
+
+    Ok(())
+}
+
+/// Listen for host-exec requests on `socket_path`, each connection is served on its own thread
+pub fn serve(socket_path: &Path, policy: RtBoxHostCommandsConfig) -> io::Result<()> {
+    control::ensure_runtime_dir()?;
+    if let Some(socket_dir) = socket_path.parent() {
+        std::fs::create_dir_all(socket_dir)?;
+    }
+    if std::fs::symlink_metadata(socket_path).is_ok() {
+        std::fs::remove_file(socket_path)?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))?;
+    info!("listening for host-exec requests on {}", socket_path.display());
+
+    let policy = Arc::new(policy);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let policy = policy.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = handle_connection(stream, &policy) {
+                            warn!("host-exec request failed: {}", err);
+                        }
+                    });
+                },
+                Err(err) => warn!("could not accept host-exec connection: {}", err),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Ask the host helper listening on `socket_path` to run `command`, returns its exit code
+pub fn host_exec(socket_path: &Path, command: Vec<String>, pty: bool) -> io::Result<i32> {
+    let tty = pty && tty::is_interactive();
+    let request = RtBoxHostExecRequest {
+        command,
+        cwd: std::env::current_dir()?.display().to_string(),
+        env: FORWARDED_ENV.iter()
+            .filter_map(|name| std::env::var(name).ok().map(|value| (name.to_string(), value)))
+            .collect(),
+        tty,
+        size: tty.then(tty::terminal_size).flatten(),
+    };
+
+    let stream = UnixStream::connect(socket_path)?;
+    let mut reader = stream.try_clone()?;
+    let writer = Arc::new(Mutex::new(stream));
+    send_frame(&writer, FRAME_REQUEST, &serde_json::to_vec(&request)?)?;
+
+    let _raw_terminal = tty.then(RawTerminal::new).flatten();
+
+    {
+        let writer = writer.clone();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buffer = [0u8; 4096];
+            loop {
+                match stdin.read(&mut buffer) {
+                    Ok(0) | Err(_) => {
+                        let _ = send_frame(&writer, FRAME_STDIN_CLOSED, &[]);
+                        break;
+                    },
+                    Ok(length) => {
+                        if send_frame(&writer, FRAME_STDIN, &buffer[..length]).is_err() {
+                            break;
+                        }
+                    },
+                }
+            }
+        });
+    }
+
+    if tty {
+        let writer = writer.clone();
+        thread::spawn(move || {
+            let mut size = request.size;
+            loop {
+                thread::sleep(Duration::from_millis(250));
+                let new_size = tty::terminal_size();
+                if new_size.is_some() && new_size != size {
+                    size = new_size;
+                    let resized = send_frame(&writer, FRAME_RESIZE, &serde_json::to_vec(&size.unwrap()).unwrap());
+                    if resized.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    loop {
+        match read_frame(&mut reader)? {
+            Some((FRAME_STDOUT, data)) => {
+                let mut stdout = io::stdout();
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+            },
+            Some((FRAME_STDERR, data)) => io::stderr().write_all(&data)?,
+            Some((FRAME_EXIT, data)) if data.len() == 4 => {
+                return Ok(i32::from_be_bytes([data[0], data[1], data[2], data[3]]));
+            },
+            Some(_) => {},
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the host helper went away")),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+/// Where the default profile mounts the host root inside a box
+pub const HOST_ROOT: &str = "/run/host";
+
+/// Host files kept in sync with their copy inside the container, the hostname is left alone
+/// since each box has its own `<name>.host` one
+pub const SYNCED_FILES: [&str; 3] = [
+    "/etc/resolv.conf",
+    "/etc/hosts",
+    "/etc/localtime",
+];
+
+const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_TO
+    | libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_ATTRIB;
+
+fn host_path(host_root: &Path, file: &str) -> PathBuf {
+    host_root.join(file.trim_start_matches('/'))
+}
+
+/// Point the container's localtime at the host one, keeping `/etc/timezone` in step for Debian based images
+fn sync_localtime(host_root: &Path, root: &Path) -> io::Result<bool> {
+    let host_localtime = host_path(host_root, "/etc/localtime");
+    let localtime = root.join("etc/localtime");
+
+    let mut changed = false;
+    if fs::read_link(&localtime).ok().as_deref() != Some(host_localtime.as_path()) {
+        if fs::symlink_metadata(&localtime).is_ok() {
+            fs::remove_file(&localtime)?;
+        }
+        symlink(&host_localtime, &localtime)?;
+        changed = true;
+    }
+
+    let timezone = root.join("etc/timezone");
+    let zone = fs::read_link(&host_localtime)
+        .ok()
+        .and_then(|target| {
+            let target = target.to_string_lossy().to_string();
+            target.split_once("zoneinfo/").map(|(_, zone)| format!("{}\n", zone))
+        });
+    if let Some(zone) = zone.filter(|_| timezone.exists()) {
+        if fs::read_to_string(&timezone).ok().as_deref() != Some(zone.as_str()) {
+            fs::write(&timezone, zone)?;
+            changed = true;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Copy one of `SYNCED_FILES` from `host_root` into `root`, returns whether anything changed
+pub fn sync_file(host_root: &Path, root: &Path, file: &str) -> io::Result<bool> {
+    let source = host_path(host_root, file);
+    if !source.exists() {
+        return Ok(false);
+    }
+    if file == "/etc/localtime" {
+        return sync_localtime(host_root, root);
+    }
+
+    let contents = fs::read(&source)?;
+    let destination = root.join(file.trim_start_matches('/'));
+    if fs::read(&destination).ok().as_ref() == Some(&contents) {
+        return Ok(false);
+    }
+
+    /* podman bind mounts some of these, so they have to be rewritten in place */
+    fs::write(&destination, contents)?;
+    Ok(true)
+}
+
+pub fn sync_all(host_root: &Path, root: &Path) {
+    for file in SYNCED_FILES {
+        match sync_file(host_root, root, file) {
+            Ok(true) => info!("updated {} from the host", file),
+            Ok(false) => {},
+            Err(err) => warn!("could not update {} from the host: {}", file, err),
+        }
+    }
+}
+
+/// Watches the host directories holding `SYNCED_FILES`, and the targets when they are symlinks
+pub struct RtBoxHostWatcher {
+    fd: i32,
+    host_root: PathBuf,
+    watches: BTreeMap<i32, PathBuf>,
+    names: Vec<Vec<u8>>,
+}
+
+impl RtBoxHostWatcher {
+    pub fn new(host_root: &Path) -> io::Result<Self> {
+        let fd = unsafe {
+            libc::inotify_init1(libc::IN_CLOEXEC)
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut watcher = Self {
+            fd,
+            host_root: host_root.to_path_buf(),
+            watches: BTreeMap::new(),
+            names: vec![],
+        };
+        watcher.add_watches();
+        Ok(watcher)
+    }
+
+    /// Symlink targets change when e.g. NetworkManager hands resolv.conf to systemd-resolved, so this runs after every change
+    fn add_watches(&mut self) {
+        for file in SYNCED_FILES {
+            let path = host_path(&self.host_root, file);
+            let mut paths = vec![path.clone()];
+            if let Ok(target) = fs::canonicalize(&path) {
+                paths.push(target);
+            }
+
+            for path in paths {
+                let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+                    continue;
+                };
+                if !self.names.iter().any(|it| it.as_slice() == name.as_bytes()) {
+                    self.names.push(name.as_bytes().to_vec());
+                }
+                if self.watches.values().any(|it| it == dir) {
+                    continue;
+                }
+
+                let Ok(dir_path) = CString::new(dir.as_os_str().as_bytes()) else {
+                    continue;
+                };
+                let wd = unsafe {
+                    libc::inotify_add_watch(self.fd, dir_path.as_ptr(), WATCH_MASK)
+                };
+                if wd < 0 {
+                    debug!("could not watch {}: {}", dir.display(), io::Error::last_os_error());
+                } else {
+                    debug!("watching {} for host changes", dir.display());
+                    self.watches.insert(wd, dir.to_path_buf());
+                }
+            }
+        }
+    }
+
+    /// Block until one of the watched files changes
+    pub fn wait(&mut self) -> io::Result<()> {
+        let mut buffer = [0u8; 4096];
+        loop {
+            let length = unsafe {
+                libc::read(self.fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len())
+            };
+            if length < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err);
+            }
+
+            let header_size = std::mem::size_of::<libc::inotify_event>();
+            let mut offset = 0;
+            let mut relevant = false;
+            while offset + header_size <= length as usize {
+                let event = unsafe {
+                    std::ptr::read_unaligned(buffer.as_ptr().add(offset) as *const libc::inotify_event)
+                };
+                let name_start = offset + header_size;
+                let name = &buffer[name_start..name_start + event.len as usize];
+                let name = name.split(|byte| *byte == 0).next().unwrap_or_default();
+
+                relevant |= self.names.iter().any(|it| it.as_slice() == name);
+                offset = name_start + event.len as usize;
+            }
+
+            if relevant {
+                self.add_watches();
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl Drop for RtBoxHostWatcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Keep the container's copies of the host files up to date, only returns if inotify breaks
+pub fn watch(host_root: &Path) -> io::Result<()> {
+    if !host_root.join("etc").is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not mounted", host_root.display())));
+    }
+
+    let mut watcher = RtBoxHostWatcher::new(host_root)?;
+    sync_all(host_root, Path::new("/"));
+
+    loop {
+        watcher.wait()?;
+        sync_all(host_root, Path::new("/"));
+    }
+}
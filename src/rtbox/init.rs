@@ -1,35 +1,517 @@
 use log::{debug, info};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::thread::sleep;
 use std::time;
 
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use crate::rtbox::cli::HOST_SPAWN_NAME;
+use crate::rtbox::control::{self, RtBoxControlError, RtBoxControlHandler};
+use crate::rtbox::host_commands;
+use crate::rtbox::host_exec;
+use crate::rtbox::hooks::{self, RtBoxHookFailurePolicy, RtBoxHookPhase, RtBoxHookResult, RtBoxHookRunner};
+use crate::rtbox::host_sync;
+use crate::rtbox::packages;
+use crate::rtbox::services::{RtBoxService, RtBoxServiceStatus, RtBoxSupervisor, SERVICE_LOG_DIR};
+
 use libc::{
-    pid_t,
-    size_t,
     sigset_t,
     c_int,
+    pid_t,
     SIG_BLOCK,
     sigprocmask,
-    sigfillset,
+    sigemptyset,
+    sigaddset,
+    sigwait,
     fork,
-    wait,
+    waitpid,
     setsid,
-    setpgid
+    WNOHANG,
 };
 
-use crate::rtbox::error::RtBoxError;
+/// Written by init inside the container so the host knows when the box can be used
+pub const RTBOX_READINESS_PATH: &str = "/run/.rtboxenv";
+
+/// Where we link the rtbox binary inside the box
+const BIN_DIR: &str = "/usr/local/bin";
+
+/// Oneshot unit doing rtbox's setup when systemd is PID 1
+pub const SYSTEMD_SETUP_UNIT: &str = "rtbox-init.service";
+
+/// Where images usually ship systemd, the first one found is used
+const SYSTEMD_PATHS: [&str; 3] = [
+    "/usr/lib/systemd/systemd",
+    "/lib/systemd/systemd",
+    "/sbin/init",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RtBoxInitMode {
+    /// rtbox init stays PID 1 and supervises the box
+    #[default]
+    Rtbox,
+    /// The image's systemd becomes PID 1 and runs rtbox's setup as a oneshot unit
+    Systemd,
+}
+
+impl RtBoxInitMode {
+    pub fn name(&self) -> &str {
+        match self {
+            RtBoxInitMode::Rtbox => "rtbox",
+            RtBoxInitMode::Systemd => "systemd",
+        }
+    }
+}
 
+#[allow(dead_code)]
 pub struct RtBoxInitState<'a> {
     pub uid: i32,
     pub gid: i32,
     pub username: &'a str,
     pub home: &'a str,
-    pub shell: &'a str
+    pub shell: &'a str,
+    pub additional_packages: &'a [String],
+    pub pre_init_hooks: &'a [String],
+    pub init_hooks: &'a [String],
+    pub hook_timeout: u64,
+    pub hook_failure_policy: RtBoxHookFailurePolicy,
+    pub services: &'a [RtBoxService],
+    pub mode: RtBoxInitMode,
+    /// Only run the setup and exit, used by the systemd setup unit
+    pub oneshot: bool,
+    /// Host commands that get a shim on the box's PATH
+    pub host_commands: &'a [String],
+    /// Forward commands the box does not have to the host
+    pub command_not_found: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RtBoxInitStatus {
+    Starting,
+    Ready,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxReadiness {
+    pub status: RtBoxInitStatus,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub hooks: Vec<RtBoxHookResult>,
+}
+
+impl RtBoxReadiness {
+    fn write(status: RtBoxInitStatus, message: Option<String>, hooks: &[RtBoxHookResult]) {
+        let readiness = RtBoxReadiness { status, message, hooks: hooks.to_vec() };
+        debug!("readiness: {:?}", readiness);
+
+        if let Err(err) = std::fs::write(RTBOX_READINESS_PATH, serde_json::to_string(&readiness).unwrap()) {
+            error!("could not write {}: {}", RTBOX_READINESS_PATH, err);
+        }
+    }
+}
+
+/// Seconds since the epoch when a session last started or ended, kept across restarts
+pub const LAST_SESSION_PATH: &str = "/var/lib/rtbox/last-session";
+
+/// Sourced by login shells so new sessions see the environment the host last sent
+pub const HOST_ENV_PROFILE_PATH: &str = "/etc/profile.d/rtbox-host-env.sh";
+
+/// Where we install shell snippets sourced by every login shell
+const PROFILE_DIR: &str = "/etc/profile.d";
+
+/// Written by podman on every start, it carries the current container name
+const CONTAINERENV_PATH: &str = "/run/.containerenv";
+
+/// Hostname of a box, podman only sets it on create so init keeps it in step when the box is renamed
+pub fn box_hostname(name: &str) -> String {
+    format!("{}.host", name)
+}
+
+/// The `name="..."` entry of `/run/.containerenv`
+pub fn containerenv_name(containerenv: &str) -> Option<String> {
+    containerenv.lines()
+        .find_map(|line| line.strip_prefix("name="))
+        .map(|name| name.trim_matches('"').to_string())
+        .filter(|name| !name.is_empty())
+}
+
+fn set_hostname(hostname: &str) -> Result<(), String> {
+    let result = unsafe {
+        libc::sethostname(hostname.as_ptr() as *const libc::c_char, hostname.len())
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(format!("could not set hostname to {}: {}", hostname, std::io::Error::last_os_error())),
+    }
+}
+
+/// Use the hostname of the name the container has now, it differs from the created one after a rename
+fn sync_hostname() {
+    let Some(name) = std::fs::read_to_string(CONTAINERENV_PATH).ok().and_then(|it| containerenv_name(&it)) else {
+        return;
+    };
+    let hostname = box_hostname(&name);
+    let current_hostname = std::fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_default();
+
+    if current_hostname.trim() != hostname {
+        if let Err(message) = set_hostname(&hostname) {
+            warn!("{}", message);
+        }
+    }
+}
+
+/// An enter or run the host registered with init
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxSession {
+    pub id: u64,
+    pub pid: u32,
+    pub started_at: u64,
+}
+
+/// Answer to the `status` control request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxInitReport {
+    pub readiness: Option<RtBoxReadiness>,
+    pub started_at: u64,
+    pub sessions: Vec<RtBoxSession>,
+    pub last_session_at: Option<u64>,
+    pub services: Vec<RtBoxServiceStatus>,
+}
+
+pub fn unix_time() -> u64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Whether `name` can be exported by a shell, anything else could inject commands into the profile
+pub fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().map(|first| first.is_ascii_alphabetic() || first == '_').unwrap_or(false)
+        && chars.all(|it| it.is_ascii_alphanumeric() || it == '_')
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Escape `value` so a sed basic regular expression delimited by `/` matches it literally
+fn sed_escape(value: &str) -> String {
+    value.chars()
+        .map(|it| match it {
+            '\\' | '/' | '.' | '*' | '[' | ']' | '^' | '$' => format!("\\{}", it),
+            it => it.to_string(),
+        })
+        .collect()
+}
+
+/// Shell command undoing what init and the engine set up for `username`, run on a scratch
+/// copy of a box before it is committed so the image can be shared
+pub fn cleanup_command(username: &str) -> Vec<String> {
+    let bin_dir = Path::new(BIN_DIR);
+    /* only ever a file in sudoers.d, whatever the container claims its user is called */
+    let sudoers = Path::new("/etc/sudoers.d").join(Path::new(username).file_name().unwrap_or_default());
+    let script = format!("\
+        for file in /etc/passwd /etc/shadow /etc/group /etc/gshadow; do\n\
+        \x20   [ -f \"$file\" ] && sed -i {delete_user} \"$file\"\n\
+        done\n\
+        rm -f {sudoers} /etc/sudoers.d/toolbox\n\
+        for link in {rtbox_link} {host_spawn_link}; do\n\
+        \x20   [ -L \"$link\" ] && rm -f \"$link\"\n\
+        done\n\
+        for file in {bin_dir}/* {profile_dir}/{command_not_found}; do\n\
+        \x20   grep -qxF {marker} \"$file\" 2>/dev/null && rm -f \"$file\"\n\
+        done\n\
+        rm -f {host_env} {readiness} {last_session}\n\
+        exit 0\n",
+        delete_user = shell_quote(&format!("/^{}:/d", sed_escape(username))),
+        sudoers = shell_quote(&sudoers.display().to_string()),
+        rtbox_link = bin_dir.join("rtbox").display(),
+        host_spawn_link = bin_dir.join(HOST_SPAWN_NAME).display(),
+        bin_dir = BIN_DIR,
+        profile_dir = PROFILE_DIR,
+        command_not_found = host_commands::COMMAND_NOT_FOUND_SCRIPT,
+        marker = shell_quote(host_commands::SHIM_MARKER),
+        host_env = HOST_ENV_PROFILE_PATH,
+        readiness = RTBOX_READINESS_PATH,
+        last_session = LAST_SESSION_PATH,
+    );
+
+    vec!["/bin/sh".to_string(), "-c".to_string(), script]
+}
+
+/// Answers the host's control requests
+struct RtBoxInitControl {
+    supervisor: Arc<RtBoxSupervisor>,
+    started_at: u64,
+    sessions: Mutex<Vec<RtBoxSession>>,
+    next_session_id: Mutex<u64>,
 }
 
+impl RtBoxInitControl {
+    fn string_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RtBoxControlError> {
+        params.get(name)
+            .and_then(|value| value.as_str())
+            .ok_or(RtBoxControlError::invalid_params(&format!("missing {}", name)))
+    }
 
+    fn record_session_activity() {
+        let last_session_dir = Path::new(LAST_SESSION_PATH).parent().unwrap();
+        let written = std::fs::create_dir_all(last_session_dir)
+            .and_then(|_| std::fs::write(LAST_SESSION_PATH, unix_time().to_string()));
+        if let Err(err) = written {
+            warn!("could not record session activity in {}: {}", LAST_SESSION_PATH, err);
+        }
+    }
+
+    fn status(&self) -> RtBoxInitReport {
+        RtBoxInitReport {
+            readiness: std::fs::read(RTBOX_READINESS_PATH)
+                .ok()
+                .and_then(|contents| serde_json::from_slice(&contents).ok()),
+            started_at: self.started_at,
+            sessions: self.sessions.lock().unwrap().clone(),
+            last_session_at: std::fs::read_to_string(LAST_SESSION_PATH)
+                .ok()
+                .and_then(|contents| contents.trim().parse().ok()),
+            services: self.supervisor.list(),
+        }
+    }
+
+    fn register_session(&self, params: &Value) -> Result<Value, RtBoxControlError> {
+        let pid = params.get("pid")
+            .and_then(|pid| pid.as_u64())
+            .ok_or(RtBoxControlError::invalid_params("missing pid"))?;
+
+        let mut next_session_id = self.next_session_id.lock().unwrap();
+        *next_session_id += 1;
+        let session = RtBoxSession {
+            id: *next_session_id,
+            pid: pid as u32,
+            started_at: unix_time(),
+        };
+        debug!("registered session {:?}", session);
+
+        self.sessions.lock().unwrap().push(session.clone());
+        Self::record_session_activity();
+        Ok(json!(session))
+    }
+
+    fn unregister_session(&self, params: &Value) -> Result<Value, RtBoxControlError> {
+        let id = params.get("id")
+            .and_then(|id| id.as_u64())
+            .ok_or(RtBoxControlError::invalid_params("missing id"))?;
+
+        self.sessions.lock().unwrap().retain(|session| session.id != id);
+        Self::record_session_activity();
+        Ok(Value::Null)
+    }
+
+    /// Services started from now on and new login shells get `env`
+    fn refresh_env(&self, params: &Value) -> Result<Value, RtBoxControlError> {
+        let env: BTreeMap<String, String> = params.get("env")
+            .cloned()
+            .and_then(|env| serde_json::from_value(env).ok())
+            .ok_or(RtBoxControlError::invalid_params("env has to map names to values"))?;
+        if let Some(name) = env.keys().find(|name| !is_env_name(name)) {
+            return Err(RtBoxControlError::invalid_params(&format!("invalid variable name {:?}", name)));
+        }
+
+        let profile = env.iter()
+            .map(|(name, value)| format!("export {}={}\n", name, shell_quote(value)))
+            .collect::<String>();
+        std::fs::write(HOST_ENV_PROFILE_PATH, profile)
+            .map_err(|err| format!("could not write {}: {}", HOST_ENV_PROFILE_PATH, err))?;
+
+        self.supervisor.set_env(env);
+        Ok(Value::Null)
+    }
+
+    /// The box was renamed while running
+    fn update_hostname(&self, params: &Value) -> Result<Value, RtBoxControlError> {
+        let name = Self::string_param(params, "name")?;
+        set_hostname(&box_hostname(name))?;
+        Ok(Value::Null)
+    }
+
+    /// Stop the services and exit, which stops the container since we are PID 1
+    fn shutdown(&self) -> Result<Value, RtBoxControlError> {
+        info!("shutting down on request of the host");
+
+        let supervisor = self.supervisor.clone();
+        thread::spawn(move || {
+            supervisor.stop_all();
+            std::process::exit(0);
+        });
+        Ok(Value::Null)
+    }
+}
+
+impl RtBoxControlHandler for RtBoxInitControl {
+    fn handle(&self, method: &str, params: Value) -> Result<Value, RtBoxControlError> {
+        match method {
+            "status" => Ok(json!(self.status())),
+            "session.register" => self.register_session(&params),
+            "session.unregister" => self.unregister_session(&params),
+            "env.refresh" => self.refresh_env(&params),
+            "hostname.update" => self.update_hostname(&params),
+            "shutdown" => self.shutdown(),
+            "service.list" => Ok(json!(self.supervisor.list())),
+            "service.start" => Ok(json!(self.supervisor.start(Self::string_param(&params, "name")?)?)),
+            "service.stop" => Ok(json!(self.supervisor.stop(Self::string_param(&params, "name")?)?)),
+            "service.logs" => {
+                let lines = params.get("lines").and_then(|lines| lines.as_u64()).unwrap_or(100);
+                Ok(json!(self.supervisor.logs(Self::string_param(&params, "name")?, lines as usize)?))
+            },
+            _ => Err(RtBoxControlError::method_not_found(method)),
+        }
+    }
+}
+
+/// Quote `arg` for an `ExecStart=` line, systemd expands `$` and `%` on its own
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "$$")
+        .replace('%', "%%");
+    format!("\"{}\"", escaped)
+}
+
+/// The setup unit runs our own command line again with `--oneshot`
+pub fn systemd_setup_unit(init_command: &[String]) -> String {
+    let exec_start = init_command.iter()
+        .map(|arg| systemd_quote(arg))
+        .chain(std::iter::once(systemd_quote("--oneshot")))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    format!("\
+        [Unit]\n\
+        Description=rtbox container setup\n\
+        After=local-fs.target\n\
+        Before=systemd-user-sessions.service\n\
+        \n\
+        [Service]\n\
+        Type=oneshot\n\
+        RemainAfterExit=yes\n\
+        TimeoutStartSec=infinity\n\
+        ExecStart={}\n\
+        \n\
+        [Install]\n\
+        WantedBy=multi-user.target\n", exec_start)
+}
+
+/// Install the setup unit and replace ourselves with the image's systemd, only returns on failure
+fn exec_systemd() -> Result<(), String> {
+    let unit_dir = Path::new("/etc/systemd/system");
+    let wants_dir = unit_dir.join("multi-user.target.wants");
+    let init_command: Vec<String> = std::env::args().collect();
+
+    std::fs::create_dir_all(&wants_dir)
+        .and_then(|_| std::fs::write(unit_dir.join(SYSTEMD_SETUP_UNIT), systemd_setup_unit(&init_command)))
+        .and_then(|_| {
+            let wants_link = wants_dir.join(SYSTEMD_SETUP_UNIT);
+            if std::fs::symlink_metadata(&wants_link).is_ok() {
+                std::fs::remove_file(&wants_link)?;
+            }
+            std::os::unix::fs::symlink(unit_dir.join(SYSTEMD_SETUP_UNIT), wants_link)
+        })
+        .map_err(|err| format!("could not install {}: {}", SYSTEMD_SETUP_UNIT, err))?;
+
+    let systemd = SYSTEMD_PATHS.iter()
+        .find(|path| Path::new(path).exists())
+        .ok_or("systemd is not installed in this image".to_string())?;
+    info!("handing PID 1 over to {}", systemd);
+
+    let err = Command::new(systemd).exec();
+    Err(format!("could not execute {}: {}", systemd, err))
+}
+
+/// Signals PID 1 handles itself, they stay blocked and are picked up with sigwait
+const INIT_SIGNALS: [c_int; 4] = [libc::SIGCHLD, libc::SIGTERM, libc::SIGINT, libc::SIGHUP];
+
+fn signal_set(signals: &[c_int]) -> sigset_t {
+    unsafe {
+        let mut set: sigset_t = std::mem::zeroed();
+        sigemptyset(&mut set);
+        for signal in signals {
+            sigaddset(&mut set, *signal);
+        }
+        set
+    }
+}
+
+/// Exit code of a process as a shell reports it
+fn wait_status_code(status: c_int) -> i32 {
+    if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else {
+        128 + libc::WTERMSIG(status)
+    }
+}
+
+/// What PID 1 does once the box runs in `box_pid`: reap every process that ends up with us,
+/// orphans of services and sessions included, and pass termination on to the box process
+fn reap(box_pid: pid_t) -> ! {
+    let signals = signal_set(&INIT_SIGNALS);
+
+    loop {
+        let mut signal: c_int = 0;
+        if unsafe { sigwait(&signals, &mut signal) } != 0 {
+            continue;
+        }
+
+        if signal != libc::SIGCHLD {
+            debug!("forwarding signal {} to {}", signal, box_pid);
+            unsafe {
+                libc::kill(box_pid, signal);
+            }
+            continue;
+        }
+
+        loop {
+            let mut status: c_int = 0;
+            let pid = unsafe { waitpid(-1, &mut status, WNOHANG) };
+            if pid <= 0 {
+                break;
+            }
+            if pid == box_pid {
+                info!("box process exited, stopping the container");
+                std::process::exit(wait_status_code(status));
+            }
+            debug!("reaped orphaned process {}", pid);
+        }
+    }
+}
+
+/// Stop the services and exit once the box process is asked to terminate
+fn handle_termination(supervisor: Arc<RtBoxSupervisor>) {
+    let signals = signal_set(&INIT_SIGNALS[1..]);
+
+    thread::spawn(move || loop {
+        let mut signal: c_int = 0;
+        if unsafe { sigwait(&signals, &mut signal) } == 0 {
+            info!("received signal {}, shutting down", signal);
+            supervisor.stop_all();
+            std::process::exit(0);
+        }
+    });
+}
 
 pub trait RtBoxInitSystem {
-    fn run(&self, init_state: &RtBoxInitState);
+    fn run(&self, init_state: &RtBoxInitState) -> Result<(), String>;
 }
 
 pub struct RtBoxInit;
@@ -39,50 +521,147 @@ impl RtBoxInit {
     }
 }
 
-impl RtBoxInitSystem for RtBoxInit {
-    fn run(&self, init_state: &RtBoxInitState) {
-        debug!("starting up container with init_state: {:?}", init_state.username);
+impl RtBoxInit {
+    /// Make `rtbox` and the host-spawn style name available on the box's PATH, existing files are left alone
+    fn install_links(&self) {
+        let Ok(rtbox_exe) = std::env::current_exe() else {
+            return;
+        };
 
-        loop {
+        for name in ["rtbox", HOST_SPAWN_NAME] {
+            let link = Path::new(BIN_DIR).join(name);
+            if std::fs::symlink_metadata(&link).is_ok() {
+                continue;
+            }
+            let linked = std::fs::create_dir_all(BIN_DIR)
+                .and_then(|_| std::os::unix::fs::symlink(&rtbox_exe, &link));
+            if let Err(err) = linked {
+                warn!("could not link {} to {}: {}", link.display(), rtbox_exe.display(), err);
+            }
+        }
+    }
 
-            unsafe {
-                info!("we are entering unsafe code now, let there be dragons");
+    /// Shims forwarding the configured commands to the host and the command_not_found hook
+    fn install_host_commands(&self, init_state: &RtBoxInitState) {
+        let rtbox_exe = Path::new(BIN_DIR).join("rtbox").display().to_string();
+
+        let shims = host_commands::install_shims(Path::new(BIN_DIR), Path::new("/"), &rtbox_exe, init_state.host_commands);
+        debug!("installed host command shims: {:?}", shims);
+
+        if init_state.command_not_found {
+            let host_socket = host_exec::container_socket().display().to_string();
+            if let Err(err) = host_commands::install_command_not_found(Path::new(PROFILE_DIR), &rtbox_exe, &host_socket) {
+                warn!("could not install the command_not_found hook: {}", err);
+            }
+        }
+    }
+
+    /// Run the hooks and install packages, reporting the outcome through the readiness file
+    fn bootstrap(&self, init_state: &RtBoxInitState) -> Result<(), String> {
+        self.install_links();
+        self.install_host_commands(init_state);
+
+        let hook_runner = RtBoxHookRunner::new(
+            time::Duration::from_secs(init_state.hook_timeout),
+            init_state.hook_failure_policy,
+        );
+        let pre_init_hooks = hooks::collect(RtBoxHookPhase::PreInit, init_state.pre_init_hooks, init_state.home);
+        let init_hooks = hooks::collect(RtBoxHookPhase::Init, init_state.init_hooks, init_state.home);
+        let mut hook_results = vec![];
 
-                let mut set: sigset_t = std::mem::zeroed();
-                let mut oldset: sigset_t = std::mem::zeroed();
-                let mut waitstatus: c_int = std::mem::zeroed();
+        let bootstrap_result = hook_runner.run_all(&pre_init_hooks, &mut hook_results)
+            .and_then(|_| packages::install(init_state.additional_packages))
+            .and_then(|_| hook_runner.run_all(&init_hooks, &mut hook_results));
 
-                sigfillset(&mut set);
-                sigprocmask(SIG_BLOCK, &mut set, &mut oldset);
+        match bootstrap_result {
+            Ok(_) => {
+                let failed_hooks: Vec<&str> = hook_results.iter()
+                    .filter(|hook_result| hook_result.message.is_some())
+                    .map(|hook_result| hook_result.name.as_str())
+                    .collect();
+                let message = (!failed_hooks.is_empty())
+                    .then(|| format!("some hooks failed: {}", failed_hooks.join(", ")));
+                RtBoxReadiness::write(RtBoxInitStatus::Ready, message, &hook_results);
+                Ok(())
+            },
+            Err(message) => {
+                error!("{}", message);
+                RtBoxReadiness::write(RtBoxInitStatus::Failed, Some(message.clone()), &hook_results);
+                Err(message)
+            },
+        }
+    }
+}
+
+impl RtBoxInitSystem for RtBoxInit {
+    fn run(&self, init_state: &RtBoxInitState) -> Result<(), String> {
+        debug!("starting up container with init_state: {:?}", init_state.username);
 
-                if fork() == 0 {
-                    loop {
-                        wait(&mut waitstatus);
-                        info!("waiting for children");
-                        sleep(time::Duration::from_millis(1000));
-                    }
-                }
+        if !init_state.oneshot {
+            sync_hostname();
+        }
+        if init_state.oneshot {
+            RtBoxReadiness::write(RtBoxInitStatus::Starting, None, &[]);
+            return self.bootstrap(init_state);
+        }
+        if init_state.mode == RtBoxInitMode::Systemd {
+            RtBoxReadiness::write(RtBoxInitStatus::Starting, None, &[]);
+            return exec_systemd();
+        }
 
+        /* blocked before forking so neither side misses one, processes we spawn get a clean mask from std */
+        let signals = signal_set(&INIT_SIGNALS);
+        unsafe {
+            sigprocmask(SIG_BLOCK, &signals, std::ptr::null_mut());
+        }
+
+        /* the box runs in a child so the processes it starts are waited for by their parent,
+           leaving PID 1 free to reap whatever gets orphaned */
+        match unsafe { fork() } {
+            -1 => return Err(format!("could not fork the box process: {}", std::io::Error::last_os_error())),
+            0 => unsafe {
                 setsid();
-                setpgid(0, 0);
+            },
+            box_pid => reap(box_pid),
+        }
 
-            }
+        RtBoxReadiness::write(RtBoxInitStatus::Starting, None, &[]);
 
-            debug!("we should execute our bootstrap script here");
-            loop {
-                debug!("here we should execute our host monitoring");
-                sleep(time::Duration::from_millis(5000));
-            }
-/*
+        /* the services were validated on create, so this only fails for hand written init arguments */
+        let supervisor = RtBoxSupervisor::new(
+            init_state.services,
+            Some((init_state.uid as u32, init_state.gid as u32)),
+            PathBuf::from(SERVICE_LOG_DIR),
+        ).unwrap_or_else(|message| {
+            error!("not supervising services: {}", message);
+            RtBoxSupervisor::new(&[], None, PathBuf::from(SERVICE_LOG_DIR)).unwrap()
+        });
+        let supervisor = Arc::new(supervisor);
+        handle_termination(supervisor.clone());
 
-			if (fork()) for (;;) wait(&status);
+        let control_handler = Arc::new(RtBoxInitControl {
+            supervisor: supervisor.clone(),
+            started_at: unix_time(),
+            sessions: Mutex::new(vec![]),
+            next_session_id: Mutex::new(0),
+        });
+        let control_socket = Path::new(control::CONTROL_DIR).join(control::CONTROL_SOCKET);
+        let socket_owner = Some((init_state.uid as u32, init_state.gid as u32));
+        if let Err(err) = control::serve(&control_socket, socket_owner, control_handler) {
+            warn!("control socket is not available: {}", err);
+        }
 
-			sigprocmask(SIG_UNBLOCK, &set, 0);
+        if self.bootstrap(init_state).is_ok() {
+            supervisor.start_all();
+            supervisor.supervise();
+        }
+
+        if let Err(err) = host_sync::watch(Path::new(host_sync::HOST_ROOT)) {
+            warn!("not keeping host files in sync: {}", err);
+        }
 
-			setsid();
-			setpgid(0, 0);
-			return execve("/etc/rc", (char *[]){ "rc", 0 }, (char *[]){ 0 });
-*/
+        loop {
+            sleep(time::Duration::from_millis(5000));
         }
     }
 }
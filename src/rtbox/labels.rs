@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::rtbox::profile::RtBoxProfile;
+
+/// Prefix of every label rtbox sets on its containers
+pub const LABEL_PREFIX: &str = "io.github.akdev1l.rtbox";
+
+/// Bumped whenever the meaning of a label changes so older boxes can still be read
+pub const LABELS_VERSION: u32 = 1;
+
+const LABELS_VERSION_LABEL: &str = "labels-version";
+const VERSION_LABEL: &str = "version";
+const IMAGE_LABEL: &str = "image";
+const IMAGE_DIGEST_LABEL: &str = "image-digest";
+const HOME_LABEL: &str = "home";
+const PROFILE_LABEL: &str = "profile";
+const ENV_LABEL: &str = "env";
+const HOOKS_HASH_LABEL: &str = "hooks-hash";
+const EXPIRES_AT_LABEL: &str = "expires-at";
+
+/// Written to the home label when the box shares the host home
+const HOST_HOME: &str = "host";
+
+/// How a box was created, stored as labels so it can be recreated or compared later
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxLabels {
+    pub labels_version: u32,
+    /// Version of rtbox that created the box
+    pub version: String,
+    /// Image reference as requested, before it was resolved
+    pub image: String,
+    pub image_digest: Option<String>,
+    /// Custom home of the box, the host home is shared when unset
+    pub home: Option<String>,
+    pub profile: RtBoxProfile,
+    /// Names of the host variables passed through
+    pub env: Vec<String>,
+    pub hooks_hash: String,
+    /// Seconds since the epoch after which prune removes the box
+    pub expires_at: Option<u64>,
+}
+
+pub fn label(name: &str) -> String {
+    format!("{}.{}", LABEL_PREFIX, name)
+}
+
+/// Dockerfile instructions blanking the rtbox labels in `labels`, podman commit copies them into the image otherwise
+pub fn clear_instructions(labels: &BTreeMap<String, String>) -> Vec<String> {
+    labels.keys()
+        .filter(|name| name.starts_with(&format!("{}.", LABEL_PREFIX)))
+        .map(|name| format!("LABEL {}=\"\"", name))
+        .collect()
+}
+
+/// Stable across builds and platforms unlike the std hasher, 64 bit FNV-1a is plenty to notice a change
+pub fn hooks_hash(pre_init_hooks: &[String], init_hooks: &[String]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let hooks = pre_init_hooks.iter()
+        .map(|hook| ("pre-init", hook))
+        .chain(init_hooks.iter().map(|hook| ("init", hook)));
+
+    for (phase, hook) in hooks {
+        /* the separators keep ["ab"] and ["a", "b"] apart */
+        for byte in phase.bytes().chain([0]).chain(hook.bytes()).chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    format!("{:016x}", hash)
+}
+
+impl RtBoxLabels {
+    pub fn to_labels(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (label(LABELS_VERSION_LABEL), self.labels_version.to_string()),
+            (label(VERSION_LABEL), self.version.clone()),
+            (label(IMAGE_LABEL), self.image.clone()),
+            (label(HOME_LABEL), self.home.clone().unwrap_or(HOST_HOME.to_string())),
+            (label(PROFILE_LABEL), self.profile.name().to_string()),
+            (label(ENV_LABEL), self.env.join(",")),
+            (label(HOOKS_HASH_LABEL), self.hooks_hash.clone()),
+            /* written even when unset so values the image may carry do not leak into the box */
+            (label(IMAGE_DIGEST_LABEL), self.image_digest.clone().unwrap_or_default()),
+            (label(EXPIRES_AT_LABEL), self.expires_at.map(|expires_at| expires_at.to_string()).unwrap_or_default()),
+        ])
+    }
+
+    /// Read back the labels of a box, `None` for boxes created before rtbox labelled them
+    pub fn from_labels(labels: &BTreeMap<String, String>) -> Option<Self> {
+        let get = |name: &str| labels.get(&label(name)).cloned();
+
+        let labels_version = get(LABELS_VERSION_LABEL)?.parse().ok()?;
+        if labels_version > LABELS_VERSION {
+            warn!("labels were written by a newer rtbox (version {}), reading them anyway", labels_version);
+        }
+
+        Some(Self {
+            labels_version,
+            version: get(VERSION_LABEL).unwrap_or_default(),
+            image: get(IMAGE_LABEL).unwrap_or_default(),
+            image_digest: get(IMAGE_DIGEST_LABEL).filter(|image_digest| !image_digest.is_empty()),
+            home: get(HOME_LABEL).filter(|home| home != HOST_HOME),
+            /* a profile we do not know gets nothing from the host rather than everything */
+            profile: match get(PROFILE_LABEL).as_deref() {
+                Some("default") => RtBoxProfile::Default,
+                Some("restricted") => RtBoxProfile::Restricted,
+                profile => {
+                    warn!("unknown profile {:?}, treating the box as restricted", profile);
+                    RtBoxProfile::Restricted
+                },
+            },
+            env: get(ENV_LABEL)
+                .map(|env| env.split(',').filter(|it| !it.is_empty()).map(|it| it.to_string()).collect())
+                .unwrap_or_default(),
+            hooks_hash: get(HOOKS_HASH_LABEL).unwrap_or_default(),
+            expires_at: get(EXPIRES_AT_LABEL).and_then(|expires_at| expires_at.parse().ok()),
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Where the host os-release file can be found, inside a box it is under /run/host
+pub const OS_RELEASE_PATHS: [&str; 2] = [
+    "/etc/os-release",
+    "/usr/lib/os-release",
+];
+
+/// Parse the `KEY=value` pairs of an os-release file, quotes are stripped
+pub fn parse(contents: &str) -> BTreeMap<String, String> {
+    contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim().trim_matches('"').trim_matches('\'');
+            (key.trim().to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Read the first os-release file found under `root`
+pub fn read(root: &str) -> Option<BTreeMap<String, String>> {
+    OS_RELEASE_PATHS.iter()
+        .find_map(|path| fs::read_to_string(format!("{}{}", root.trim_end_matches('/'), path)).ok())
+        .map(|contents| parse(&contents))
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Serialize, Deserialize};
+
+use crate::rtbox::os_release;
+
+/// Records which additional packages were installed so restarts skip the install
+pub const PACKAGES_STAMP_PATH: &str = "/var/lib/rtbox/additional-packages";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RtBoxPackageManager {
+    Dnf,
+    Apt,
+    Pacman,
+    Zypper,
+    Apk,
+    Xbps,
+}
+
+impl RtBoxPackageManager {
+    fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "fedora" | "rhel" | "centos" | "rocky" | "almalinux" | "ol" | "amzn" => Some(Self::Dnf),
+            "debian" | "ubuntu" | "linuxmint" | "pop" | "elementary" | "kali" => Some(Self::Apt),
+            "arch" | "archarm" | "manjaro" | "endeavouros" | "steamos" => Some(Self::Pacman),
+            "opensuse" | "opensuse-leap" | "opensuse-tumbleweed" | "suse" | "sles" => Some(Self::Zypper),
+            "alpine" | "postmarketos" => Some(Self::Apk),
+            "void" => Some(Self::Xbps),
+            _ => None,
+        }
+    }
+
+    /// Detect the package manager from the `ID` of an os-release file, falling back to `ID_LIKE`
+    pub fn detect(os_release: &BTreeMap<String, String>) -> Option<Self> {
+        let id = os_release.get("ID").map(|it| it.as_str()).unwrap_or("");
+        let id_like = os_release.get("ID_LIKE").map(|it| it.as_str()).unwrap_or("");
+
+        std::iter::once(id)
+            .chain(id_like.split_whitespace())
+            .find_map(Self::from_id)
+    }
+
+    /// Commands that install `packages` non-interactively, run in order
+    pub fn install_commands(&self, packages: &[String]) -> Vec<Vec<String>> {
+        let command = |args: &[&str]| -> Vec<String> {
+            args.iter()
+                .map(|arg| arg.to_string())
+                .chain(packages.iter().cloned())
+                .collect()
+        };
+
+        match self {
+            Self::Dnf => vec![command(&["dnf", "-y", "install"])],
+            Self::Apt => vec![
+                vec!["apt-get".to_string(), "update".to_string()],
+                command(&["apt-get", "install", "-y"]),
+            ],
+            Self::Pacman => vec![command(&["pacman", "-Sy", "--noconfirm", "--needed"])],
+            Self::Zypper => vec![command(&["zypper", "--non-interactive", "install"])],
+            Self::Apk => vec![command(&["apk", "add", "--update-cache"])],
+            Self::Xbps => vec![command(&["xbps-install", "-Sy"])],
+        }
+    }
+}
+
+fn stamp_contents(packages: &[String]) -> String {
+    let mut packages = packages.to_vec();
+    packages.sort();
+    packages.join("\n") + "\n"
+}
+
+/// Install `packages` inside the container unless a previous boot already did
+pub fn install(packages: &[String]) -> Result<(), String> {
+    if packages.is_empty() {
+        return Ok(());
+    }
+
+    let stamp = stamp_contents(packages);
+    if fs::read_to_string(PACKAGES_STAMP_PATH).map(|it| it == stamp).unwrap_or(false) {
+        info!("additional packages already installed, skipping");
+        return Ok(());
+    }
+
+    let package_manager = os_release::read("/")
+        .as_ref()
+        .and_then(RtBoxPackageManager::detect)
+        .ok_or("could not detect the package manager of this container")?;
+    info!("installing {} additional packages with {:?}", packages.len(), package_manager);
+
+    for command in package_manager.install_commands(packages) {
+        debug!("running {:?}", command);
+
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .env("DEBIAN_FRONTEND", "noninteractive")
+            .status()
+            .map_err(|err| format!("could not run {}: {}", command[0], err))?;
+
+        if !status.success() {
+            return Err(format!("{:?} failed with {}", command, status));
+        }
+    }
+
+    if let Some(stamp_dir) = Path::new(PACKAGES_STAMP_PATH).parent() {
+        let _ = fs::create_dir_all(stamp_dir);
+    }
+    fs::write(PACKAGES_STAMP_PATH, stamp)
+        .map_err(|err| format!("could not record installed packages: {}", err))
+}
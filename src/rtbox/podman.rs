@@ -1,75 +1,233 @@
 use async_trait::async_trait;
-use log::{debug};
+use futures::{AsyncWriteExt as _, StreamExt, TryStreamExt};
+use std::collections::BTreeMap;
+use std::io::{Read, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use log::{debug, warn};
+use containers_api::conn::{get_http_connector, get_unix_connector, hyper, Transport};
+use containers_api::url::url::Url;
 use podman_api::Podman;
 use podman_api::ApiVersion;
-use podman_api::api::{Container};
-use podman_api::models::{ListContainer, Namespace, ContainerMount};
-use podman_api::opts::{ContainerCreateOpts, ContainerListOpts, ContainerListFilter};
+use podman_api::api::{Container, Exec};
+use podman_api::conn::TtyChunk;
+use podman_api::models::{ImageLoadReport, ListContainer, Namespace, NamedVolume, ContainerMount};
+use podman_api::opts::{
+    ContainerCommitOpts,
+    ContainerCreateOpts,
+    ContainerDeleteOpts,
+    ContainerListOpts,
+    ContainerListFilter,
+    ContainerStopOpts,
+    ExecCreateOpts,
+    ExecStartOpts,
+    ImageExportOpts,
+    ImageListFilter,
+    ImageListOpts,
+    ImageTagOpts,
+    PullOpts,
+    SystemdEnabled,
+    UserOpt,
+    VolumeCreateOpts,
+    VolumeListFilter,
+    VolumeListOpts,
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::signal::unix::{signal, SignalKind};
 
-use crate::rtbox::engine::ContainerEngine;
+use crate::rtbox::engine::{
+    ContainerEngine,
+    RtBoxCapturedOutput,
+    RtBoxContainerInfo,
+    RtBoxContainerSpec,
+    RtBoxExecOpts,
+    RtBoxImageInfo,
+    RtBoxMount,
+    RtBoxSecurityOpts,
+};
+use crate::rtbox::init;
+use crate::rtbox::toolbx::TOOLBX_LABEL;
+use crate::rtbox::tty::{RawTerminal, terminal_size};
+use crate::rtbox::volume::{self, RtBoxNamedVolume, RtBoxVolumeInfo};
 use crate::rtbox::engine::Result;
 use crate::rtbox::error::RtBoxError;
 
+/// Prefix of the endpoints of the API version we talk to
+const API_PREFIX: &str = "/v3";
+/// How much of an upload is read from disk at a time
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(Debug)]
 pub struct PodmanEngine {
     podman: Podman,
+    /// What podman-api talks to the engine over, it keeps its own to itself
+    transport: Transport,
 }
 
 impl PodmanEngine {
     pub fn new(podman_uri: &String) -> Self {
         Podman::new_versioned(podman_uri, ApiVersion::new(3, None, None))
             .map(|podman| Self {
-                podman: podman,
+                podman,
+                transport: engine_transport(podman_uri).unwrap(),
             }).unwrap()
     }
+
+    /// POST `body` to the libpod `endpoint`, podman-api only sends request bodies it holds in memory
+    async fn post_body(&self, command: &str, endpoint: &str, content_type: &str, body: hyper::Body) -> Result<Vec<u8>> {
+        let uri = self.transport.make_uri(&format!("{}{}", API_PREFIX, endpoint)).map_err(podman_error(command))?;
+        let request = hyper::Request::post(uri)
+            .header(hyper::header::HOST, "")
+            .header(hyper::header::CONTENT_TYPE, content_type)
+            .body(body)
+            .map_err(podman_error(command))?;
+
+        let response = self.transport.request(request).await.map_err(podman_error(command))?;
+
+        let status = response.status();
+        let contents = hyper::body::to_bytes(response.into_body()).await.map_err(podman_error(command))?;
+        if !status.is_success() {
+            return Err(podman_error(command)(format!("{}: {}", status, String::from_utf8_lossy(&contents).trim())));
+        }
+
+        Ok(contents.to_vec())
+    }
+
+    async fn create_exec(&self, name: &str, command: &[String], opts: &RtBoxExecOpts, attach: bool) -> Result<Exec> {
+        let mut exec_create_opts = ExecCreateOpts::builder()
+            .command(command)
+            .env(opts.env.clone())
+            .attach_stdin(attach && opts.interactive)
+            .attach_stdout(attach)
+            .attach_stderr(attach)
+            .tty(opts.tty);
+        if let Some(user) = &opts.user {
+            exec_create_opts = exec_create_opts.user(UserOpt::User(user.clone()));
+        }
+        if let Some(workdir) = &opts.workdir {
+            exec_create_opts = exec_create_opts.working_dir(workdir);
+        }
+
+        self.podman.containers()
+            .get(name)
+            .create_exec(&exec_create_opts.build())
+            .await
+            .map_err(podman_error("exec"))
+    }
+}
+
+/// The transport podman-api sets up for `uri`, `None` for schemes it does not support either
+fn engine_transport(uri: &str) -> Option<Transport> {
+    match uri.split_once("://")? {
+        ("unix", path) => Some(Transport::Unix {
+            client: hyper::Client::builder()
+                .pool_max_idle_per_host(0)
+                .build(get_unix_connector()),
+            path: PathBuf::from(path),
+        }),
+        ("tcp", host) | ("http", host) => Some(Transport::Tcp {
+            client: hyper::Client::builder().build(get_http_connector()),
+            host: Url::parse(&format!("tcp://{}", host)).ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// SIGKILL `pid` and every process below it, found through the parent pids in /proc
+fn kill_process_tree(pid: i32) {
+    let parents: Vec<(i32, i32)> = std::fs::read_dir("/proc")
+        .map(|entries| entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+            .filter_map(|child| {
+                let stat = std::fs::read_to_string(format!("/proc/{}/stat", child)).ok()?;
+                /* the command name is in parentheses and may contain spaces */
+                let parent = stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()?;
+                Some((child, parent))
+            })
+            .collect())
+        .unwrap_or_default();
+
+    let mut tree = vec![pid];
+    let mut index = 0;
+    while index < tree.len() {
+        let parent = tree[index];
+        tree.extend(parents.iter().filter(|(_, it)| *it == parent).map(|(child, _)| *child));
+        index += 1;
+    }
+
+    for pid in tree {
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+        }
+    }
 }
 
 #[async_trait]
 impl ContainerEngine for PodmanEngine {
-    async fn create(
-        &self,
-        name: &str,
-        image: &str,
-        entrypoint: Vec<String>,
-        env: Vec<(&str, String)>,
-        mounts: Vec<&(&str, &str, &str)>,
-    ) -> Result<Container> {
-        debug!("podman-create - name: {:?}", name);
-        debug!("FROM {:?}", image);
-        debug!("ENTRYPOINT {:?}", entrypoint);
-        debug!("ENV: {:?}", env);
-
-        let labels = vec![
-            ("com.github.containers.toolbox", "true")
-        ];
-
-        let mounts = mounts
+    async fn create(&self, spec: &RtBoxContainerSpec) -> Result<Container> {
+        debug!("podman-create - name: {:?}", spec.name);
+        debug!("FROM {:?}", spec.image);
+        debug!("ENTRYPOINT {:?}", spec.entrypoint);
+        debug!("ENV: {:?}", spec.env);
+
+        let mut labels = spec.labels.clone();
+        labels.insert(TOOLBX_LABEL.to_string(), "true".to_string());
+
+        let mounts = spec.mounts
             .iter()
             .map(|mount| ContainerMount{
-                source: Some(mount.0.to_string()),
-                destination: Some(mount.1.to_string()),
-                options: Some(mount.2.split(":").map(|it| it.to_string()).collect()),
+                source: Some(mount.source.clone()),
+                destination: Some(mount.destination.clone()),
+                options: Some(mount.options.clone()),
                 _type: None,
                 gid_mappings: None,
                 uid_mappings: None,
             })
             .collect::<Vec<ContainerMount>>();
+        let volumes = spec.volumes
+            .iter()
+            .map(|volume| NamedVolume {
+                name: Some(volume.name.clone()),
+                dest: Some(volume.destination.clone()),
+                is_anonymous: None,
+                options: None,
+            })
+            .collect::<Vec<NamedVolume>>();
 
-        let podman_create_opts = ContainerCreateOpts::builder()
-            .image(image.to_string())
-            .command(entrypoint)
-            .env(env)
+        let mut podman_create_opts = ContainerCreateOpts::builder()
+            .image(&spec.image)
+            .command(&spec.entrypoint)
+            .env(spec.env.clone())
             .mounts(mounts)
-            .hostname(format!("{}.host", name))
-            .name(name.to_string())
-            .selinux_opts(vec!["disable"])
-            .work_dir("/var/home/akdev")
+            .volumes(volumes)
+            .hostname(init::box_hostname(&spec.name))
+            .name(&spec.name)
+            .work_dir(&spec.workdir)
             .labels(labels)
+            /* keep-id would otherwise run init as our uid, it needs root to set up the box like toolbx does */
+            .user("root:root")
             .user_namespace(Namespace{
                 nsmode: Some("keep-id".to_string()),
                 value: None,
-            })
-            .build();
+            });
+
+        if spec.systemd {
+            podman_create_opts = podman_create_opts.systemd(SystemdEnabled::Always);
+        }
+        if spec.security.selinux_disabled {
+            podman_create_opts = podman_create_opts.selinux_opts(vec!["disable"]);
+        }
+        if !spec.security.drop_capabilities.is_empty() {
+            podman_create_opts = podman_create_opts.drop_capabilities(&spec.security.drop_capabilities);
+        }
+        if !spec.security.add_capabilities.is_empty() {
+            podman_create_opts = podman_create_opts.add_capabilities(&spec.security.add_capabilities);
+        }
+        if let Some(seccomp_profile) = &spec.security.seccomp_profile {
+            podman_create_opts = podman_create_opts.seccomp_profile_path(seccomp_profile);
+        }
+
+        let podman_create_opts = podman_create_opts.build();
         debug!("podman_create_opts: {:?}", podman_create_opts);
             
         self.podman.containers()
@@ -85,8 +243,6 @@ impl ContainerEngine for PodmanEngine {
 
     async fn list(&self, all: bool) -> Result<Vec<ListContainer>> {
 
-        const TOOLBX_LABEL: &str = "com.github.containers.toolbox";
-
         let podman_list_response = self.podman
             .containers()
             .list(
@@ -108,23 +264,595 @@ impl ContainerEngine for PodmanEngine {
     async fn rm(&self, name: String, force: bool) -> Result<()> {
         debug!("podman-rm - name: {:?}, force: {:?}", name, force);
 
-        Ok::<(), RtBoxError>(())
+        self.podman.containers()
+            .get(&name)
+            .delete(&ContainerDeleteOpts::builder().force(force).build())
+            .await
+            .map_err(podman_error("rm"))
     }
 
-    async fn start(&self, name: String) -> Result<Container> {
+    async fn start(&self, name: String) -> Result<()> {
         debug!("podman-start - name: {:?}", name);
 
-        Ok::<Container, RtBoxError>(Container::new(self.podman.clone(), ""))
+        self.podman.containers()
+            .get(&name)
+            .start(None)
+            .await
+            .map_err(podman_error("start"))
+    }
+
+    async fn stop(&self, name: String) -> Result<()> {
+        debug!("podman-stop - name: {:?}", name);
+
+        self.podman.containers()
+            .get(&name)
+            .stop(&ContainerStopOpts::builder().ignore(true).build())
+            .await
+            .map_err(podman_error("stop"))
+    }
+
+    async fn rename(&self, name: String, new_name: String) -> Result<()> {
+        debug!("podman-rename - name: {:?}, new_name: {:?}", name, new_name);
+
+        self.podman.containers()
+            .get(&name)
+            .rename(&new_name)
+            .await
+            .map_err(podman_error("rename"))
     }
 
-    async fn inspect(&self, name: String) -> Result<Container> {
+    async fn inspect(&self, name: String) -> Result<RtBoxContainerInfo> {
         debug!("podman-inspect - name: {:?}", name);
 
-        Ok::<Container, RtBoxError>(Container::new(self.podman.clone(), ""))
+        let inspect_response = self.podman.containers()
+            .get(&name)
+            .inspect()
+            .await
+            .map_err(podman_error("inspect"))?;
+
+        let mut entrypoint: Vec<String> = inspect_response.path.into_iter().collect();
+        entrypoint.extend(inspect_response.args.unwrap_or_default());
+
+        let (labels, env) = inspect_response.config
+            .map(|config| (config.labels.unwrap_or_default(), config.env.unwrap_or_default()))
+            .unwrap_or_default();
+
+        let security = inspect_response.host_config
+            .map(|host_config| {
+                let security_opts = host_config.security_opt.unwrap_or_default();
+                RtBoxSecurityOpts {
+                    selinux_disabled: security_opts.iter().any(|opt| opt == "label=disable"),
+                    drop_capabilities: host_config.cap_drop.unwrap_or_default(),
+                    add_capabilities: host_config.cap_add.unwrap_or_default(),
+                    seccomp_profile: security_opts.iter()
+                        .find_map(|opt| opt.strip_prefix("seccomp="))
+                        .map(|profile| profile.to_string()),
+                }
+            })
+            .unwrap_or_default();
+
+        let inspect_mounts = inspect_response.mounts.unwrap_or_default();
+        let volumes = inspect_mounts
+            .iter()
+            .filter(|mount| mount.type_.as_deref() == Some("volume"))
+            .map(|mount| RtBoxNamedVolume {
+                name: mount.name.clone().unwrap_or_default(),
+                destination: mount.destination.clone().unwrap_or_default(),
+            })
+            .collect();
+        let mounts = inspect_mounts
+            .into_iter()
+            .filter(|mount| mount.type_.as_deref() == Some("bind"))
+            .map(|mount| {
+                let mut options = mount.options.unwrap_or_default();
+                if mount.rw == Some(false) {
+                    options.push("ro".to_string());
+                }
+                if let Some(propagation) = mount.propagation.filter(|it| !it.is_empty()) {
+                    options.push(propagation);
+                }
+
+                RtBoxMount {
+                    source: mount.source.unwrap_or_default(),
+                    destination: mount.destination.unwrap_or_default(),
+                    options,
+                }
+            })
+            .collect();
+
+        let (running, pid, status) = inspect_response.state
+            .map(|state| (state.running.unwrap_or(false), state.pid.unwrap_or(0), state.status.unwrap_or_default()))
+            .unwrap_or_default();
+
+        Ok(RtBoxContainerInfo {
+            id: inspect_response.id.unwrap_or_default(),
+            name: inspect_response.name.unwrap_or(name),
+            image: inspect_response.image_name
+                .or(inspect_response.image)
+                .unwrap_or_default(),
+            image_digest: inspect_response.image_digest.unwrap_or_default(),
+            entrypoint,
+            labels: labels.into_iter().collect(),
+            mounts,
+            volumes,
+            env,
+            security,
+            created: inspect_response.created
+                .map(|created| created.to_rfc3339())
+                .unwrap_or_default(),
+            status,
+            running,
+            pid,
+        })
     }
 
-    async fn exec(&self, name: String, command: Vec<String>, tty: bool, interactive: bool) {
-        debug!("podman-exec - name: {:?}, tty: {:?}, interactive: {:?}", name, tty, interactive);
+    async fn exec(&self, name: String, command: Vec<String>, opts: RtBoxExecOpts) -> Result<i64> {
+        debug!("podman-exec - name: {:?}, opts: {:?}", name, opts);
         debug!("command: {:?}", command);
+
+        let exec = self.create_exec(&name, &command, &opts, true).await?;
+
+        let exec_start_opts = ExecStartOpts::builder()
+            .tty(opts.tty)
+            .build();
+
+        /* restored when this goes out of scope, after the session is over */
+        let _raw_terminal = if opts.tty && opts.interactive {
+            RawTerminal::new()
+        } else {
+            None
+        };
+
+        if let Some(multiplexer) = exec.start(&exec_start_opts).await.map_err(podman_error("exec"))? {
+            if opts.tty {
+                if let Some((width, height)) = terminal_size() {
+                    let _ = exec.resize(width, height).await;
+                }
+            }
+
+            let (reader, mut writer) = multiplexer.split();
+            futures::pin_mut!(reader);
+
+            let mut stdin = tokio::io::stdin();
+            let mut stdout = tokio::io::stdout();
+            let mut stderr = tokio::io::stderr();
+            let mut stdin_buffer = [0u8; 4096];
+            let mut stdin_open = opts.interactive;
+            let mut window_change = signal(SignalKind::window_change())
+                .map_err(podman_error("exec"))?;
+            let deadline = tokio::time::sleep(opts.timeout.unwrap_or_default());
+            futures::pin_mut!(deadline);
+            let mut timed_out = false;
+
+            loop {
+                tokio::select! {
+                    chunk = reader.next() => match chunk {
+                        Some(Ok(TtyChunk::StdOut(bytes))) => {
+                            let _ = stdout.write_all(&bytes).await;
+                            let _ = stdout.flush().await;
+                        },
+                        Some(Ok(TtyChunk::StdErr(bytes))) => {
+                            let _ = stderr.write_all(&bytes).await;
+                            let _ = stderr.flush().await;
+                        },
+                        Some(Ok(TtyChunk::StdIn(_))) => {},
+                        Some(Err(err)) => return Err(podman_error("exec")(err)),
+                        None => break,
+                    },
+                    read = stdin.read(&mut stdin_buffer), if stdin_open => match read {
+                        Ok(0) | Err(_) => {
+                            stdin_open = false;
+                            let _ = writer.close().await;
+                        },
+                        Ok(count) => {
+                            let _ = writer.write_all(&stdin_buffer[..count]).await;
+                        },
+                    },
+                    _ = window_change.recv(), if opts.tty => {
+                        if let Some((width, height)) = terminal_size() {
+                            let _ = exec.resize(width, height).await;
+                        }
+                    },
+                    _ = &mut deadline, if opts.timeout.is_some() && !timed_out => {
+                        timed_out = true;
+                        warn!("command timed out after {:?}, killing it", opts.timeout.unwrap());
+                        /* podman reports the host pid of the exec session */
+                        match exec.inspect().await.map(|exec_inspect| exec_inspect["Pid"].as_i64()) {
+                            Ok(Some(pid)) if pid > 0 => kill_process_tree(pid as i32),
+                            _ => warn!("could not find the process of the timed out command"),
+                        }
+                    },
+                }
+            }
+        }
+
+        let exec_inspect = exec.inspect()
+            .await
+            .map_err(podman_error("exec"))?;
+        debug!("exec_inspect: {:?}", exec_inspect);
+
+        Ok(exec_inspect["ExitCode"].as_i64().unwrap_or(-1))
+    }
+
+    async fn exec_detached(&self, name: String, command: Vec<String>, opts: RtBoxExecOpts) -> Result<String> {
+        debug!("podman-exec-detached - name: {:?}, opts: {:?}", name, opts);
+        debug!("command: {:?}", command);
+
+        let exec = self.create_exec(&name, &command, &opts, false).await?;
+        let exec_start_opts = ExecStartOpts::builder()
+            .detach(true)
+            .tty(opts.tty)
+            .build();
+        exec.start(&exec_start_opts).await.map_err(podman_error("exec"))?;
+
+        Ok(exec.id().to_string())
+    }
+
+    async fn exec_output(&self, name: String, command: Vec<String>, opts: RtBoxExecOpts) -> Result<RtBoxCapturedOutput> {
+        debug!("podman-exec-output - name: {:?}, opts: {:?}", name, opts);
+        debug!("command: {:?}", command);
+
+        let opts = RtBoxExecOpts { tty: false, interactive: false, ..opts };
+        let exec = self.create_exec(&name, &command, &opts, true).await?;
+        let exec_start_opts = ExecStartOpts::builder()
+            .tty(false)
+            .build();
+
+        let mut stdout = vec![];
+        let mut stderr = vec![];
+        if let Some(multiplexer) = exec.start(&exec_start_opts).await.map_err(podman_error("exec"))? {
+            let (reader, _writer) = multiplexer.split();
+            futures::pin_mut!(reader);
+
+            while let Some(chunk) = reader.next().await {
+                match chunk.map_err(podman_error("exec"))? {
+                    TtyChunk::StdOut(bytes) => stdout.extend(bytes),
+                    TtyChunk::StdErr(bytes) => stderr.extend(bytes),
+                    TtyChunk::StdIn(_) => {},
+                }
+            }
+        }
+
+        let exec_inspect = exec.inspect()
+            .await
+            .map_err(podman_error("exec"))?;
+
+        Ok(RtBoxCapturedOutput {
+            return_code: exec_inspect["ExitCode"].as_i64().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+        })
+    }
+
+    async fn inspect_image(&self, image: String) -> Result<RtBoxImageInfo> {
+        debug!("podman-inspect-image - image: {:?}", image);
+
+        let image_data = self.podman.images()
+            .get(&image)
+            .inspect()
+            .await
+            .map_err(podman_error("inspect_image"))?;
+
+        Ok(RtBoxImageInfo {
+            id: image_data.id.unwrap_or_default(),
+            digest: image_data.digest.unwrap_or_default(),
+            names: image_data.repo_tags.unwrap_or_default(),
+            labels: image_data.labels.unwrap_or_default().into_iter().collect(),
+        })
+    }
+
+    async fn read_file(&self, name: String, path: String) -> Result<Vec<u8>> {
+        debug!("podman-read-file - name: {:?}, path: {:?}", name, path);
+
+        let archive_bytes = self.podman.containers()
+            .get(&name)
+            .copy_from(&path)
+            .try_concat()
+            .await
+            .map_err(podman_error("read_file"))?;
+
+        /* podman hands us a tar archive with the single requested file in it */
+        let mut archive = tar::Archive::new(archive_bytes.as_slice());
+        let mut entries = archive.entries().map_err(podman_error("read_file"))?;
+        let mut entry = entries
+            .next()
+            .ok_or_else(|| RtBoxError {
+                command: Some("read_file".to_string()),
+                message: Some(format!("{} not found in {}", path, name)),
+                root_cause: Some("empty archive".to_string()),
+            })?
+            .map_err(podman_error("read_file"))?;
+
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents).map_err(podman_error("read_file"))?;
+
+        Ok(contents)
+    }
+
+    async fn pull_image(&self, image: String) -> Result<()> {
+        debug!("podman-pull - image: {:?}", image);
+
+        let images = self.podman.images();
+        let pull_opts = PullOpts::builder().reference(&image).build();
+        let mut reports = images.pull(&pull_opts);
+
+        while let Some(report) = reports.next().await {
+            let report = report.map_err(podman_error("pull_image"))?;
+            if let Some(err) = report.error {
+                return Err(RtBoxError {
+                    command: Some("pull_image".to_string()),
+                    message: Some(format!("could not pull {}", image)),
+                    root_cause: Some(err),
+                });
+            }
+            if let Some(progress) = report.stream {
+                debug!("podman-pull - {}", progress.trim_end());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn import_path(&self, name: String, path: String, archive: Vec<u8>) -> Result<()> {
+        debug!("podman-import-path - name: {:?}, path: {:?}, size: {}", name, path, archive.len());
+
+        self.podman.containers()
+            .get(&name)
+            .copy_to(&path, hyper::Body::from(archive))
+            .await
+            .map_err(podman_error("import_path"))
+    }
+
+    async fn copy_path(&self, source: String, target: String, path: String) -> Result<()> {
+        debug!("podman-copy-path - source: {:?}, target: {:?}, path: {:?}", source, target, path);
+
+        let source_container = self.podman.containers().get(&source);
+        let target_container = self.podman.containers().get(&target);
+        let parent = Path::new(&path).parent().unwrap_or(Path::new("/")).to_path_buf();
+
+        /* the archive of a directory holds it under its own name, it is extracted into the parent */
+        let mut archive = Box::pin(source_container.copy_from(&path));
+        let first_chunk = match archive.next().await {
+            Some(Err(podman_api::Error::Fault { code, .. })) if code == hyper::StatusCode::NOT_FOUND => {
+                debug!("{} does not exist in {}, nothing to copy", path, source);
+                return Ok(());
+            },
+            Some(chunk) => chunk.map_err(podman_error("copy_path"))?,
+            None => return Ok(()),
+        };
+
+        let (mut sender, body) = hyper::Body::channel();
+        let forward = async move {
+            sender.send_data(first_chunk.into()).await.map_err(podman_error("copy_path"))?;
+            while let Some(chunk) = archive.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        sender.abort();
+                        return Err(podman_error("copy_path")(err));
+                    },
+                };
+                sender.send_data(chunk.into()).await.map_err(podman_error("copy_path"))?;
+            }
+            Ok(())
+        };
+
+        let (forwarded, copied) = futures::join!(forward, target_container.copy_to(&parent, body));
+        forwarded?;
+        copied.map_err(podman_error("copy_path"))
+    }
+
+    async fn commit(&self, name: String, image: String, cleanup: Vec<String>, changes: Vec<String>) -> Result<()> {
+        debug!("podman-commit - name: {:?}, image: {:?}, changes: {:?}", name, image, changes);
+
+        let (repo, tag) = split_image_reference(&image);
+        let container = self.podman.containers().get(&name);
+
+        if cleanup.is_empty() {
+            return container
+                .commit(&ContainerCommitOpts::builder().repo(repo).tag(tag).changes(&changes).pause(true).build())
+                .await
+                .map_err(podman_error("commit"));
+        }
+
+        let snapshot_repo = format!("localhost/rtbox-commit-{}", name);
+        let scratch_name = format!("{}-commit", name);
+
+        container
+            .commit(&ContainerCommitOpts::builder().repo(&snapshot_repo).tag("latest").pause(true).build())
+            .await
+            .map_err(podman_error("commit"))?;
+
+        let committed = async {
+            let scratch_create_opts = ContainerCreateOpts::builder()
+                .image(format!("{}:latest", snapshot_repo))
+                .name(&scratch_name)
+                .command(&cleanup)
+                .user("root")
+                .build();
+            self.podman.containers()
+                .create(&scratch_create_opts)
+                .await
+                .map_err(podman_error("commit"))?;
+
+            let scratch = self.podman.containers().get(&scratch_name);
+            scratch.start(None).await.map_err(podman_error("commit"))?;
+            scratch.wait(&Default::default()).await.map_err(podman_error("commit"))?;
+
+            let exit_code = scratch.inspect()
+                .await
+                .map_err(podman_error("commit"))?
+                .state
+                .and_then(|state| state.exit_code)
+                .unwrap_or_default();
+            if exit_code != 0 {
+                return Err(RtBoxError {
+                    command: Some("commit".to_string()),
+                    message: Some(format!("cleaning up the snapshot of {} failed", name)),
+                    root_cause: Some(format!("cleanup exited with {}", exit_code)),
+                });
+            }
+
+            scratch.commit(&ContainerCommitOpts::builder().repo(repo).tag(tag).changes(&changes).build())
+                .await
+                .map_err(podman_error("commit"))
+        }.await;
+
+        /* the snapshot and scratch container are only needed while committing */
+        if let Err(err) = self.podman.containers().get(&scratch_name).remove().await {
+            warn!("could not remove {}: {}", scratch_name, err);
+        }
+        if let Err(err) = self.podman.images().get(format!("{}:latest", snapshot_repo)).remove().await {
+            warn!("could not remove {}: {}", snapshot_repo, err);
+        }
+
+        committed
+    }
+
+    async fn untag_image(&self, image: String) -> Result<()> {
+        debug!("podman-untag - image: {:?}", image);
+
+        let (repo, tag) = split_image_reference(&image);
+        self.podman.images()
+            .get(&image)
+            .untag(&ImageTagOpts::builder().repo(repo).tag(tag).build())
+            .await
+            .map_err(podman_error("untag_image"))
+    }
+
+    async fn save_image(&self, image: String, output: PathBuf) -> Result<()> {
+        debug!("podman-save - image: {:?}, output: {:?}", image, output);
+
+        let mut file = tokio::fs::File::create(&output).await.map_err(podman_error("save_image"))?;
+        let podman_image = self.podman.images().get(&image);
+        let mut export = podman_image.export(&ImageExportOpts::builder().build());
+        while let Some(chunk) = export.next().await {
+            file.write_all(&chunk.map_err(podman_error("save_image"))?)
+                .await
+                .map_err(podman_error("save_image"))?;
+        }
+
+        file.sync_all().await.map_err(podman_error("save_image"))
+    }
+
+    async fn load_image(&self, input: PathBuf, range: Range<u64>) -> Result<Vec<String>> {
+        debug!("podman-load - input: {:?}, range: {:?}", input, range);
+
+        let mut file = tokio::fs::File::open(&input).await.map_err(podman_error("load_image"))?;
+        file.seek(SeekFrom::Start(range.start)).await.map_err(podman_error("load_image"))?;
+        let chunks = futures::stream::unfold(file.take(range.end - range.start), |mut archive| async move {
+            let mut buffer = vec![0u8; UPLOAD_CHUNK_SIZE];
+            match archive.read(&mut buffer).await {
+                Ok(0) => None,
+                Ok(count) => {
+                    buffer.truncate(count);
+                    Some((Ok(buffer), archive))
+                },
+                Err(err) => Some((Err(err), archive)),
+            }
+        });
+
+        let report = self.post_body("load_image", "/libpod/images/load", "application/x-tar", hyper::Body::wrap_stream(chunks)).await?;
+        serde_json::from_slice::<ImageLoadReport>(&report)
+            .map(|report| report.names.unwrap_or_default())
+            .map_err(podman_error("load_image"))
+    }
+
+    async fn dangling_images(&self) -> Result<Vec<RtBoxImageInfo>> {
+        debug!("podman-dangling-images");
+
+        let images = self.podman.images()
+            .list(
+                &ImageListOpts::builder()
+                    .filter(vec![
+                        ImageListFilter::Dangling(true),
+                        ImageListFilter::LabelKey(TOOLBX_LABEL.to_string()),
+                    ])
+                    .build()
+            )
+            .await
+            .map_err(podman_error("dangling_images"))?;
+
+        Ok(images.into_iter()
+            .filter(|image| image.containers.unwrap_or_default() == 0)
+            .map(|image| RtBoxImageInfo {
+                id: image.id.unwrap_or_default(),
+                digest: image.digest.unwrap_or_default(),
+                names: image.names.unwrap_or_default(),
+                labels: image.labels.unwrap_or_default().into_iter().collect(),
+            })
+            .collect())
+    }
+
+    async fn remove_image(&self, image: String) -> Result<()> {
+        debug!("podman-remove-image - image: {:?}", image);
+
+        self.podman.images()
+            .get(&image)
+            .delete()
+            .await
+            .map_err(podman_error("remove_image"))
+    }
+
+    async fn create_volume(&self, name: String, labels: BTreeMap<String, String>) -> Result<()> {
+        debug!("podman-create-volume - name: {:?}, labels: {:?}", name, labels);
+
+        let exists = self.podman.volumes()
+            .get(&name)
+            .exists()
+            .await
+            .map_err(podman_error("create_volume"))?;
+        if exists {
+            return Ok(());
+        }
+
+        self.podman.volumes()
+            .create(&VolumeCreateOpts::builder().name(&name).labels(labels).build())
+            .await
+            .map(|_| ())
+            .map_err(podman_error("create_volume"))
+    }
+
+    async fn list_volumes(&self) -> Result<Vec<RtBoxVolumeInfo>> {
+        debug!("podman-list-volumes");
+
+        let volumes = self.podman.volumes()
+            .list(
+                &VolumeListOpts::builder()
+                    .filter(vec![VolumeListFilter::LabelKey(volume::volume_label())])
+                    .build()
+            )
+            .await
+            .map_err(podman_error("list_volumes"))?;
+
+        Ok(volumes.into_iter()
+            .filter_map(|it| RtBoxVolumeInfo::from_labels(&it.name, &it.labels.into_iter().collect(), &it.mountpoint))
+            .collect())
+    }
+
+    async fn remove_volume(&self, name: String, force: bool) -> Result<()> {
+        debug!("podman-remove-volume - name: {:?}, force: {:?}", name, force);
+
+        let volume = self.podman.volumes().get(&name);
+        match force {
+            true => volume.remove().await,
+            false => volume.delete().await,
+        }.map_err(podman_error("remove_volume"))
+    }
+}
+
+/// Split an image reference into repository and tag, podman defaults the tag to `latest`
+fn split_image_reference(image: &str) -> (&str, &str) {
+    match image.rsplit_once(':') {
+        Some((repo, tag)) if !tag.contains('/') => (repo, tag),
+        _ => (image, "latest"),
+    }
+}
+
+fn podman_error<E: ToString>(command: &str) -> impl Fn(E) -> RtBoxError + '_ {
+    move |err| RtBoxError {
+        command: Some(command.to_string()),
+        message: Some(err.to_string()),
+        root_cause: Some("podman".to_string()),
     }
 }
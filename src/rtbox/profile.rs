@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+use crate::rtbox::config::RtBoxConfig;
+use crate::rtbox::engine::{RtBoxMount, RtBoxSecurityOpts};
+use crate::rtbox::init::RtBoxInitMode;
+
+/// Capabilities kept by containers created with the restricted profile,
+/// these are the minimum needed by `rtbox init` to set up the user account
+const RESTRICTED_CAPABILITIES: [&str; 7] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+];
+
+/// Host paths systemd sets up on its own when it runs as PID 1 of a box,
+/// binding the host's over them would let the box's journal and units clobber the host's
+const SYSTEMD_OWNED_PATHS: [&str; 7] = [
+    "/run/systemd/journal",
+    "/run/systemd/resolve",
+    "/run/systemd/sessions",
+    "/run/systemd/system",
+    "/run/systemd/users",
+    "/tmp",
+    "/var/log/journal",
+];
+
+/// Defines how much of the host a rtbox container can see and do
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RtBoxProfile {
+    /// Full host integration, the host root is available at /run/host
+    #[default]
+    Default,
+    /// Only an allowlist of host paths is mounted, SELinux and seccomp stay enabled
+    Restricted,
+}
+
+impl RtBoxProfile {
+    pub fn name(&self) -> &str {
+        match self {
+            RtBoxProfile::Default => "default",
+            RtBoxProfile::Restricted => "restricted",
+        }
+    }
+
+    /// Whether boxes get a host helper running commands on the host for them
+    pub fn allows_host_exec(&self) -> bool {
+        *self == RtBoxProfile::Default
+    }
+
+    pub fn mounts(&self, config: &RtBoxConfig, home_dir: &str, rtbox_exe: &str, init_mode: RtBoxInitMode) -> Vec<RtBoxMount> {
+        let mounts = match self {
+            RtBoxProfile::Default => {
+                let host_mounts = [
+                    /* (source, destination, options) */
+                    ("/", "/run/host", "rbind"),
+                    ("/etc/machine-id", "/etc/machine-id", "rbind:ro"),
+                    ("/run/libvirt", "/run/libvirt", "rbind"),
+                    ("/run/systemd/journal", "/run/systemd/journal", "rbind"),
+                    ("/run/systemd/resolve", "/run/systemd/resolve", "rbind"),
+                    ("/run/systemd/sessions", "/run/systemd/sessions", "rbind"),
+                    ("/run/systemd/system", "/run/systemd/system", "rbind"),
+                    ("/run/systemd/users", "/run/systemd/users", "rbind"),
+                    ("/run/udev/data", "/run/udev/data", "rbind"),
+                    ("/run/udev/tags", "/run/udev/tags", "rbind"),
+                    ("/tmp", "/tmp", "rbind"),
+                    ("/var/lib/flatpak", "/var/lib/flatpak", "rbind:ro"),
+                    ("/var/lib/libvirt", "/var/lib/libvirt", "rbind"),
+                    ("/var/lib/systemd/coredump", "/var/lib/systemd/coredump", "rbind:ro"),
+                    ("/var/log/journal", "/var/log/journal", "rbind:ro"),
+                    ("/var/mnt", "/var/mnt", "rbind:rslave"),
+                    (home_dir, home_dir, "rbind")
+                ];
+
+                host_mounts.iter()
+                    .map(|mount| RtBoxMount::new(mount.0, mount.1, mount.2))
+                    .collect::<Vec<RtBoxMount>>()
+            },
+            RtBoxProfile::Restricted => {
+                /* without the host root we need to expose our own binary so it can act as init */
+                let rtbox_mount = RtBoxMount::new(
+                    rtbox_exe,
+                    &format!("/run/host{}", rtbox_exe),
+                    "rbind:ro",
+                );
+
+                let mut allowed_mounts: Vec<RtBoxMount> = config.restricted_mounts
+                    .iter()
+                    .filter_map(|mount| {
+                        let parsed_mount = RtBoxMount::parse(mount);
+                        if parsed_mount.is_none() {
+                            warn!("ignoring invalid restricted mount: {}", mount);
+                        }
+                        parsed_mount
+                    })
+                    .collect();
+                allowed_mounts.push(rtbox_mount);
+
+                allowed_mounts
+            },
+        };
+        let available_host_mounts = mounts.len();
+        debug!("host_mounts: {:?}", mounts);
+
+        let mounts: Vec<_> = mounts.into_iter()
+            .filter(|mount| Path::new(&mount.source).exists())
+            .filter(|mount| init_mode != RtBoxInitMode::Systemd || !SYSTEMD_OWNED_PATHS.contains(&mount.destination.as_str()))
+            .collect();
+        debug!("active mounts: {:?}", mounts);
+        info!("detected {}/{} active host mounts", mounts.len(), available_host_mounts);
+
+        mounts
+    }
+
+    pub fn security_opts(&self, config: &RtBoxConfig) -> RtBoxSecurityOpts {
+        match self {
+            RtBoxProfile::Default => RtBoxSecurityOpts {
+                selinux_disabled: true,
+                ..RtBoxSecurityOpts::default()
+            },
+            RtBoxProfile::Restricted => RtBoxSecurityOpts {
+                selinux_disabled: false,
+                drop_capabilities: vec!["ALL".to_string()],
+                add_capabilities: RESTRICTED_CAPABILITIES
+                    .iter()
+                    .map(|cap| cap.to_string())
+                    .collect(),
+                seccomp_profile: config.seccomp_profile.clone(),
+            },
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Deserialize};
+
+/// Where each service writes its output inside the container
+pub const SERVICE_LOG_DIR: &str = "/var/log/rtbox/services";
+
+/// How long a service gets to exit after SIGTERM before it is killed
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum RtBoxRestartPolicy {
+    #[default]
+    No,
+    OnFailure,
+    Always,
+}
+
+/// A long running process init keeps alive, declared per box in the configuration file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxService {
+    pub name: String,
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Runs as the box user when unset
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub restart: RtBoxRestartPolicy,
+    /// Services that have to be started before this one
+    #[serde(default)]
+    pub after: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RtBoxServiceState {
+    Stopped,
+    Running,
+    Exited,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxServiceStatus {
+    pub name: String,
+    pub state: RtBoxServiceState,
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    pub exit_code: Option<i32>,
+}
+
+/// Order `services` so each one comes after the services it depends on
+pub fn start_order(services: &[RtBoxService]) -> Result<Vec<String>, String> {
+    let by_name: BTreeMap<&str, &RtBoxService> = services.iter()
+        .map(|service| (service.name.as_str(), service))
+        .collect();
+    if by_name.len() != services.len() {
+        return Err("service names have to be unique".to_string());
+    }
+
+    fn visit<'a>(
+        name: &'a str,
+        by_name: &BTreeMap<&'a str, &'a RtBoxService>,
+        visiting: &mut Vec<&'a str>,
+        order: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if order.iter().any(|it| it == name) {
+            return Ok(());
+        }
+        if visiting.contains(&name) {
+            return Err(format!("services depend on each other: {} -> {}", visiting.join(" -> "), name));
+        }
+
+        let service = by_name.get(name).ok_or(format!("unknown service {}", name))?;
+        if service.command.is_empty() {
+            return Err(format!("service {} has no command", name));
+        }
+        visiting.push(name);
+        for dependency in &service.after {
+            if !by_name.contains_key(dependency.as_str()) {
+                return Err(format!("service {} depends on unknown service {}", name, dependency));
+            }
+            visit(dependency, by_name, visiting, order)?;
+        }
+        visiting.pop();
+
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    let mut order = vec![];
+    for service in services {
+        visit(&service.name, &by_name, &mut vec![], &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Look up the uid, gid and home of `user` in the container's passwd database
+fn lookup_user(user: &str) -> Result<(u32, u32, String), String> {
+    let user_name = CString::new(user).map_err(|err| err.to_string())?;
+    unsafe {
+        let passwd = libc::getpwnam(user_name.as_ptr());
+        if passwd.is_null() {
+            return Err(format!("user {} does not exist", user));
+        }
+        let home = std::ffi::CStr::from_ptr((*passwd).pw_dir).to_string_lossy().to_string();
+        Ok(((*passwd).pw_uid, (*passwd).pw_gid, home))
+    }
+}
+
+struct RtBoxSupervisedService {
+    service: RtBoxService,
+    child: Option<Child>,
+    /// Cleared by stop so an exit is not treated as a crash
+    wanted: bool,
+    state: RtBoxServiceState,
+    restarts: u32,
+    exit_code: Option<i32>,
+}
+
+impl RtBoxSupervisedService {
+    fn status(&self) -> RtBoxServiceStatus {
+        RtBoxServiceStatus {
+            name: self.service.name.clone(),
+            state: self.state,
+            pid: self.child.as_ref().map(|child| child.id()),
+            restarts: self.restarts,
+            exit_code: self.exit_code,
+        }
+    }
+}
+
+pub struct RtBoxSupervisor {
+    services: Mutex<BTreeMap<String, RtBoxSupervisedService>>,
+    order: Vec<String>,
+    /// Environment the host last sent, services add their own on top
+    env: Mutex<BTreeMap<String, String>>,
+    /// uid and gid services run as when they do not name a user
+    default_user: Option<(u32, u32)>,
+    log_dir: PathBuf,
+}
+
+impl RtBoxSupervisor {
+    pub fn new(services: &[RtBoxService], default_user: Option<(u32, u32)>, log_dir: PathBuf) -> Result<Self, String> {
+        let order = start_order(services)?;
+        let services = services.iter()
+            .map(|service| (service.name.clone(), RtBoxSupervisedService {
+                service: service.clone(),
+                child: None,
+                wanted: false,
+                state: RtBoxServiceState::Stopped,
+                restarts: 0,
+                exit_code: None,
+            }))
+            .collect();
+
+        Ok(Self {
+            services: Mutex::new(services),
+            order,
+            env: Mutex::new(BTreeMap::new()),
+            default_user,
+            log_dir,
+        })
+    }
+
+    /// Applies to services started after the call, running ones keep their environment
+    pub fn set_env(&self, env: BTreeMap<String, String>) {
+        *self.env.lock().unwrap() = env;
+    }
+
+    pub fn log_path(&self, name: &str) -> PathBuf {
+        self.log_dir.join(format!("{}.log", name))
+    }
+
+    fn spawn(&self, service: &RtBoxService) -> Result<Child, String> {
+        fs::create_dir_all(&self.log_dir).map_err(|err| err.to_string())?;
+        let log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path(&service.name))
+            .map_err(|err| format!("could not open log for service {}: {}", service.name, err))?;
+        let log_file_err = log_file.try_clone().map_err(|err| err.to_string())?;
+
+        let mut command = Command::new(&service.command[0]);
+        command.args(&service.command[1..])
+            .envs(self.env.lock().unwrap().iter())
+            .envs(&service.env)
+            .stdin(Stdio::null())
+            .stdout(log_file)
+            .stderr(log_file_err)
+            .process_group(0);
+
+        let user = match &service.user {
+            Some(user) => Some(lookup_user(user).map(|(uid, gid, home)| {
+                command.env("HOME", home).env("USER", user);
+                (uid, gid)
+            })?),
+            None => self.default_user,
+        };
+        if let Some((uid, gid)) = user {
+            command.uid(uid).gid(gid);
+        }
+
+        command.spawn().map_err(|err| format!("could not start service {}: {}", service.name, err))
+    }
+
+    fn start_locked(
+        &self,
+        services: &mut BTreeMap<String, RtBoxSupervisedService>,
+        name: &str,
+    ) -> Result<RtBoxServiceStatus, String> {
+        let dependencies = services.get(name)
+            .ok_or(format!("unknown service {}", name))?
+            .service.after.clone();
+        for dependency in dependencies {
+            self.start_locked(services, &dependency)?;
+        }
+
+        let supervised = services.get_mut(name).unwrap();
+        supervised.wanted = true;
+        if supervised.child.is_none() {
+            info!("starting service {}", name);
+            match self.spawn(&supervised.service) {
+                Ok(child) => {
+                    supervised.child = Some(child);
+                    supervised.state = RtBoxServiceState::Running;
+                    supervised.exit_code = None;
+                },
+                Err(message) => {
+                    supervised.state = RtBoxServiceState::Failed;
+                    return Err(message);
+                },
+            }
+        }
+
+        Ok(supervised.status())
+    }
+
+    /// Start `name` and the services it depends on
+    pub fn start(&self, name: &str) -> Result<RtBoxServiceStatus, String> {
+        let mut services = self.services.lock().unwrap();
+        self.start_locked(&mut services, name)
+    }
+
+    /// Start every service in dependency order, failures are logged and do not stop the others
+    pub fn start_all(&self) {
+        for name in &self.order {
+            if let Err(message) = self.start(name) {
+                error!("{}", message);
+            }
+        }
+    }
+
+    /// Stop `name` with SIGTERM, falling back to SIGKILL when it does not exit in time
+    pub fn stop(&self, name: &str) -> Result<RtBoxServiceStatus, String> {
+        let child = {
+            let mut services = self.services.lock().unwrap();
+            let supervised = services.get_mut(name).ok_or(format!("unknown service {}", name))?;
+            supervised.wanted = false;
+            supervised.child.take()
+        };
+
+        let exit_code = child.map(|mut child| {
+            info!("stopping service {}", name);
+            unsafe {
+                libc::kill(-(child.id() as i32), libc::SIGTERM);
+            }
+
+            let start_time = Instant::now();
+            loop {
+                match child.try_wait() {
+                    Ok(Some(status)) => break status.code(),
+                    Ok(None) if start_time.elapsed() < STOP_TIMEOUT => thread::sleep(Duration::from_millis(100)),
+                    _ => {
+                        warn!("service {} did not stop in time, killing it", name);
+                        unsafe {
+                            libc::kill(-(child.id() as i32), libc::SIGKILL);
+                        }
+                        break child.wait().ok().and_then(|status| status.code());
+                    },
+                }
+            }
+        });
+
+        let mut services = self.services.lock().unwrap();
+        let supervised = services.get_mut(name).unwrap();
+        supervised.state = RtBoxServiceState::Stopped;
+        if let Some(exit_code) = exit_code {
+            supervised.exit_code = exit_code;
+        }
+        Ok(supervised.status())
+    }
+
+    /// Stop every service, dependents first
+    pub fn stop_all(&self) {
+        for name in self.order.iter().rev() {
+            if let Err(message) = self.stop(name) {
+                error!("{}", message);
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<RtBoxServiceStatus> {
+        let services = self.services.lock().unwrap();
+        self.order.iter()
+            .filter_map(|name| services.get(name))
+            .map(|supervised| supervised.status())
+            .collect()
+    }
+
+    /// The last `lines` lines written by `name`
+    pub fn logs(&self, name: &str, lines: usize) -> Result<String, String> {
+        if !self.services.lock().unwrap().contains_key(name) {
+            return Err(format!("unknown service {}", name));
+        }
+
+        let contents = fs::read_to_string(self.log_path(name)).unwrap_or_default();
+        let all_lines: Vec<&str> = contents.lines().collect();
+        let first_line = all_lines.len().saturating_sub(lines);
+        Ok(all_lines[first_line..].iter().map(|line| format!("{}\n", line)).collect())
+    }
+
+    /// Collect exited services and restart the ones whose policy asks for it
+    pub fn poll(&self) {
+        let mut services = self.services.lock().unwrap();
+        let mut restarts = vec![];
+
+        for (name, supervised) in services.iter_mut() {
+            let status = match supervised.child.as_mut().map(|child| child.try_wait()) {
+                Some(Ok(Some(status))) => status,
+                _ => continue,
+            };
+
+            supervised.child = None;
+            supervised.exit_code = status.code();
+            supervised.state = if status.success() {
+                RtBoxServiceState::Exited
+            } else {
+                RtBoxServiceState::Failed
+            };
+            info!("service {} exited with {}", name, status);
+
+            let restart = match supervised.service.restart {
+                RtBoxRestartPolicy::No => false,
+                RtBoxRestartPolicy::OnFailure => !status.success(),
+                RtBoxRestartPolicy::Always => true,
+            };
+            if supervised.wanted && restart {
+                supervised.restarts += 1;
+                restarts.push(name.clone());
+            }
+        }
+
+        for name in restarts {
+            if let Err(message) = self.start_locked(&mut services, &name) {
+                error!("could not restart service {}: {}", name, message);
+            }
+        }
+    }
+
+    /// Poll the services forever on a background thread
+    pub fn supervise(self: &Arc<Self>) {
+        let supervisor = self.clone();
+        thread::spawn(move || loop {
+            supervisor.poll();
+            thread::sleep(Duration::from_millis(1000));
+        });
+    }
+}
+
+/// Properties `systemd_status` reads from `systemctl show`
+pub const SYSTEMD_STATUS_PROPERTIES: &str = "Id,ActiveState,SubState,MainPID,NRestarts,ExecMainStatus";
+
+/// The unit systemd knows a service of a systemd box by, `.service` is implied like systemctl does
+pub fn systemd_unit(name: &str) -> String {
+    if name.contains('.') {
+        name.to_string()
+    } else {
+        format!("{}.service", name)
+    }
+}
+
+/// The state of a unit from its `ActiveState` and `SubState`
+fn systemd_state(active_state: &str, sub_state: &str) -> RtBoxServiceState {
+    match (active_state, sub_state) {
+        ("failed", _) => RtBoxServiceState::Failed,
+        ("active", "exited") => RtBoxServiceState::Exited,
+        ("active", _) | ("reloading", _) | ("activating", _) => RtBoxServiceState::Running,
+        _ => RtBoxServiceState::Stopped,
+    }
+}
+
+/// Read a unit's status from `systemctl show --property=SYSTEMD_STATUS_PROPERTIES`
+pub fn systemd_status(output: &str) -> Option<RtBoxServiceStatus> {
+    let properties: BTreeMap<&str, &str> = output.lines()
+        .filter_map(|line| line.split_once('='))
+        .collect();
+    let name = properties.get("Id")?;
+
+    Some(RtBoxServiceStatus {
+        name: name.strip_suffix(".service").unwrap_or(name).to_string(),
+        state: systemd_state(properties.get("ActiveState")?, properties.get("SubState").unwrap_or(&"")),
+        pid: properties.get("MainPID").and_then(|pid| pid.parse().ok()).filter(|pid| *pid != 0),
+        restarts: properties.get("NRestarts").and_then(|restarts| restarts.parse().ok()).unwrap_or(0),
+        exit_code: properties.get("ExecMainStatus").and_then(|code| code.parse().ok()),
+    })
+}
+
+/// Read the services of `systemctl list-units --type=service --all --plain --no-legend`
+pub fn systemd_services(output: &str) -> Vec<RtBoxServiceStatus> {
+    output.lines()
+        .filter_map(|line| {
+            /* UNIT LOAD ACTIVE SUB DESCRIPTION, failed units may be prefixed with a marker */
+            let mut columns = line.split_whitespace().skip_while(|column| !column.contains('.'));
+            let unit = columns.next()?;
+            let _load = columns.next()?;
+            let active_state = columns.next()?;
+            let sub_state = columns.next()?;
+
+            Some(RtBoxServiceStatus {
+                name: unit.strip_suffix(".service").unwrap_or(unit).to_string(),
+                state: systemd_state(active_state, sub_state),
+                pid: None,
+                restarts: 0,
+                exit_code: None,
+            })
+        })
+        .collect()
+}
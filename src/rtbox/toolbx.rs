@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+use std::time;
+
+use crate::rtbox::engine::RtBoxContainerInfo;
+
+/// Label set by both toolbx and rtbox on every container they create
+pub const TOOLBX_LABEL: &str = "com.github.containers.toolbox";
+
+/// Older toolbx releases only recognise images carrying this label
+pub const TOOLBX_LEGACY_LABEL: &str = "com.github.debarshiray.toolbox";
+
+/// Mount destinations toolbx adds for its own use, these must not be carried
+/// over when a toolbx container is adopted by rtbox
+const TOOLBX_INTERNAL_MOUNTS: [&str; 3] = [
+    "/usr/bin/toolbox",
+    "/run/.toolboxenv",
+    "/etc/profile.d/toolbox.sh",
+];
+
+/// How long `toolbox init-container` gets to finish setting up a container,
+/// this is the same timeout toolbx itself uses
+const TOOLBX_INIT_TIMEOUT: time::Duration = time::Duration::from_secs(25);
+
+/// Returns true when the container entrypoint is `toolbox ... init-container ...`
+pub fn is_toolbx_entrypoint(entrypoint: &[String]) -> bool {
+    let is_toolbox_binary = entrypoint
+        .first()
+        .and_then(|binary| Path::new(binary).file_name())
+        .map(|binary| binary == "toolbox")
+        .unwrap_or(false);
+
+    is_toolbox_binary && entrypoint.iter().any(|arg| arg == "init-container")
+}
+
+/// Returns true for mounts that toolbx manages internally
+pub fn is_internal_mount(destination: &str) -> bool {
+    TOOLBX_INTERNAL_MOUNTS.contains(&destination)
+}
+
+/// Toolbx execs every command through capsh so the session does not inherit
+/// the container capabilities, we do the same to keep behaviour identical
+pub fn wrap_command(command: Vec<String>) -> Vec<String> {
+    let mut wrapped_command = vec![
+        "capsh".to_string(),
+        "--caps=".to_string(),
+        "--".to_string(),
+        "-c".to_string(),
+        "exec \"$@\"".to_string(),
+        "/bin/sh".to_string(),
+    ];
+    wrapped_command.extend(command);
+
+    wrapped_command
+}
+
+/// Stamp file written by `toolbox init-container` once the container is ready
+fn init_stamp_path(container: &RtBoxContainerInfo) -> Option<PathBuf> {
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").ok()?;
+
+    Some(
+        Path::new(&runtime_dir)
+            .join("toolbox")
+            .join(format!("container-initialized-{}", container.pid))
+    )
+}
+
+/// Wait for `toolbox init-container` to signal that it has finished, returns
+/// false if the container did not become ready in time
+pub async fn wait_for_init(container: &RtBoxContainerInfo) -> bool {
+    let stamp_path = match init_stamp_path(container) {
+        Some(stamp_path) => stamp_path,
+        None => {
+            warn!("XDG_RUNTIME_DIR is not set, cannot wait for toolbx initialization");
+            return true;
+        },
+    };
+    debug!("waiting for toolbx init stamp: {:?}", stamp_path);
+
+    let start_time = time::Instant::now();
+    while start_time.elapsed() < TOOLBX_INIT_TIMEOUT {
+        if stamp_path.exists() {
+            return true;
+        }
+        tokio::time::sleep(time::Duration::from_millis(250)).await;
+    }
+
+    false
+}
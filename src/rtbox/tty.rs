@@ -0,0 +1,79 @@
+use libc::{
+    c_int,
+    termios,
+    winsize,
+    cfmakeraw,
+    ioctl,
+    isatty,
+    tcgetattr,
+    tcsetattr,
+    STDIN_FILENO,
+    STDOUT_FILENO,
+    TCSANOW,
+    TIOCGWINSZ,
+};
+
+pub fn is_terminal(fd: c_int) -> bool {
+    unsafe {
+        isatty(fd) == 1
+    }
+}
+
+/// Returns true when both stdin and stdout are attached to a terminal
+pub fn is_interactive() -> bool {
+    is_terminal(STDIN_FILENO) && is_terminal(STDOUT_FILENO)
+}
+
+/// Size of the controlling terminal as (width, height)
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut size: winsize = unsafe {
+        std::mem::zeroed()
+    };
+
+    let result = unsafe {
+        ioctl(STDOUT_FILENO, TIOCGWINSZ, &mut size)
+    };
+
+    if result == 0 && size.ws_col > 0 && size.ws_row > 0 {
+        Some((size.ws_col as usize, size.ws_row as usize))
+    } else {
+        None
+    }
+}
+
+/// Puts stdin in raw mode for as long as this value is alive, the original
+/// terminal settings are restored when it is dropped
+pub struct RawTerminal {
+    original: termios,
+}
+
+impl RawTerminal {
+    pub fn new() -> Option<Self> {
+        if !is_terminal(STDIN_FILENO) {
+            return None;
+        }
+
+        unsafe {
+            let mut original: termios = std::mem::zeroed();
+            if tcgetattr(STDIN_FILENO, &mut original) != 0 {
+                return None;
+            }
+
+            let mut raw = original;
+            cfmakeraw(&mut raw);
+            if tcsetattr(STDIN_FILENO, TCSANOW, &raw) != 0 {
+                return None;
+            }
+
+            Some(Self { original })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe {
+            tcsetattr(STDIN_FILENO, TCSANOW, &self.original);
+        }
+    }
+}
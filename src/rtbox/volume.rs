@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::rtbox::labels;
+
+/// Engine volumes of every rtbox volume start with this
+const VOLUME_PREFIX: &str = "rtbox-";
+/// Shared volumes are named `rtbox-shared-<name>` and per box ones `rtbox-box-<box>_<name>`
+const SHARED_PREFIX: &str = "shared-";
+const BOX_PREFIX: &str = "box-";
+/// Ends the box name of a per box volume, a `_` in the box name itself is doubled
+const OWNER_SEPARATOR: char = '_';
+
+/// Volumes holding a preserved container path are named after the path
+const PRESERVED_PREFIX: &str = "preserve";
+
+const VOLUME_LABEL: &str = "volume";
+const VOLUME_BOX_LABEL: &str = "volume-box";
+
+/// A rtbox managed volume mounted into a box, kept by the engine when the box is recreated
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxVolumeConfig {
+    pub name: String,
+    /// Where the volume is mounted, a leading `~/` is the home of the box user
+    pub path: String,
+    /// Mount the same volume into every box declaring it instead of one per box
+    #[serde(default)]
+    pub shared: bool,
+    /// Engine volume already holding it when that is not the one `resolve` names,
+    /// per box volumes of a renamed box keep the name of the box they were created for
+    #[serde(skip)]
+    pub volume: Option<String>,
+}
+
+/// Engine independent view of a rtbox managed volume
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxVolumeInfo {
+    pub name: String,
+    /// Name of the volume in the container engine
+    pub volume: String,
+    /// Box the volume belongs to, shared volumes have none
+    pub owner: Option<String>,
+    pub mountpoint: String,
+}
+
+/// A named volume as the container engine mounts it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RtBoxNamedVolume {
+    pub name: String,
+    pub destination: String,
+}
+
+/// Name of the engine volume holding `name`, shared when it has no owner
+pub fn volume_name(name: &str, owner: Option<&str>) -> String {
+    match owner {
+        Some(owner) => format!(
+            "{}{}{}{}{}",
+            VOLUME_PREFIX,
+            BOX_PREFIX,
+            owner.replace(OWNER_SEPARATOR, "__"),
+            OWNER_SEPARATOR,
+            name,
+        ),
+        None => format!("{}{}{}", VOLUME_PREFIX, SHARED_PREFIX, name),
+    }
+}
+
+/// The volume name and box of a per box engine volume, `None` when `volume` is not one
+fn split_box_volume(volume: &str) -> Option<(String, String)> {
+    let mut chars = volume.strip_prefix(VOLUME_PREFIX)?.strip_prefix(BOX_PREFIX)?.chars();
+    let mut owner = String::new();
+    while let Some(it) = chars.next() {
+        match it {
+            OWNER_SEPARATOR if chars.as_str().starts_with(OWNER_SEPARATOR) => {
+                owner.push(OWNER_SEPARATOR);
+                chars.next();
+            },
+            OWNER_SEPARATOR => {
+                let name = chars.as_str();
+                return Some((name.to_string(), owner)).filter(|(name, owner)| !name.is_empty() && !owner.is_empty());
+            },
+            it => owner.push(it),
+        }
+    }
+
+    None
+}
+
+/// The per box volume keeping the container path `path` across upgrades,
+/// `/` becomes `.` and anything but letters, digits and `-` is escaped so no two paths share a volume
+pub fn preserved_volume(path: &str) -> RtBoxVolumeConfig {
+    let escaped: String = path.trim_end_matches('/')
+        .chars()
+        .map(|it| match it {
+            '/' => ".".to_string(),
+            it if it.is_ascii_alphanumeric() || it == '-' => it.to_string(),
+            it => it.to_string().bytes().map(|byte| format!("_{:02x}", byte)).collect(),
+        })
+        .collect();
+
+    RtBoxVolumeConfig {
+        name: format!("{}{}", PRESERVED_PREFIX, escaped),
+        path: path.to_string(),
+        shared: false,
+        volume: None,
+    }
+}
+
+/// Labels identifying an engine volume as the rtbox volume `name`
+pub fn volume_labels(name: &str, owner: Option<&str>) -> BTreeMap<String, String> {
+    let mut volume_labels = BTreeMap::from([(labels::label(VOLUME_LABEL), name.to_string())]);
+    if let Some(owner) = owner {
+        volume_labels.insert(labels::label(VOLUME_BOX_LABEL), owner.to_string());
+    }
+
+    volume_labels
+}
+
+/// Label every rtbox managed volume carries, used to tell them apart from other engine volumes
+pub fn volume_label() -> String {
+    labels::label(VOLUME_LABEL)
+}
+
+impl RtBoxVolumeInfo {
+    /// Read back an engine volume, `None` for volumes rtbox did not create
+    pub fn from_labels(volume: &str, volume_labels: &BTreeMap<String, String>, mountpoint: &str) -> Option<Self> {
+        Some(Self {
+            name: volume_labels.get(&labels::label(VOLUME_LABEL))?.clone(),
+            volume: volume.to_string(),
+            owner: volume_labels.get(&labels::label(VOLUME_BOX_LABEL)).cloned(),
+            mountpoint: mountpoint.to_string(),
+        })
+    }
+}
+
+impl RtBoxVolumeConfig {
+    /// Parse a `NAME:PATH` volume specification, the path must be absolute or start with `~/`
+    pub fn parse(spec: &str, shared: bool) -> Option<Self> {
+        let (name, path) = spec.split_once(':')?;
+        let valid_name = name.chars().next().map(|it| it.is_ascii_alphanumeric()).unwrap_or(false)
+            && name.chars().all(|it| it.is_ascii_alphanumeric() || "_.-".contains(it));
+        if !valid_name || !(path.starts_with('/') || path.starts_with("~/")) {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_string(),
+            path: path.to_string(),
+            shared,
+            volume: None,
+        })
+    }
+
+    /// The engine volume and where it is mounted in `owner`, a box using `home` as its home
+    pub fn resolve(&self, owner: &str, home: &str) -> RtBoxNamedVolume {
+        let destination = match self.path.strip_prefix("~/") {
+            Some(relative) => Path::new(home).join(relative).display().to_string(),
+            None => self.path.clone(),
+        };
+
+        RtBoxNamedVolume {
+            name: self.volume.clone().unwrap_or_else(|| volume_name(&self.name, Some(owner).filter(|_| !self.shared))),
+            destination,
+        }
+    }
+
+    /// The volume `resolve` mounted into `owner`, `None` for engine volumes rtbox does not manage,
+    /// a per box volume created for another name of `owner` keeps pointing at that engine volume
+    pub fn from_named_volume(volume: &RtBoxNamedVolume, owner: &str) -> Option<Self> {
+        if let Some(name) = volume.name.strip_prefix(VOLUME_PREFIX).and_then(|name| name.strip_prefix(SHARED_PREFIX)) {
+            return Some(Self {
+                name: name.to_string(),
+                path: volume.destination.clone(),
+                shared: true,
+                volume: None,
+            });
+        }
+
+        let (name, created_for) = split_box_volume(&volume.name)?;
+        Some(Self {
+            name,
+            path: volume.destination.clone(),
+            shared: false,
+            volume: Some(volume.name.clone()).filter(|_| created_for != owner),
+        })
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs;
+
+use crate::rtbox::archive::{self, RtBoxArchiveManifest, ARCHIVE_VERSION};
+use crate::rtbox::engine::{RtBoxContainerInfo, RtBoxMount};
+use crate::rtbox::labels::{self, RtBoxLabels};
+use crate::rtbox::profile::RtBoxProfile;
+
+
+fn test_manifest() -> RtBoxArchiveManifest {
+    RtBoxArchiveManifest {
+        archive_version: ARCHIVE_VERSION,
+        name: "rtbox-archive-test".to_string(),
+        image: "localhost/rtbox-save-rtbox-archive-test:latest".to_string(),
+        container: RtBoxContainerInfo {
+            mounts: vec![
+                RtBoxMount::new("/home/old/src", "/src", "rbind"),
+                RtBoxMount::new("/srv/data", "/srv/data", "rbind"),
+            ],
+            labels: RtBoxLabels {
+                labels_version: labels::LABELS_VERSION,
+                version: "0.1.0".to_string(),
+                image: "registry.fedoraproject.org/fedora:39".to_string(),
+                image_digest: None,
+                home: Some("/home/old/boxes/dev".to_string()),
+                profile: RtBoxProfile::Default,
+                env: vec![],
+                hooks_hash: labels::hooks_hash(&[], &[]),
+                expires_at: None,
+            }.to_labels(),
+            ..RtBoxContainerInfo::default()
+        },
+        home: "/home/old".to_string(),
+        uid: 1000,
+        gid: 1000,
+        exports: vec![],
+    }
+}
+
+#[test]
+fn archive_round_trips_manifest_and_image() {
+
+    let path = std::env::temp_dir().join(format!("rtbox-archive-{}.tar", std::process::id()));
+    let image_path = std::env::temp_dir().join(format!("rtbox-archive-image-{}.tar", std::process::id()));
+    let manifest = test_manifest();
+    fs::write(&image_path, b"image archive").unwrap();
+
+    archive::write(&path, &manifest, &image_path).unwrap();
+    let (read_manifest, image_archive) = archive::read(&path).unwrap();
+    let contents = fs::read(&path).unwrap();
+
+    assert_eq!(read_manifest, manifest);
+    assert_eq!(&contents[image_archive.start as usize..image_archive.end as usize], b"image archive");
+
+    fs::write(&path, b"").unwrap();
+    assert!(archive::read(&path).is_err());
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&image_path).unwrap();
+}
+
+#[test]
+fn archive_rebases_home_paths() {
+
+    let container = test_manifest().rebased_container("/var/home/new");
+    let recorded = RtBoxLabels::from_labels(&container.labels).unwrap();
+
+    assert_eq!(container.mounts[0].source, "/var/home/new/src");
+    assert_eq!(container.mounts[1].source, "/srv/data");
+    assert_eq!(recorded.home.as_deref(), Some("/var/home/new/boxes/dev"));
+    assert_eq!(archive::rebase_path("/home/older", "/home/old", "/var/home/new"), "/home/older");
+}
+
+#[test]
+fn ownership_hook_extracts_at_root() {
+
+    let hook = archive::ownership_hook(1000, 1000, 1001, 1001).unwrap();
+    let mut hook_archive = tar::Archive::new(hook.as_slice());
+    let paths: Vec<String> = hook_archive.entries().unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+        .collect();
+
+    assert_eq!(paths, vec![
+        "etc/",
+        "etc/rtbox/",
+        "etc/rtbox/pre-init.d/",
+        "etc/rtbox/pre-init.d/00-rtbox-load-ownership",
+    ]);
+}
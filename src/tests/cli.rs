@@ -0,0 +1,16 @@
+use crate::rtbox::cli::parse_duration;
+
+
+#[test]
+fn cli_parses_durations() {
+
+    assert_eq!(parse_duration("90"), Ok(90));
+    assert_eq!(parse_duration("30m"), Ok(30 * 60));
+    assert_eq!(parse_duration("12h"), Ok(12 * 60 * 60));
+    assert_eq!(parse_duration("7d"), Ok(7 * 24 * 60 * 60));
+    assert_eq!(parse_duration("2w"), Ok(14 * 24 * 60 * 60));
+    assert!(parse_duration("7y").is_err());
+    assert!(parse_duration("d").is_err());
+    assert!(parse_duration("99999999999999999d").is_err());
+    assert_eq!(parse_duration(&u64::MAX.to_string()), Ok(u64::MAX));
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::rtbox::control::{
+    self,
+    RtBoxControlError,
+    RtBoxControlHandler,
+    RtBoxControlResponse,
+    METHOD_NOT_FOUND,
+    PARSE_ERROR,
+};
+
+
+struct EchoHandler;
+
+impl RtBoxControlHandler for EchoHandler {
+    fn handle(&self, method: &str, params: Value) -> Result<Value, RtBoxControlError> {
+        match method {
+            "echo" => Ok(params),
+            _ => Err(RtBoxControlError::method_not_found(method)),
+        }
+    }
+}
+
+fn serve_echo(name: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("rtbox-control-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let socket_path = dir.join(control::CONTROL_SOCKET);
+    control::serve(&socket_path, None, Arc::new(EchoHandler)).unwrap();
+    (dir, socket_path)
+}
+
+#[test]
+fn control_socket_is_private() {
+
+    let (dir, socket_path) = serve_echo("private");
+
+    assert_eq!(fs::metadata(&socket_path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn control_socket_round_trip() {
+
+    let (dir, socket_path) = serve_echo("call");
+
+    let result = control::call(&socket_path, "echo", json!({ "name": "db" })).await;
+    assert_eq!(result.unwrap(), json!({ "name": "db" }));
+
+    let error = control::call(&socket_path, "missing", json!({})).await.unwrap_err();
+    assert_eq!(error.message, Some("unknown method missing".to_string()));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn control_socket_speaks_jsonrpc() {
+
+    let (dir, socket_path) = serve_echo("jsonrpc");
+    let mut stream = UnixStream::connect(&socket_path).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut response = |request: &str| -> RtBoxControlResponse {
+        writeln!(stream, "{}", request).unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    };
+
+    let echoed = response(r#"{"jsonrpc": "2.0", "id": 7, "method": "echo", "params": [1, 2]}"#);
+    assert_eq!(echoed.jsonrpc, "2.0");
+    assert_eq!(echoed.id, 7);
+    assert_eq!(echoed.result, Some(json!([1, 2])));
+
+    let missing = response(r#"{"jsonrpc": "2.0", "id": 8, "method": "missing"}"#);
+    assert_eq!(missing.error.unwrap().code, METHOD_NOT_FOUND);
+
+    let garbage = response("not json");
+    assert_eq!(garbage.error.unwrap().code, PARSE_ERROR);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use crate::rtbox::config::RtBoxConfig;
+use crate::rtbox::distro::{resolve_image, RtBoxDistro, RtBoxReleaseFormat};
+use crate::rtbox::os_release;
+
+
+fn host(contents: &str) -> BTreeMap<String, String> {
+    os_release::parse(contents)
+}
+
+#[test]
+fn distro_defaults_to_host() {
+
+    let config = RtBoxConfig::default();
+    let fedora_host = host("NAME=\"Fedora Linux\"\nID=fedora\nVERSION_ID=37\n");
+
+    let image = resolve_image(&config, None, None, Some(&fedora_host)).unwrap();
+
+    assert_eq!(image, "registry.fedoraproject.org/fedora-toolbox:37");
+}
+
+#[test]
+fn distro_unsupported_host_falls_back_to_fedora() {
+
+    let config = RtBoxConfig::default();
+    let gentoo_host = host("ID=gentoo\nVERSION_ID=2.14\n");
+
+    let image = resolve_image(&config, None, None, Some(&gentoo_host)).unwrap();
+
+    assert_eq!(image, "registry.fedoraproject.org/fedora-toolbox:38");
+}
+
+#[test]
+fn distro_explicit_distro_and_release() {
+
+    let config = RtBoxConfig::default();
+    let fedora_host = host("ID=fedora\nVERSION_ID=37\n");
+
+    assert_eq!(
+        resolve_image(&config, Some("rhel"), Some("8.7"), Some(&fedora_host)).unwrap(),
+        "registry.access.redhat.com/ubi8/toolbox:8.7"
+    );
+    assert_eq!(
+        resolve_image(&config, None, Some("f39"), Some(&fedora_host)).unwrap(),
+        "registry.fedoraproject.org/fedora-toolbox:39"
+    );
+    assert_eq!(
+        resolve_image(&config, Some("ubuntu"), None, Some(&fedora_host)).unwrap(),
+        "quay.io/toolbx/ubuntu-toolbox:22.04"
+    );
+}
+
+#[test]
+fn distro_unsupported_combinations() {
+
+    let config = RtBoxConfig::default();
+
+    assert!(resolve_image(&config, Some("gentoo"), None, None).is_err());
+    assert!(resolve_image(&config, Some("fedora"), Some("9.1"), None).is_err());
+    assert!(resolve_image(&config, Some("fedora"), Some("ff39"), None).is_err());
+    assert!(resolve_image(&config, Some("arch"), Some("2023"), None).is_err());
+}
+
+#[test]
+fn distro_from_config() {
+
+    let mut config = RtBoxConfig::default();
+    config.distros.insert("debian".to_string(), RtBoxDistro {
+        image: "quay.io/toolbx-images/debian-toolbox:{release}".to_string(),
+        release_format: RtBoxReleaseFormat::Number,
+        default_release: "12".to_string(),
+    });
+
+    assert_eq!(
+        resolve_image(&config, Some("debian"), None, None).unwrap(),
+        "quay.io/toolbx-images/debian-toolbox:12"
+    );
+}
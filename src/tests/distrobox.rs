@@ -0,0 +1,64 @@
+use crate::rtbox::distrobox::parse_assemble;
+
+
+const ASSEMBLE_FILE: &str = r#"
+# shared definitions
+[common]
+additional_packages="git vim"
+volume=/srv/data:/srv/data:ro
+
+[dev]
+include=common
+image=registry.fedoraproject.org/fedora-toolbox:38
+additional_packages="htop"
+init_hooks="touch /etc/dev-ready"
+init_hooks=echo done
+pre_init_hooks='dnf -y upgrade'
+exported_apps="code"
+exported_bins="/usr/bin/htop /usr/bin/git"
+home=/var/home/user/boxes/dev
+nvidia=true
+"#;
+
+#[test]
+fn distrobox_parse_sections() {
+
+    let definitions = parse_assemble(ASSEMBLE_FILE).unwrap();
+    let names: Vec<&str> = definitions.iter().map(|it| it.name.as_str()).collect();
+
+    assert_eq!(names, vec!["common", "dev"]);
+}
+
+#[test]
+fn distrobox_parse_accumulates_and_includes() {
+
+    let definitions = parse_assemble(ASSEMBLE_FILE).unwrap();
+    let dev = &definitions[1];
+
+    assert_eq!(dev.image.as_deref(), Some("registry.fedoraproject.org/fedora-toolbox:38"));
+    assert_eq!(dev.additional_packages, vec!["git", "vim", "htop"]);
+    assert_eq!(dev.init_hooks, vec!["touch /etc/dev-ready", "echo done"]);
+    assert_eq!(dev.pre_init_hooks, vec!["dnf -y upgrade"]);
+    assert_eq!(dev.exported_bins, vec!["/usr/bin/htop", "/usr/bin/git"]);
+    assert_eq!(dev.home.as_deref(), Some("/var/home/user/boxes/dev"));
+}
+
+#[test]
+fn distrobox_create_opts() {
+
+    let definitions = parse_assemble(ASSEMBLE_FILE).unwrap();
+    let create_opts = definitions[1].create_opts();
+
+    assert_eq!(create_opts.mounts.len(), 1);
+    assert_eq!(create_opts.mounts[0].destination, "/srv/data");
+    assert_eq!(create_opts.mounts[0].options, vec!["rbind", "ro"]);
+    assert_eq!(create_opts.home.as_deref(), Some("/var/home/user/boxes/dev"));
+}
+
+#[test]
+fn distrobox_parse_errors() {
+
+    assert!(parse_assemble("image=fedora").is_err());
+    assert!(parse_assemble("[a]\ninclude=a").is_err());
+    assert!(parse_assemble("[a]\nnot a key value").is_err());
+}
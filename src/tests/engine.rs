@@ -1,9 +1,29 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use podman_api::Podman;
+use podman_api::api::Container;
+use podman_api::models::ListContainer;
+
+use crate::rtbox::archive::{self, RtBoxArchiveManifest, ARCHIVE_VERSION};
 use crate::rtbox::config::RtBoxConfig;
 use crate::rtbox::engine::{
     RtBox,
     MockContainerEngine,
+    RtBoxContainerInfo,
+    RtBoxCapturedOutput,
+    RtBoxCreateOpts,
+    RtBoxMount,
     RtBoxEngine,
+    RtBoxImageInfo,
+    RtBoxKind,
+    RtBoxRunOpts,
 };
+use crate::rtbox::error::RtBoxError;
+use crate::rtbox::init::{self, RtBoxInitMode, RtBoxInitStatus};
+use crate::rtbox::labels::{self, RtBoxLabels};
+use crate::rtbox::profile::RtBoxProfile;
+use crate::rtbox::volume::{self, RtBoxNamedVolume, RtBoxVolumeConfig};
 
 
 #[tokio::test]
@@ -11,19 +31,198 @@ async fn rtbox_create_with_image () {
 
     let config: RtBoxConfig = RtBoxConfig::default();
     let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+
+    container_engine.expect_create()
+        .withf(|spec| {
+            /* unset labels are written empty so the ones of a committed image do not stick */
+            spec.name == "alex" && spec.image == "test-image:latest"
+                && spec.labels.get(&labels::label("expires-at")) == Some(&String::new())
+                && RtBoxLabels::from_labels(&spec.labels).map(|recorded| recorded.expires_at.is_none()).unwrap_or(false)
+        })
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "test-id")));
+
     let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
 
-    let _created_rtbox = rtbox_engine.create(
+    let created_rtbox = rtbox_engine.create(
         "alex",
         "test-image:latest",
+        &RtBoxCreateOpts::default(),
     ).await;
 
-    let _expected_rtbox = RtBox {
+    let expected_rtbox = RtBox {
+        id: "test-id".to_string(),
         name: "alex".to_string(),
         image: "test-image:latest".to_string(),
+        status: false.to_string(),
+    };
+
+    assert_eq!(created_rtbox.unwrap(), expected_rtbox);
+}
+
+#[tokio::test]
+async fn rtbox_create_refuses_overflowing_ttl() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+    container_engine.expect_create().never();
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let create_opts = RtBoxCreateOpts {
+        ttl: Some(u64::MAX),
+        ..RtBoxCreateOpts::default()
     };
 
-    let _ = container_engine.expect_create();
+    let created = rtbox_engine.create("rtbox-ttl-test", "test-image:latest", &create_opts).await;
+
+    assert!(created.unwrap_err().message.unwrap().starts_with("cannot expire"));
+}
+
+#[tokio::test]
+async fn rtbox_create_restricted_profile () {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+
+    container_engine.expect_create()
+        .withf(|spec| {
+            !spec.security.selinux_disabled
+                && spec.security.drop_capabilities == vec!["ALL".to_string()]
+                && spec.mounts.iter().all(|mount| mount.destination != "/run/host")
+                && !spec.entrypoint.contains(&"--command-not-found".to_string())
+        })
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "test-id")));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+
+    let created_rtbox = rtbox_engine.create(
+        "alex",
+        "test-image:latest",
+        &RtBoxCreateOpts {
+            profile: RtBoxProfile::Restricted,
+            ..RtBoxCreateOpts::default()
+        },
+    ).await;
+
+    assert!(created_rtbox.is_ok());
+}
+
+#[tokio::test]
+async fn rtbox_create_systemd_init () {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+
+    container_engine.expect_create()
+        .withf(|spec| {
+            let info = RtBoxContainerInfo {
+                entrypoint: spec.entrypoint.clone(),
+                ..RtBoxContainerInfo::default()
+            };
+            spec.systemd && info.kind() == RtBoxKind::Rtbox && info.init_mode() == RtBoxInitMode::Systemd
+                && spec.mounts.iter().all(|mount| mount.destination != "/tmp" && mount.destination != "/var/log/journal")
+        })
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "test-id")));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+
+    let created_rtbox = rtbox_engine.create(
+        "alex",
+        "test-image:latest",
+        &RtBoxCreateOpts {
+            init_mode: RtBoxInitMode::Systemd,
+            ..RtBoxCreateOpts::default()
+        },
+    ).await;
+
+    assert!(created_rtbox.is_ok());
+}
+
+#[tokio::test]
+async fn rtbox_create_records_labels () {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+    let create_opts = RtBoxCreateOpts {
+        profile: RtBoxProfile::Restricted,
+        home: Some(std::env::temp_dir().join(format!("rtbox-labels-{}", std::process::id())).display().to_string()),
+        init_hooks: vec!["echo hello".to_string()],
+        ttl: Some(60),
+        ..RtBoxCreateOpts::default()
+    };
+    let expected_home = create_opts.home.clone();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo { digest: "sha256:abc".to_string(), ..RtBoxImageInfo::default() }));
+    container_engine.expect_create()
+        .withf(move |spec| {
+            let recorded = RtBoxLabels::from_labels(&spec.labels).unwrap();
+            recorded.image == "test-image:latest"
+                && recorded.image_digest.as_deref() == Some("sha256:abc")
+                && recorded.home == expected_home
+                && recorded.profile == RtBoxProfile::Restricted
+                && recorded.hooks_hash == labels::hooks_hash(&[], &["echo hello".to_string()])
+                && recorded.hooks_hash != labels::hooks_hash(&["echo hello".to_string()], &[])
+                && recorded.expires_at.map(|expires_at| expires_at > init::unix_time()).unwrap_or(false)
+        })
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "test-id")));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let created_rtbox = rtbox_engine.create("rtbox-labels", "test-image:latest", &create_opts).await;
+
+    assert!(created_rtbox.is_ok());
+
+    std::fs::remove_dir_all(create_opts.home.unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn rtbox_create_mounts_volumes () {
+
+    let config = RtBoxConfig {
+        volumes: vec![
+            RtBoxVolumeConfig::parse("cargo-registry:~/.cargo/registry", true).unwrap(),
+            RtBoxVolumeConfig::parse("dnf:/var/cache/dnf", true).unwrap(),
+        ],
+        ..RtBoxConfig::default()
+    };
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+    let create_opts = RtBoxCreateOpts {
+        volumes: vec![RtBoxVolumeConfig::parse("dnf:/var/cache/dnf", false).unwrap()],
+        ..RtBoxCreateOpts::default()
+    };
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+    /* the box shares the host home so the global volume below ~/ is left out */
+    container_engine.expect_create_volume()
+        .withf(|name, labels| name == "rtbox-box-rtbox-volumes_dnf" && *labels == volume::volume_labels("dnf", Some("rtbox-volumes")))
+        .times(1)
+        .returning(|_, _| Ok(()));
+    container_engine.expect_create()
+        .withf(|spec| {
+            spec.volumes.len() == 1
+                && spec.volumes[0].name == "rtbox-box-rtbox-volumes_dnf"
+                && spec.volumes[0].destination == "/var/cache/dnf"
+        })
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "test-id")));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let created_rtbox = rtbox_engine.create("rtbox-volumes", "test-image:latest", &create_opts).await;
+
+    assert!(created_rtbox.is_ok());
 }
 
 #[tokio::test]
@@ -53,3 +252,812 @@ async fn rtbox_list_all() {
     let _ = rtbox_engine.list(Some(true)).await;
 
 }
+
+fn toolbx_container_info() -> RtBoxContainerInfo {
+    RtBoxContainerInfo {
+        id: "toolbx-id".to_string(),
+        name: "fedora-toolbox-38".to_string(),
+        image: "registry.fedoraproject.org/fedora-toolbox:38".to_string(),
+        entrypoint: vec![
+            "toolbox".to_string(),
+            "--log-level".to_string(), "debug".to_string(),
+            "init-container".to_string(),
+        ],
+        mounts: vec![
+            RtBoxMount::new("/usr/bin/toolbox", "/usr/bin/toolbox", "rbind:ro"),
+            RtBoxMount::new("/srv/data", "/srv/data", "rbind"),
+        ],
+        ..RtBoxContainerInfo::default()
+    }
+}
+
+#[tokio::test]
+async fn rtbox_adopt_toolbx_container() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(toolbx_container_info()));
+    container_engine.expect_rename()
+        .withf(|name, new_name| name == "fedora-toolbox-38" && new_name == "fedora-toolbox-38-toolbx")
+        .times(1)
+        .returning(|_, _| Ok(()));
+    container_engine.expect_create()
+        .withf(|spec| {
+            spec.image == "registry.fedoraproject.org/fedora-toolbox:38"
+                && spec.entrypoint[1] == "init"
+                && spec.mounts.iter().any(|mount| mount.destination == "/srv/data")
+                && spec.mounts.iter().all(|mount| mount.destination != "/usr/bin/toolbox")
+        })
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "rtbox-id")));
+    container_engine.expect_rm()
+        .withf(|name, force| name == "fedora-toolbox-38-toolbx" && *force)
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let adopted_rtbox = rtbox_engine.adopt("fedora-toolbox-38", RtBoxProfile::Default).await;
+
+    assert_eq!(adopted_rtbox.unwrap().id, "rtbox-id");
+}
+
+#[tokio::test]
+async fn rtbox_adopt_restricted_leaves_host_mounts_behind() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+
+    container_engine.expect_inspect()
+        .returning(|_| {
+            let mut container = toolbx_container_info();
+            container.mounts.push(RtBoxMount::new("/", "/run/host", "rbind"));
+            container.mounts.push(RtBoxMount::new("/dev", "/dev", "rbind"));
+            Ok(container)
+        });
+    container_engine.expect_rename()
+        .returning(|_, _| Ok(()));
+    container_engine.expect_create()
+        .withf(|spec| {
+            spec.mounts.iter().all(|mount| mount.source != "/" && mount.destination != "/run/host")
+                && spec.mounts.iter().all(|mount| mount.destination != "/dev" && mount.destination != "/srv/data")
+        })
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "rtbox-id")));
+    container_engine.expect_rm()
+        .returning(|_, _| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let adopted_rtbox = rtbox_engine.adopt("fedora-toolbox-38", RtBoxProfile::Restricted).await;
+
+    assert_eq!(adopted_rtbox.unwrap().id, "rtbox-id");
+}
+
+#[tokio::test]
+async fn rtbox_adopt_restores_running_container() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo {
+            running: true,
+            ..toolbx_container_info()
+        }));
+    container_engine.expect_stop()
+        .times(1)
+        .returning(|_| Ok(()));
+    container_engine.expect_rename()
+        .times(2)
+        .returning(|_, _| Ok(()));
+    container_engine.expect_create()
+        .returning(|_| Err(RtBoxError {
+            command: Some("create".to_string()),
+            message: None,
+            root_cause: None,
+        }));
+    container_engine.expect_start()
+        .withf(|name| name == "fedora-toolbox-38")
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+
+    assert!(rtbox_engine.adopt("fedora-toolbox-38", RtBoxProfile::Default).await.is_err());
+}
+
+#[tokio::test]
+async fn rtbox_adopt_rejects_non_toolbx_container() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo {
+            entrypoint: vec!["/run/host/usr/bin/rtbox".to_string(), "init".to_string()],
+            ..toolbx_container_info()
+        }));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+
+    assert!(rtbox_engine.adopt("fedora-toolbox-38", RtBoxProfile::Default).await.is_err());
+}
+
+#[test]
+fn rtbox_container_path_prefers_specific_mount() {
+
+    let container = RtBoxContainerInfo {
+        mounts: vec![
+            RtBoxMount::new("/", "/run/host", "rbind"),
+            RtBoxMount::new("/var/home/alex", "/var/home/alex", "rbind"),
+        ],
+        ..RtBoxContainerInfo::default()
+    };
+    let restricted_container = RtBoxContainerInfo {
+        mounts: vec![RtBoxMount::new("/etc/machine-id", "/etc/machine-id", "rbind:ro")],
+        ..RtBoxContainerInfo::default()
+    };
+
+    assert_eq!(
+        container.container_path(Path::new("/var/home/alex/src")),
+        Some(PathBuf::from("/var/home/alex/src")),
+    );
+    assert_eq!(container.container_path(Path::new("/etc")), Some(PathBuf::from("/run/host/etc")));
+    assert_eq!(restricted_container.container_path(Path::new("/var/home/alex")), None);
+}
+
+#[tokio::test]
+async fn rtbox_run_with_workdir() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo { running: true, ..toolbx_container_info() }));
+    container_engine.expect_exec()
+        .withf(|name, _, opts| {
+            /* piped input reaches the command even without a terminal */
+            name == "fedora-toolbox-38" && opts.workdir.as_deref() == Some("/srv/data")
+                && !opts.tty && opts.interactive
+        })
+        .times(1)
+        .returning(|_, _, _| Ok(0));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let run_opts = RtBoxRunOpts {
+        workdir: Some("/srv/data".to_string()),
+        tty: Some(false),
+        ..RtBoxRunOpts::default()
+    };
+    let run_output = rtbox_engine.run("fedora-toolbox-38".to_string(), vec!["true".to_string()], run_opts).await;
+
+    assert_eq!(run_output.unwrap().return_code, 0);
+}
+
+#[tokio::test]
+async fn rtbox_run_detached() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo { running: true, ..toolbx_container_info() }));
+    container_engine.expect_exec().never();
+    container_engine.expect_exec_detached()
+        .withf(|_, _, opts| {
+            opts.user.as_deref() == Some("root")
+                && !opts.tty
+                && !opts.interactive
+                && opts.env.contains(&("RUST_LOG".to_string(), "debug".to_string()))
+        })
+        .times(1)
+        .returning(|_, _, _| Ok("exec-id".to_string()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let run_opts = RtBoxRunOpts {
+        user: Some("root".to_string()),
+        env: vec!["RUST_LOG=debug".to_string()],
+        tty: Some(false),
+        detach: true,
+        ..RtBoxRunOpts::default()
+    };
+    let run_output = rtbox_engine.run("fedora-toolbox-38".to_string(), vec!["sleep".to_string(), "60".to_string()], run_opts).await;
+
+    assert_eq!(run_output.unwrap().exec_id.as_deref(), Some("exec-id"));
+}
+
+#[tokio::test]
+async fn rtbox_enter_creates_default_box() {
+
+    let config = RtBoxConfig {
+        default_box: Some("dev".to_string()),
+        default_image: Some("test-image:latest".to_string()),
+        ..RtBoxConfig::default()
+    };
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo::default()));
+
+    container_engine.expect_list()
+        .returning(|_| Ok(vec![]));
+    container_engine.expect_create()
+        .withf(|spec| spec.name == "dev" && spec.image == "test-image:latest")
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "dev-id")));
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo { name: "dev".to_string(), running: true, ..toolbx_container_info() }));
+    container_engine.expect_exec()
+        .withf(|name, _, opts| name == "dev" && opts.tty)
+        .times(1)
+        .returning(|_, _, _| Ok(0));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let name = rtbox_engine.box_name(None).unwrap();
+    let enter_output = rtbox_engine.enter(&name, None).await;
+
+    assert_eq!(name, "dev");
+    assert_eq!(enter_output.unwrap().return_code, 0);
+}
+
+#[tokio::test]
+async fn rtbox_inspect_reports_creation_and_readiness() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo {
+            name: "rtbox-inspect-test".to_string(),
+            image_digest: "sha256:abc".to_string(),
+            entrypoint: [
+                "/run/host/usr/bin/rtbox", "init",
+                "--uid", "1000",
+                "--init-hook", "echo one",
+                "--init-hook", "echo two",
+                "--command-not-found",
+                "--hook-timeout", "300",
+            ].iter().map(|arg| arg.to_string()).collect(),
+            ..RtBoxContainerInfo::default()
+        }));
+    container_engine.expect_read_file()
+        .returning(|_, _| Ok(br#"{"status":"ready","message":null}"#.to_vec()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let inspect = rtbox_engine.inspect("rtbox-inspect-test").await.unwrap();
+
+    assert_eq!(inspect.kind, RtBoxKind::Rtbox);
+    assert_eq!(inspect.creation["init-hook"], vec!["echo one", "echo two"]);
+    assert_eq!(inspect.creation["command-not-found"], Vec::<String>::new());
+    assert_eq!(inspect.creation["hook-timeout"], vec!["300"]);
+    assert_eq!(inspect.readiness.as_ref().unwrap().status, RtBoxInitStatus::Ready);
+    assert_eq!(inspect.field("image_digest"), Some(serde_json::json!("sha256:abc")));
+}
+
+fn rtbox_upgrade_container_info() -> RtBoxContainerInfo {
+    RtBoxContainerInfo {
+        name: "rtbox-upgrade-test".to_string(),
+        image: "localhost/fedora:latest".to_string(),
+        image_digest: "sha256:old".to_string(),
+        entrypoint: [
+            "/run/host/usr/bin/rtbox", "init",
+            "--username", "alex",
+            "--init-hook", "dnf install -y git",
+        ].iter().map(|arg| arg.to_string()).collect(),
+        labels: RtBoxLabels {
+            labels_version: labels::LABELS_VERSION,
+            version: "0.1.0".to_string(),
+            image: "registry.fedoraproject.org/fedora:39".to_string(),
+            image_digest: Some("sha256:old".to_string()),
+            home: None,
+            profile: RtBoxProfile::Default,
+            env: vec![],
+            hooks_hash: labels::hooks_hash(&[], &["dnf install -y git".to_string()]),
+            expires_at: None,
+        }.to_labels(),
+        ..RtBoxContainerInfo::default()
+    }
+}
+
+#[test]
+fn unknown_profile_labels_are_restricted() {
+
+    let mut container = rtbox_upgrade_container_info();
+    assert_eq!(container.profile(), RtBoxProfile::Default);
+
+    container.labels.insert(labels::label("profile"), "defualt".to_string());
+    assert_eq!(container.profile(), RtBoxProfile::Restricted);
+    assert!(!container.profile().allows_host_exec());
+
+    container.labels.remove(&labels::label("profile"));
+    assert_eq!(RtBoxLabels::from_labels(&container.labels).unwrap().profile, RtBoxProfile::Restricted);
+}
+
+#[tokio::test]
+async fn rtbox_upgrade_skips_up_to_date_box() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(rtbox_upgrade_container_info()));
+    container_engine.expect_pull_image()
+        .withf(|image| image == "registry.fedoraproject.org/fedora:39")
+        .times(1)
+        .returning(|_| Ok(()));
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo { digest: "sha256:old".to_string(), ..RtBoxImageInfo::default() }));
+    container_engine.expect_rename().never();
+    container_engine.expect_create().never();
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let upgrade = rtbox_engine.upgrade("rtbox-upgrade-test", false, &[]).await.unwrap();
+
+    assert!(!upgrade.upgraded);
+}
+
+#[tokio::test]
+async fn rtbox_upgrade_rolls_back_when_init_fails() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(rtbox_upgrade_container_info()));
+    container_engine.expect_pull_image()
+        .returning(|_| Ok(()));
+    container_engine.expect_inspect_image()
+        .returning(|_| Ok(RtBoxImageInfo { digest: "sha256:new".to_string(), ..RtBoxImageInfo::default() }));
+    container_engine.expect_create_volume()
+        .withf(|name, _| name == "rtbox-box-rtbox-upgrade-test_preserve.var.lib.dnf")
+        .times(1)
+        .returning(|_, _| Ok(()));
+    container_engine.expect_rename()
+        .withf(|name, new_name| name == "rtbox-upgrade-test" && new_name == "rtbox-upgrade-test-pre-upgrade")
+        .times(1)
+        .returning(|_, _| Ok(()));
+    container_engine.expect_create()
+        .withf(|spec| {
+            spec.image == "registry.fedoraproject.org/fedora:39"
+                && spec.entrypoint.windows(2).any(|args| args[0] == "--init-hook" && args[1] == "dnf install -y git")
+                && spec.volumes.iter().any(|named_volume| named_volume.destination == "/var/lib/dnf")
+        })
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "rtbox-id")));
+    /* the path was no volume before, what the old container has there goes into the new one */
+    container_engine.expect_copy_path()
+        .withf(|source, target, path| {
+            source == "rtbox-upgrade-test-pre-upgrade" && target == "rtbox-upgrade-test" && path == "/var/lib/dnf"
+        })
+        .times(1)
+        .returning(|_, _, _| Ok(()));
+    container_engine.expect_start()
+        .times(1)
+        .returning(|_| Ok(()));
+    container_engine.expect_read_file()
+        .returning(|_, _| Ok(br#"{"status":"failed","message":"init hook failed"}"#.to_vec()));
+    container_engine.expect_rm()
+        .withf(|name, force| name == "rtbox-upgrade-test" && *force)
+        .times(1)
+        .returning(|_, _| Ok(()));
+    container_engine.expect_remove_volume()
+        .withf(|name, _| name == "rtbox-box-rtbox-upgrade-test_preserve.var.lib.dnf")
+        .times(1)
+        .returning(|_, _| Ok(()));
+    container_engine.expect_rename()
+        .withf(|name, new_name| name == "rtbox-upgrade-test-pre-upgrade" && new_name == "rtbox-upgrade-test")
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let upgrade = rtbox_engine.upgrade("rtbox-upgrade-test", false, &["/var/lib/dnf".to_string()]).await;
+
+    assert!(upgrade.is_err());
+}
+
+#[tokio::test]
+async fn rtbox_commit_strips_user_and_labels_image() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(rtbox_upgrade_container_info()));
+    container_engine.expect_commit()
+        .withf(|name, image, cleanup, changes| {
+            name == "rtbox-upgrade-test"
+                && image == "localhost/team-box:1"
+                && cleanup[2].contains("/^alex:/d")
+                && changes.contains(&format!("LABEL {}=\"\"", labels::label("image")))
+                && cleanup[2].contains("/usr/local/bin/host-spawn")
+                && changes.contains(&"LABEL com.github.containers.toolbox=true".to_string())
+        })
+        .times(1)
+        .returning(|_, _, _, _| Ok(()));
+    container_engine.expect_inspect_image()
+        .withf(|image| image == "localhost/team-box:1")
+        .returning(|_| Ok(RtBoxImageInfo {
+            id: "image-id".to_string(),
+            names: vec!["localhost/team-box:1".to_string()],
+            ..RtBoxImageInfo::default()
+        }));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let image_info = rtbox_engine.commit("rtbox-upgrade-test", "localhost/team-box:1").await.unwrap();
+
+    assert_eq!(image_info.id, "image-id");
+}
+
+#[tokio::test]
+async fn rtbox_commit_rejects_foreign_container() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo {
+            entrypoint: vec!["/usr/sbin/nginx".to_string()],
+            ..RtBoxContainerInfo::default()
+        }));
+    container_engine.expect_commit().never();
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+
+    assert!(rtbox_engine.commit("nginx", "localhost/nginx").await.is_err());
+}
+
+#[test]
+fn box_volumes_leave_shared_and_foreign_ones_out() {
+
+    let dnf = RtBoxVolumeConfig::parse("dnf:/var/cache/dnf", false).unwrap();
+    let registry = RtBoxVolumeConfig::parse("cargo-registry:/srv/cargo", true).unwrap();
+    let container = RtBoxContainerInfo {
+        volumes: vec![
+            dnf.resolve("rtbox-upgrade-test", "/home/alex"),
+            registry.resolve("rtbox-upgrade-test", "/home/alex"),
+            RtBoxNamedVolume { name: "pgdata".to_string(), destination: "/var/lib/pgsql".to_string() },
+        ],
+        ..rtbox_upgrade_container_info()
+    };
+
+    let destinations: Vec<&str> = container.box_volumes().iter().map(|it| it.destination.as_str()).collect();
+
+    assert_eq!(destinations, vec!["/var/cache/dnf"]);
+}
+
+#[tokio::test]
+async fn rtbox_clone_copies_per_box_volumes() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+    let dnf = RtBoxVolumeConfig::parse("dnf:/var/cache/dnf", false).unwrap();
+    let registry = RtBoxVolumeConfig::parse("cargo-registry:/srv/cargo", true).unwrap();
+
+    container_engine.expect_inspect()
+        .returning(move |_| Ok(RtBoxContainerInfo {
+            volumes: vec![
+                dnf.resolve("rtbox-upgrade-test", "/home/alex"),
+                registry.resolve("rtbox-upgrade-test", "/home/alex"),
+            ],
+            ..rtbox_upgrade_container_info()
+        }));
+    container_engine.expect_commit()
+        .returning(|_, _, _, _| Ok(()));
+    container_engine.expect_create_volume()
+        .returning(|_, _| Ok(()));
+    container_engine.expect_create()
+        .withf(|spec| {
+            spec.volumes.iter().any(|named_volume| named_volume.name == "rtbox-box-rtbox-clone-test_dnf")
+                && spec.volumes.iter().any(|named_volume| named_volume.name == "rtbox-shared-cargo-registry")
+        })
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "clone-id")));
+    container_engine.expect_untag_image()
+        .returning(|_| Ok(()));
+    /* shared volumes are the same volume in the clone, only per box ones need their contents copied */
+    container_engine.expect_copy_path()
+        .withf(|source, target, path| source == "rtbox-upgrade-test" && target == "rtbox-clone-test" && path == "/var/cache/dnf")
+        .times(1)
+        .returning(|_, _, _| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let cloned = rtbox_engine.clone_box("rtbox-upgrade-test", "rtbox-clone-test").await.unwrap();
+
+    assert_eq!(cloned.id, "clone-id");
+}
+
+#[tokio::test]
+async fn rtbox_clone_keeps_recorded_image() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(rtbox_upgrade_container_info()));
+    container_engine.expect_commit()
+        .withf(|name, image, cleanup, _| {
+            name == "rtbox-upgrade-test" && image == "localhost/rtbox-clone-rtbox-clone-test:latest" && cleanup.is_empty()
+        })
+        .times(1)
+        .returning(|_, _, _, _| Ok(()));
+    container_engine.expect_create()
+        .withf(|spec| {
+            let recorded = RtBoxLabels::from_labels(&spec.labels).unwrap();
+            spec.name == "rtbox-clone-test"
+                && spec.image == "localhost/rtbox-clone-rtbox-clone-test:latest"
+                && recorded.image == "registry.fedoraproject.org/fedora:39"
+                && recorded.image_digest.as_deref() == Some("sha256:old")
+        })
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "clone-id")));
+    container_engine.expect_untag_image()
+        .withf(|image| image == "localhost/rtbox-clone-rtbox-clone-test:latest")
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let cloned = rtbox_engine.clone_box("rtbox-upgrade-test", "rtbox-clone-test").await.unwrap();
+
+    assert_eq!(cloned.id, "clone-id");
+    assert_eq!(cloned.image, "registry.fedoraproject.org/fedora:39");
+}
+
+#[tokio::test]
+async fn rtbox_load_hands_files_to_current_user() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+    let path = std::env::temp_dir().join(format!("rtbox-engine-load-{}.tar", std::process::id()));
+    let manifest = RtBoxArchiveManifest {
+        archive_version: ARCHIVE_VERSION,
+        name: "rtbox-upgrade-test".to_string(),
+        image: "localhost/rtbox-save-rtbox-upgrade-test:latest".to_string(),
+        container: RtBoxContainerInfo {
+            mounts: vec![RtBoxMount::new("/nonexistent/old-home/src", "/src", "rbind")],
+            ..rtbox_upgrade_container_info()
+        },
+        home: "/nonexistent/old-home".to_string(),
+        uid: u32::MAX,
+        gid: u32::MAX,
+        exports: vec![],
+    };
+    let image_path = path.with_extension("image");
+    std::fs::write(&image_path, b"image archive").unwrap();
+    archive::write(&path, &manifest, &image_path).unwrap();
+    std::fs::remove_file(&image_path).unwrap();
+
+    container_engine.expect_load_image()
+        .withf(|input, range| std::fs::read(input).unwrap()[range.start as usize..range.end as usize] == *b"image archive")
+        .returning(|_, _| Ok(vec!["localhost/rtbox-save-rtbox-upgrade-test:latest".to_string()]));
+    container_engine.expect_create()
+        .withf(|spec| {
+            spec.name.starts_with("rtbox-load-")
+                && spec.image == "localhost/rtbox-save-rtbox-upgrade-test:latest"
+                && spec.mounts.iter().all(|mount| mount.destination != "/src")
+                && spec.entrypoint.windows(2).any(|args| args[0] == "--init-hook" && args[1] == "dnf install -y git")
+        })
+        .times(2)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "load-id")));
+    container_engine.expect_untag_image()
+        .returning(|_| Ok(()));
+    container_engine.expect_import_path()
+        .withf(|name, path, _| name == "rtbox-load-test" && path == "/")
+        .times(1)
+        .returning(|_, _, _| Ok(()));
+    container_engine.expect_import_path()
+        .withf(|name, _, _| name == "rtbox-load-failed")
+        .returning(|_, _, _| Err(RtBoxError { command: None, message: None, root_cause: None }));
+    container_engine.expect_rm()
+        .withf(|name, force| name == "rtbox-load-failed" && *force)
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let loaded = rtbox_engine.load(&path, Some("rtbox-load-test".to_string())).await.unwrap();
+
+    assert_eq!(loaded.image, "registry.fedoraproject.org/fedora:39");
+    assert!(rtbox_engine.load(&path, Some("rtbox-load-failed".to_string())).await.is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn rtbox_run_ephemeral_removes_box() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+    let name = format!("rtbox-run-{}", std::process::id());
+
+    container_engine.expect_inspect_image()
+        .returning(|_| Err(RtBoxError { command: None, message: None, root_cause: Some("missing".to_string()) }));
+    container_engine.expect_pull_image()
+        .withf(|image| image == "test-image:latest")
+        .times(1)
+        .returning(|_| Ok(()));
+    let create_name = name.clone();
+    container_engine.expect_create()
+        .withf(move |spec| spec.name == create_name && spec.image == "test-image:latest")
+        .times(1)
+        .returning(|_| Ok(Container::new(Podman::unix("/dev/null"), "ephemeral-id")));
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo { running: true, ..toolbx_container_info() }));
+    container_engine.expect_exec()
+        .times(1)
+        .returning(|_, _, _| Ok(3));
+    let rm_name = name.clone();
+    container_engine.expect_rm()
+        .withf(move |name, force| name == &rm_name && *force)
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let run_output = rtbox_engine.run_ephemeral(
+        "test-image:latest".to_string(),
+        vec!["make".to_string()],
+        RtBoxRunOpts::default(),
+    ).await;
+
+    assert_eq!(run_output.unwrap().return_code, 3);
+}
+
+fn listed_container(name: &str, state: &str, labels: BTreeMap<String, String>) -> ListContainer {
+    serde_json::from_value(serde_json::json!({
+        "Names": [name],
+        "State": state,
+        "Labels": labels,
+    })).unwrap()
+}
+
+#[tokio::test]
+async fn rtbox_prune_dry_run_reports_expired_and_unused() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+    let expired_labels = RtBoxLabels {
+        expires_at: Some(init::unix_time() - 1),
+        ..RtBoxLabels::from_labels(&rtbox_upgrade_container_info().labels).unwrap()
+    }.to_labels();
+
+    container_engine.expect_list()
+        .returning(move |_| Ok(vec![
+            listed_container("expired", "running", expired_labels.clone()),
+            listed_container("stale", "exited", BTreeMap::new()),
+            listed_container("fresh", "exited", BTreeMap::new()),
+        ]));
+    container_engine.expect_read_file()
+        .withf(|_, path| path == init::LAST_SESSION_PATH)
+        .returning(|name, _| match name.as_str() {
+            "stale" => Ok(b"1000\n".to_vec()),
+            _ => Ok(init::unix_time().to_string().into_bytes()),
+        });
+    container_engine.expect_dangling_images()
+        .returning(|| Ok(vec![RtBoxImageInfo { id: "dangling-id".to_string(), ..RtBoxImageInfo::default() }]));
+    container_engine.expect_rm().never();
+    container_engine.expect_remove_image().never();
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let report = rtbox_engine.prune(Some(30 * 24 * 60 * 60), true).await.unwrap();
+    let pruned: Vec<&str> = report.boxes.iter().map(|pruned| pruned.name.as_str()).collect();
+
+    assert!(report.dry_run);
+    assert_eq!(pruned, vec!["expired", "stale"]);
+    assert_eq!(report.boxes[0].reason, "expired");
+    assert_eq!(report.images[0].id, "dangling-id");
+}
+
+#[tokio::test]
+async fn rtbox_prune_keeps_going_past_failed_removal() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_list()
+        .returning(|_| Ok(vec![
+            listed_container("broken", "exited", BTreeMap::new()),
+            listed_container("stale", "exited", BTreeMap::new()),
+        ]));
+    container_engine.expect_read_file()
+        .withf(|_, path| path == init::LAST_SESSION_PATH)
+        .returning(|_, _| Ok(b"1000\n".to_vec()));
+    container_engine.expect_rm()
+        .withf(|name, _| name == "broken")
+        .times(1)
+        .returning(|_, _| Err(RtBoxError { command: None, message: None, root_cause: None }));
+    container_engine.expect_rm()
+        .withf(|name, force| name == "stale" && *force)
+        .times(1)
+        .returning(|_, _| Ok(()));
+    /* stop before the orphaned exports so the host's own exports are left alone */
+    container_engine.expect_dangling_images()
+        .returning(|| Err(RtBoxError { command: None, message: None, root_cause: None }));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    assert!(rtbox_engine.prune(Some(30 * 24 * 60 * 60), false).await.is_err());
+}
+
+#[tokio::test]
+async fn rtbox_rename_refuses_taken_name() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_list()
+        .returning(|_| Ok(vec![
+            listed_container("fedora-toolbox-38", "exited", BTreeMap::new()),
+            listed_container("work", "exited", BTreeMap::new()),
+        ]));
+    container_engine.expect_inspect()
+        .returning(|_| Ok(toolbx_container_info()));
+    container_engine.expect_rename()
+        .withf(|name, new_name| name == "fedora-toolbox-38" && new_name == "rtbox-renamed-test")
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+
+    assert!(rtbox_engine.rename("fedora-toolbox-38", "work").await.is_err());
+    assert_eq!(rtbox_engine.rename("fedora-toolbox-38", "rtbox-renamed-test").await.unwrap().name, "rtbox-renamed-test");
+}
+
+#[tokio::test]
+async fn rtbox_systemd_box_services_go_through_systemctl() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo {
+            name: "rtbox-systemd-test".to_string(),
+            entrypoint: [
+                "/run/host/usr/bin/rtbox", "init",
+                "--init-mode", "systemd",
+            ].iter().map(|arg| arg.to_string()).collect(),
+            ..RtBoxContainerInfo::default()
+        }));
+    container_engine.expect_exec_output()
+        .withf(|_, command, opts| command[0] == "systemctl" && command[1] == "list-units" && opts.user.as_deref() == Some("root"))
+        .returning(|_, _, _| Ok(RtBoxCapturedOutput {
+            stdout: "sshd.service loaded active running OpenSSH server daemon\n".to_string(),
+            ..RtBoxCapturedOutput::default()
+        }));
+    container_engine.expect_stop()
+        .withf(|name| name == "rtbox-systemd-test")
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+    let services = rtbox_engine.service_list("rtbox-systemd-test").await.unwrap();
+
+    assert_eq!(services[0].name, "sshd");
+    assert!(rtbox_engine.stop("rtbox-systemd-test").await.is_ok());
+}
+
+#[tokio::test]
+async fn rtbox_host_helper_refuses_restricted_box() {
+
+    let config: RtBoxConfig = RtBoxConfig::default();
+    let mut container_engine: MockContainerEngine = MockContainerEngine::default();
+
+    container_engine.expect_inspect()
+        .returning(|_| Ok(RtBoxContainerInfo {
+            name: "rtbox-restricted-test".to_string(),
+            entrypoint: vec!["/run/host/usr/bin/rtbox".to_string(), "init".to_string()],
+            labels: RtBoxLabels {
+                profile: RtBoxProfile::Restricted,
+                ..RtBoxLabels::from_labels(&rtbox_upgrade_container_info().labels).unwrap()
+            }.to_labels(),
+            ..RtBoxContainerInfo::default()
+        }));
+
+    let rtbox_engine = RtBoxEngine::new(&config, &container_engine);
+
+    assert!(rtbox_engine.host_helper("rtbox-restricted-test").await.is_err());
+}
@@ -0,0 +1,28 @@
+use crate::rtbox::export::{self, rename_export_contents};
+
+
+#[test]
+fn export_contents_follow_renamed_box() {
+
+    let shim = "#!/bin/sh\n# X-RtBox-Container=dev\nexec /usr/bin/rtbox run --container dev /usr/bin/htop \"$@\"\n";
+    assert_eq!(
+        rename_export_contents(shim, "dev", "work"),
+        "#!/bin/sh\n# X-RtBox-Container=work\nexec /usr/bin/rtbox run --container work /usr/bin/htop \"$@\"\n",
+    );
+
+    let desktop_entry = export::rewrite_desktop_entry(
+        "dev",
+        "[Desktop Entry]\nName=Editor\nExec=editor %F\n",
+        "/usr/bin/rtbox",
+    );
+    assert_eq!(
+        rename_export_contents(&desktop_entry, "dev", "work"),
+        export::rewrite_desktop_entry("work", "[Desktop Entry]\nName=Editor\nExec=editor %F\n", "/usr/bin/rtbox"),
+    );
+
+    /* only whole container names are replaced */
+    let unit = export::rewrite_service_unit("dev", "[Service]\nExecStart=/usr/bin/dev-server --dev\n", "/usr/bin/rtbox");
+    let renamed_unit = rename_export_contents(&unit, "dev", "work");
+    assert!(renamed_unit.contains("ExecStart=/usr/bin/rtbox run --container work /usr/bin/dev-server --dev"));
+    assert!(renamed_unit.contains("X-RtBox-Container=work"));
+}
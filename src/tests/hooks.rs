@@ -0,0 +1,130 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::rtbox::hooks::{
+    box_hooks,
+    rc_hook,
+    script_hooks,
+    RtBoxHookFailurePolicy,
+    RtBoxHookPhase,
+    RtBoxHookRunner,
+    RtBoxHookSource,
+    RtBoxHookStatus,
+};
+
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rtbox-hooks-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn runner(log_dir: PathBuf, timeout: Duration, failure_policy: RtBoxHookFailurePolicy) -> RtBoxHookRunner {
+    RtBoxHookRunner {
+        timeout,
+        failure_policy,
+        log_dir,
+    }
+}
+
+#[test]
+fn script_hooks_are_sorted_and_executable() {
+
+    let dir = test_dir("scripts");
+    for (name, mode) in [("20-second", 0o755), ("10-first", 0o755), ("30-disabled", 0o644)] {
+        let path = dir.join(name);
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    let hooks = script_hooks(RtBoxHookPhase::Init, RtBoxHookSource::Image, &dir);
+
+    let names: Vec<&str> = hooks.iter().map(|hook| hook.name.as_str()).collect();
+    assert_eq!(names, vec!["10-first", "20-second"]);
+    assert!(hooks.iter().all(|hook| hook.source == RtBoxHookSource::Image));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn hooks_log_their_output() {
+
+    let dir = test_dir("logs");
+    let hooks = box_hooks(RtBoxHookPhase::PreInit, &["echo hello".to_string()]);
+
+    let hook_result = runner(dir.clone(), Duration::from_secs(10), RtBoxHookFailurePolicy::Abort).run(&hooks[0]);
+
+    assert_eq!(hook_result.status, RtBoxHookStatus::Succeeded);
+    assert_eq!(hook_result.name, "pre-init-hook-1");
+    let log = fs::read_to_string(hook_result.log_path.unwrap()).unwrap();
+    assert_eq!(log, "hello\n");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rc_script_runs_first_with_its_own_log() {
+
+    let dir = test_dir("rc");
+    let rc_path = dir.join("rc");
+    fs::write(&rc_path, "echo rc\n").unwrap();
+    let user_dir = dir.join("pre-init.d");
+    fs::create_dir_all(&user_dir).unwrap();
+    fs::write(user_dir.join("rc"), "#!/bin/sh\necho user\n").unwrap();
+    fs::set_permissions(user_dir.join("rc"), fs::Permissions::from_mode(0o755)).unwrap();
+
+    /* not executable, so it goes through sh */
+    let rc = rc_hook(&rc_path).unwrap();
+    assert_eq!(rc.command, vec!["/bin/sh".to_string(), rc_path.display().to_string()]);
+    assert_eq!(rc.phase, RtBoxHookPhase::PreInit);
+    assert!(rc_hook(&dir.join("missing")).is_none());
+
+    let user_hook = script_hooks(RtBoxHookPhase::PreInit, RtBoxHookSource::User, &user_dir).remove(0);
+    let hook_runner = runner(dir.join("logs"), Duration::from_secs(10), RtBoxHookFailurePolicy::Abort);
+    let rc_log = hook_runner.run(&rc).log_path.unwrap();
+    let user_log = hook_runner.run(&user_hook).log_path.unwrap();
+
+    assert_ne!(rc_log, user_log);
+    assert_eq!(fs::read_to_string(rc_log).unwrap(), "rc\n");
+    assert_eq!(fs::read_to_string(user_log).unwrap(), "user\n");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn hooks_time_out() {
+
+    let dir = test_dir("timeout");
+    let hooks = box_hooks(RtBoxHookPhase::Init, &["sleep 30".to_string()]);
+
+    let hook_result = runner(dir.clone(), Duration::from_millis(200), RtBoxHookFailurePolicy::Abort).run(&hooks[0]);
+
+    assert_eq!(hook_result.status, RtBoxHookStatus::TimedOut);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn hook_failure_policy() {
+
+    let dir = test_dir("policy");
+    let hooks = box_hooks(RtBoxHookPhase::Init, &["exit 1".to_string(), "true".to_string()]);
+
+    let mut results = vec![];
+    let abort = runner(dir.clone(), Duration::from_secs(10), RtBoxHookFailurePolicy::Abort)
+        .run_all(&hooks, &mut results);
+    assert!(abort.is_err());
+    assert_eq!(results.len(), 1);
+
+    let mut results = vec![];
+    let resume = runner(dir.clone(), Duration::from_secs(10), RtBoxHookFailurePolicy::Continue)
+        .run_all(&hooks, &mut results);
+    assert!(resume.is_ok());
+    let statuses: Vec<RtBoxHookStatus> = results.iter().map(|hook_result| hook_result.status).collect();
+    assert_eq!(statuses, vec![RtBoxHookStatus::Failed, RtBoxHookStatus::Succeeded]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,85 @@
+use std::fs;
+
+use crate::rtbox::host_commands::{self, RtBoxHostCommandsConfig, COMMAND_NOT_FOUND_SCRIPT};
+use crate::rtbox::host_exec::{self, HOST_EXEC_SOCKET};
+
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn host_commands_allow_and_deny() {
+
+    let default_policy = RtBoxHostCommandsConfig::default();
+    let restricted_policy = RtBoxHostCommandsConfig {
+        shims: args(&["podman"]),
+        allow: args(&["flatpak"]),
+        deny: args(&["sudo"]),
+        command_not_found: true,
+    };
+
+    assert!(default_policy.is_allowed("htop"));
+    assert!(!default_policy.is_allowed("env"));
+    assert!(!default_policy.is_allowed("/bin/bash"));
+    assert!(default_policy.is_allowed("/usr/bin/podman"));
+    assert!(!default_policy.is_allowed("sudo"));
+    assert!(!default_policy.is_allowed("/usr/bin/sudo"));
+    assert!(restricted_policy.is_allowed("podman"));
+    assert!(restricted_policy.is_allowed("flatpak"));
+    assert!(!restricted_policy.is_allowed("htop"));
+    assert!(!restricted_policy.is_allowed(""));
+}
+
+#[test]
+fn host_commands_install_shims() {
+
+    let root = std::env::temp_dir().join(format!("rtbox-host-commands-shims-{}", std::process::id()));
+    let bin_dir = root.join("usr/local/bin");
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    fs::create_dir_all(&bin_dir).unwrap();
+    fs::write(root.join("usr/bin/flatpak"), "").unwrap();
+    fs::write(bin_dir.join("xdg-open"), "#!/bin/sh\n# not ours\n").unwrap();
+
+    let shims = args(&["flatpak", "podman", "xdg-open"]);
+    let installed = host_commands::install_shims(&bin_dir, &root, "/usr/local/bin/rtbox", &shims);
+    let reinstalled = host_commands::install_shims(&bin_dir, &root, "/usr/local/bin/rtbox", &shims);
+    let written = host_commands::install_command_not_found(&root.join("etc/profile.d"), "/usr/local/bin/rtbox", "/run/rtbox/host.sock");
+
+    assert_eq!(installed, args(&["podman"]));
+    assert_eq!(reinstalled, args(&["podman"]));
+    assert!(!bin_dir.join("flatpak").exists());
+    assert_eq!(fs::read_to_string(bin_dir.join("xdg-open")).unwrap(), "#!/bin/sh\n# not ours\n");
+    assert!(fs::read_to_string(bin_dir.join("podman")).unwrap()
+        .contains("exec /usr/local/bin/rtbox host-exec --forwarded -- podman \"$@\""));
+    assert!(written.unwrap());
+    assert!(fs::read_to_string(root.join("etc/profile.d").join(COMMAND_NOT_FOUND_SCRIPT)).unwrap()
+        .contains("command_not_found_handler()"));
+
+    fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn host_commands_refuse_denied() {
+
+    let dir = std::env::temp_dir().join(format!("rtbox-host-commands-deny-{}", std::process::id()));
+    let socket_path = dir.join(HOST_EXEC_SOCKET);
+    let policy = RtBoxHostCommandsConfig {
+        allow: args(&["*", "true"]),
+        deny: args(&["true"]),
+        ..RtBoxHostCommandsConfig::default()
+    };
+    host_exec::serve(&socket_path, policy).unwrap();
+
+    let denied = host_exec::host_exec(&socket_path, args(&["/bin/true"]), false);
+    let wrapped = host_exec::host_exec(&socket_path, args(&["env", "true"]), false);
+    let shell = host_exec::host_exec(&socket_path, args(&["/bin/sh", "-c", "exit 0"]), false);
+    let allowed = host_exec::host_exec(&socket_path, args(&["false"]), false);
+
+    assert_eq!(denied.unwrap(), 127);
+    assert_eq!(wrapped.unwrap(), 127);
+    assert_eq!(shell.unwrap(), 127);
+    assert_eq!(allowed.unwrap(), 1);
+
+    fs::remove_dir_all(dir).unwrap();
+}
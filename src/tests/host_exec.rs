@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use crate::rtbox::cli::multiplexed_args;
+use crate::rtbox::config::{RtBoxBoxConfig, RtBoxConfig};
+use crate::rtbox::host_commands::RtBoxHostCommandsConfig;
+use crate::rtbox::host_exec::{self, host_cwd, HOST_EXEC_SOCKET};
+
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn host_cwd_strips_host_prefix() {
+
+    let tmp = std::env::temp_dir();
+
+    assert_eq!(host_cwd("/run/host"), PathBuf::from("/"));
+    assert_eq!(host_cwd(&format!("/run/host{}", tmp.display())), tmp);
+    assert_eq!(host_cwd(&tmp.display().to_string()), tmp);
+    assert_eq!(
+        host_cwd("/only/inside/the/box"),
+        PathBuf::from(std::env::var("HOME").unwrap_or("/".to_string())),
+    );
+}
+
+#[test]
+fn host_spawn_symlink_runs_host_exec() {
+
+    assert_eq!(
+        multiplexed_args(args(&["/usr/local/bin/host-spawn", "ls", "-l"]), &RtBoxConfig::default()),
+        args(&["rtbox", "host-exec", "ls", "-l"]),
+    );
+    assert_eq!(
+        multiplexed_args(args(&["/usr/bin/rtbox", "list"]), &RtBoxConfig::default()),
+        args(&["/usr/bin/rtbox", "list"]),
+    );
+}
+
+#[test]
+fn box_symlink_enters_or_runs() {
+
+    let config = RtBoxConfig {
+        default_box: Some("work".to_string()),
+        boxes: BTreeMap::from([("dev".to_string(), RtBoxBoxConfig::default())]),
+        ..RtBoxConfig::default()
+    };
+
+    assert_eq!(
+        multiplexed_args(args(&["/home/alex/bin/dev"]), &config),
+        args(&["rtbox", "enter", "dev"]),
+    );
+    assert_eq!(
+        multiplexed_args(args(&["dev", "cargo", "--version"]), &config),
+        args(&["rtbox", "run", "-c", "dev", "--", "cargo", "--version"]),
+    );
+    assert_eq!(
+        multiplexed_args(args(&["work"]), &config),
+        args(&["rtbox", "enter", "work"]),
+    );
+    assert_eq!(
+        multiplexed_args(args(&["/usr/local/bin/rtbox-dev", "list"]), &config),
+        args(&["/usr/local/bin/rtbox-dev", "list"]),
+    );
+}
+
+#[test]
+fn host_exec_returns_exit_code() {
+
+    let dir = std::env::temp_dir().join(format!("rtbox-host-exec-{}", std::process::id()));
+    let socket_path = dir.join(HOST_EXEC_SOCKET);
+    let policy = RtBoxHostCommandsConfig {
+        allow: args(&["sh"]),
+        ..RtBoxHostCommandsConfig::default()
+    };
+    host_exec::serve(&socket_path, policy).unwrap();
+
+    let exit_code = host_exec::host_exec(&socket_path, args(&["/bin/sh", "-c", "exit 3"]), false);
+
+    assert_eq!(exit_code.unwrap(), 3);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn host_exec_rejects_oversized_frames() {
+
+    let dir = std::env::temp_dir().join(format!("rtbox-host-exec-frames-{}", std::process::id()));
+    let socket_path = dir.join(HOST_EXEC_SOCKET);
+    host_exec::serve(&socket_path, RtBoxHostCommandsConfig::default()).unwrap();
+
+    assert_eq!(fs::metadata(&socket_path).unwrap().permissions().mode() & 0o777, 0o600);
+
+    /* a request claiming 4 GiB is dropped instead of allocated */
+    let mut stream = UnixStream::connect(&socket_path).unwrap();
+    stream.write_all(&[0, 0xff, 0xff, 0xff, 0xff]).unwrap();
+    let mut answer = vec![];
+    stream.read_to_end(&mut answer).unwrap();
+
+    assert!(answer.is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,72 @@
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::PathBuf;
+
+use crate::rtbox::host_sync::{sync_file, RtBoxHostWatcher};
+
+
+fn test_roots(name: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("rtbox-host-sync-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let (host_root, root) = (dir.join("run/host"), dir.join("root"));
+    fs::create_dir_all(host_root.join("etc")).unwrap();
+    fs::create_dir_all(root.join("etc")).unwrap();
+    (host_root, root)
+}
+
+#[test]
+fn sync_copies_changed_files() {
+
+    let (host_root, root) = test_roots("copy");
+    fs::write(host_root.join("etc/resolv.conf"), "nameserver 10.0.0.1\n").unwrap();
+    fs::write(root.join("etc/resolv.conf"), "nameserver 192.168.1.1\n").unwrap();
+
+    assert!(sync_file(&host_root, &root, "/etc/resolv.conf").unwrap());
+    assert!(!sync_file(&host_root, &root, "/etc/resolv.conf").unwrap());
+    assert_eq!(fs::read_to_string(root.join("etc/resolv.conf")).unwrap(), "nameserver 10.0.0.1\n");
+
+    /* nothing to copy when the host does not have the file */
+    assert!(!sync_file(&host_root, &root, "/etc/hosts").unwrap());
+    assert!(!root.join("etc/hosts").exists());
+
+    fs::remove_dir_all(host_root.parent().unwrap().parent().unwrap()).unwrap();
+}
+
+#[test]
+fn sync_links_localtime_to_host() {
+
+    let (host_root, root) = test_roots("localtime");
+    symlink("../usr/share/zoneinfo/Europe/Berlin", host_root.join("etc/localtime")).unwrap();
+    fs::create_dir_all(host_root.join("usr/share/zoneinfo/Europe")).unwrap();
+    fs::write(host_root.join("usr/share/zoneinfo/Europe/Berlin"), "TZif").unwrap();
+    fs::write(root.join("etc/localtime"), "UTC").unwrap();
+    fs::write(root.join("etc/timezone"), "Etc/UTC\n").unwrap();
+
+    assert!(sync_file(&host_root, &root, "/etc/localtime").unwrap());
+
+    assert_eq!(fs::read_link(root.join("etc/localtime")).unwrap(), host_root.join("etc/localtime"));
+    assert_eq!(fs::read_to_string(root.join("etc/timezone")).unwrap(), "Europe/Berlin\n");
+    assert!(!sync_file(&host_root, &root, "/etc/localtime").unwrap());
+
+    fs::remove_dir_all(host_root.parent().unwrap().parent().unwrap()).unwrap();
+}
+
+#[test]
+fn watcher_wakes_up_on_host_changes() {
+
+    let (host_root, _) = test_roots("watch");
+    fs::write(host_root.join("etc/hosts"), "127.0.0.1 localhost\n").unwrap();
+    let mut watcher = RtBoxHostWatcher::new(&host_root).unwrap();
+
+    let test_dir = host_root.parent().unwrap().parent().unwrap().to_path_buf();
+    let hosts = host_root.join("etc/hosts");
+    std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        fs::write(host_root.join("etc/unrelated"), "").unwrap();
+        fs::write(hosts, "127.0.0.1 localhost\n10.0.0.2 vpn\n").unwrap();
+    });
+
+    watcher.wait().unwrap();
+
+    fs::remove_dir_all(test_dir).unwrap();
+}
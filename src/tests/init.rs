@@ -0,0 +1,55 @@
+use crate::rtbox::init::{box_hostname, cleanup_command, containerenv_name, is_env_name, systemd_setup_unit};
+
+
+#[test]
+fn systemd_setup_unit_reruns_init_as_oneshot() {
+
+    let init_command = vec![
+        "/run/host/usr/bin/rtbox".to_string(),
+        "init".to_string(),
+        "--init-hook".to_string(), "echo \"$HOME\" 100%".to_string(),
+    ];
+
+    let unit = systemd_setup_unit(&init_command);
+
+    assert!(unit.contains("Type=oneshot\n"));
+    assert!(unit.contains("WantedBy=multi-user.target\n"));
+    assert!(unit.contains(
+        "ExecStart=\"/run/host/usr/bin/rtbox\" \"init\" \"--init-hook\" \"echo \\\"$$HOME\\\" 100%%\" \"--oneshot\"\n"
+    ));
+}
+
+#[test]
+fn containerenv_name_gives_renamed_hostname() {
+
+    let containerenv = "engine=\"podman-4.9.4\"\nname=\"work\"\nid=\"abc\"\nrootless=1\n";
+
+    assert_eq!(containerenv_name(containerenv).map(|name| box_hostname(&name)), Some("work.host".to_string()));
+    assert_eq!(containerenv_name("engine=\"podman-4.9.4\"\nname=\"\"\n"), None);
+}
+
+#[test]
+fn env_names_cannot_inject_shell() {
+
+    assert!(is_env_name("DISPLAY"));
+    assert!(is_env_name("_X11_1"));
+    assert!(!is_env_name(""));
+    assert!(!is_env_name("1PATH"));
+    assert!(!is_env_name("PATH=x; rm -rf /"));
+    assert!(!is_env_name("$(id)"));
+}
+
+#[test]
+fn cleanup_command_quotes_the_username() {
+
+    let script = &cleanup_command("alex")[2];
+    assert!(script.contains("sed -i '/^alex:/d' \"$file\""));
+    assert!(script.contains("rm -f '/etc/sudoers.d/alex' "));
+
+    let script = &cleanup_command("a.x'$(id)")[2];
+    assert!(script.contains("sed -i '/^a\\.x'\\''\\$(id):/d' \"$file\""));
+    assert!(script.contains("rm -f '/etc/sudoers.d/a.x'\\''$(id)' "));
+
+    let script = &cleanup_command("../shadow")[2];
+    assert!(script.contains("rm -f '/etc/sudoers.d/shadow' "));
+}
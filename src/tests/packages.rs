@@ -0,0 +1,43 @@
+use crate::rtbox::os_release;
+use crate::rtbox::packages::RtBoxPackageManager;
+
+
+#[test]
+fn package_manager_from_id() {
+
+    let ubuntu = os_release::parse("ID=ubuntu\nID_LIKE=debian\nVERSION_ID=\"22.04\"\n");
+    let alpine = os_release::parse("ID=alpine\nVERSION_ID=3.18.0\n");
+    let void = os_release::parse("ID=\"void\"\n");
+
+    assert_eq!(RtBoxPackageManager::detect(&ubuntu), Some(RtBoxPackageManager::Apt));
+    assert_eq!(RtBoxPackageManager::detect(&alpine), Some(RtBoxPackageManager::Apk));
+    assert_eq!(RtBoxPackageManager::detect(&void), Some(RtBoxPackageManager::Xbps));
+}
+
+#[test]
+fn package_manager_falls_back_to_id_like() {
+
+    let nobara = os_release::parse("ID=nobara\nID_LIKE=\"rhel centos fedora\"\n");
+    let unknown = os_release::parse("ID=gentoo\n");
+
+    assert_eq!(RtBoxPackageManager::detect(&nobara), Some(RtBoxPackageManager::Dnf));
+    assert_eq!(RtBoxPackageManager::detect(&unknown), None);
+}
+
+#[test]
+fn package_manager_install_commands() {
+
+    let packages = vec!["git".to_string(), "vim".to_string()];
+
+    assert_eq!(
+        RtBoxPackageManager::Apt.install_commands(&packages),
+        vec![
+            vec!["apt-get", "update"],
+            vec!["apt-get", "install", "-y", "git", "vim"],
+        ],
+    );
+    assert_eq!(
+        RtBoxPackageManager::Pacman.install_commands(&packages),
+        vec![vec!["pacman", "-Sy", "--noconfirm", "--needed", "git", "vim"]],
+    );
+}
@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::rtbox::services::{
+    start_order,
+    systemd_services,
+    systemd_status,
+    systemd_unit,
+    RtBoxRestartPolicy,
+    RtBoxService,
+    RtBoxServiceState,
+    RtBoxSupervisor,
+};
+
+
+fn service(name: &str, command: &str, after: &[&str]) -> RtBoxService {
+    RtBoxService {
+        name: name.to_string(),
+        command: vec!["/bin/sh".to_string(), "-c".to_string(), command.to_string()],
+        env: Default::default(),
+        user: None,
+        restart: RtBoxRestartPolicy::No,
+        after: after.iter().map(|it| it.to_string()).collect(),
+    }
+}
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rtbox-services-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn services_start_after_dependencies() {
+
+    let services = vec![
+        service("app", "true", &["cache", "db"]),
+        service("cache", "true", &["db"]),
+        service("db", "true", &[]),
+    ];
+
+    assert_eq!(start_order(&services).unwrap(), vec!["db", "cache", "app"]);
+}
+
+#[test]
+fn services_reject_bad_dependencies() {
+
+    let cycle = vec![service("a", "true", &["b"]), service("b", "true", &["a"])];
+    let unknown = vec![service("a", "true", &["missing"])];
+
+    assert!(start_order(&cycle).is_err());
+    assert!(start_order(&unknown).is_err());
+}
+
+#[test]
+fn supervisor_starts_and_stops_services() {
+
+    let dir = test_dir("supervisor");
+    let services = vec![
+        service("web", "echo serving; exec sleep 30", &["db"]),
+        service("db", "exec sleep 30", &[]),
+    ];
+    let supervisor = RtBoxSupervisor::new(&services, None, dir.clone()).unwrap();
+
+    let web = supervisor.start("web").unwrap();
+    assert_eq!(web.state, RtBoxServiceState::Running);
+    assert!(supervisor.list().iter().all(|status| status.state == RtBoxServiceState::Running));
+
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(supervisor.logs("web", 10).unwrap(), "serving\n");
+
+    let web = supervisor.stop("web").unwrap();
+    assert_eq!(web.state, RtBoxServiceState::Stopped);
+    assert_eq!(web.pid, None);
+    supervisor.stop("db").unwrap();
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn supervisor_restarts_failed_services() {
+
+    let dir = test_dir("restart");
+    let mut crashing = service("crashing", "exit 3", &[]);
+    crashing.restart = RtBoxRestartPolicy::OnFailure;
+    let supervisor = RtBoxSupervisor::new(&[crashing, service("oneshot", "exit 3", &[])], None, dir.clone()).unwrap();
+
+    supervisor.start_all();
+    std::thread::sleep(Duration::from_millis(200));
+    supervisor.poll();
+
+    let statuses = supervisor.list();
+    let crashing = statuses.iter().find(|status| status.name == "crashing").unwrap();
+    let oneshot = statuses.iter().find(|status| status.name == "oneshot").unwrap();
+    assert_eq!(crashing.restarts, 1);
+    assert_eq!(oneshot.restarts, 0);
+    assert_eq!(oneshot.state, RtBoxServiceState::Failed);
+    assert_eq!(oneshot.exit_code, Some(3));
+
+    supervisor.stop("crashing").unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn systemd_units_read_as_services() {
+    let show = "Id=sshd.service\nActiveState=active\nSubState=running\nMainPID=42\nNRestarts=1\nExecMainStatus=0\n";
+    let status = systemd_status(show).unwrap();
+    assert_eq!(status.name, "sshd");
+    assert_eq!(status.state, RtBoxServiceState::Running);
+    assert_eq!(status.pid, Some(42));
+    assert_eq!(status.restarts, 1);
+
+    let units = "cron.service loaded failed failed Cron\nsetup.service loaded active exited Setup\ndbus.service loaded inactive dead D-Bus\n";
+    let states: Vec<RtBoxServiceState> = systemd_services(units).iter().map(|status| status.state).collect();
+    assert_eq!(states, vec![RtBoxServiceState::Failed, RtBoxServiceState::Exited, RtBoxServiceState::Stopped]);
+
+    assert_eq!(systemd_unit("sshd"), "sshd.service");
+    assert_eq!(systemd_unit("backup.timer"), "backup.timer");
+}
@@ -0,0 +1,92 @@
+use crate::rtbox::volume::{self, RtBoxNamedVolume, RtBoxVolumeConfig};
+
+
+#[test]
+fn volume_specs_are_parsed() {
+
+    assert_eq!(
+        RtBoxVolumeConfig::parse("cargo-registry:~/.cargo/registry", true),
+        Some(RtBoxVolumeConfig {
+            name: "cargo-registry".to_string(),
+            path: "~/.cargo/registry".to_string(),
+            shared: true,
+            volume: None,
+        }),
+    );
+    assert!(RtBoxVolumeConfig::parse("dnf:/var/cache/dnf", false).is_some());
+
+    assert_eq!(RtBoxVolumeConfig::parse("dnf", false), None);
+    assert_eq!(RtBoxVolumeConfig::parse("dnf:var/cache/dnf", false), None);
+    assert_eq!(RtBoxVolumeConfig::parse("-dnf:/var/cache/dnf", false), None);
+    assert_eq!(RtBoxVolumeConfig::parse("dnf/cache:/var/cache/dnf", false), None);
+}
+
+#[test]
+fn volumes_resolve_per_box_or_shared() {
+
+    let dnf = RtBoxVolumeConfig::parse("dnf:/var/cache/dnf", false).unwrap();
+    let registry = RtBoxVolumeConfig::parse("cargo-registry:~/.cargo/registry", true).unwrap();
+
+    assert_eq!(
+        dnf.resolve("dev", "/home/alex"),
+        RtBoxNamedVolume { name: "rtbox-box-dev_dnf".to_string(), destination: "/var/cache/dnf".to_string() },
+    );
+    assert_eq!(
+        registry.resolve("dev", "/home/alex/boxes/dev"),
+        RtBoxNamedVolume {
+            name: volume::volume_name("cargo-registry", None),
+            destination: "/home/alex/boxes/dev/.cargo/registry".to_string(),
+        },
+    );
+
+    /* recreated boxes get the same volumes back */
+    let named_volume = dnf.resolve("dev-2", "/home/alex");
+    assert_eq!(RtBoxVolumeConfig::from_named_volume(&named_volume, "dev-2"), Some(dnf));
+    assert_eq!(
+        RtBoxVolumeConfig::from_named_volume(&named_volume, "work").and_then(|it| it.volume),
+        Some(named_volume.name.clone()),
+    );
+    assert_eq!(
+        RtBoxVolumeConfig::from_named_volume(&registry.resolve("dev", "/home/alex/boxes/dev"), "other").map(|it| it.shared),
+        Some(true),
+    );
+    assert_eq!(
+        RtBoxVolumeConfig::from_named_volume(&RtBoxNamedVolume { name: "pgdata".to_string(), destination: "/data".to_string() }, "dev"),
+        None,
+    );
+}
+
+#[test]
+fn preserved_paths_get_distinct_volumes() {
+
+    let dnf = volume::preserved_volume("/var/lib/dnf");
+    let repos = volume::preserved_volume("/etc/yum.repos.d");
+
+    assert_eq!(dnf.name, "preserve.var.lib.dnf");
+    assert_eq!(dnf.path, "/var/lib/dnf");
+    assert!(!dnf.shared);
+    assert_eq!(repos.name, "preserve.etc.yum_2erepos_2ed");
+    assert_ne!(repos.name, volume::preserved_volume("/etc/yum/repos/d").name);
+    assert!(RtBoxVolumeConfig::parse(&format!("{}:{}", repos.name, repos.path), false).is_some());
+}
+
+#[test]
+fn box_volume_names_are_unambiguous() {
+
+    let dnf = RtBoxVolumeConfig::parse("b-dnf:/var/cache/dnf", false).unwrap();
+    let other = RtBoxVolumeConfig::parse("dnf:/var/cache/dnf", false).unwrap();
+
+    assert_ne!(dnf.resolve("a", "/home/alex"), other.resolve("a-b", "/home/alex"));
+    assert_ne!(
+        volume::volume_name("dnf", Some("a_")),
+        volume::volume_name("_dnf", Some("a")),
+    );
+
+    /* a renamed box is recreated on the volume it was created with */
+    let named_volume = other.resolve("old_name", "/home/alex");
+    assert_eq!(named_volume.name, "rtbox-box-old__name_dnf");
+    let recorded = RtBoxVolumeConfig::from_named_volume(&named_volume, "new").unwrap();
+    assert_eq!(recorded.name, "dnf");
+    assert_eq!(recorded.resolve("new", "/home/alex"), named_volume);
+    assert_eq!(RtBoxVolumeConfig::from_named_volume(&named_volume, "old_name").unwrap().volume, None);
+}
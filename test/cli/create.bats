@@ -13,12 +13,70 @@ Create a rtbox container
 Usage: rtbox create [OPTIONS] <NAME>
 
 Arguments:
-  <NAME>  Container name, will also be used as part of the hostname
+  <NAME>
+          Container name, will also be used as part of the hostname
 
 Options:
-  -i, --image <IMAGE>  Image to use as base for the container
-  -H, --home <HOME>    Set a custom HOME directory for the container
-  -h, --help           Print help information
+  -i, --image <IMAGE>
+          Image to use as base for the container
+
+  -d, --distro <DISTRO>
+          Distro to create the container from, defaults to the host distro
+
+  -r, --release <RELEASE>
+          Release of the distro to use, defaults to the host release
+
+  -H, --home <HOME>
+          Set a custom HOME directory for the container
+
+  -p, --profile <PROFILE>
+          Security profile used to integrate the container with the host
+          
+          [default: default]
+
+          Possible values:
+          - default:    Full host integration, the host root is available at /run/host
+          - restricted: Only an allowlist of host paths is mounted, SELinux and seccomp stay enabled
+
+  -a, --additional-packages <ADDITIONAL_PACKAGES>
+          Packages to install when the container first starts, may be repeated or space separated
+
+      --pre-init-hook <PRE_INIT_HOOK>
+          Shell command run before packages are installed on first start, may be repeated
+
+      --init-hook <INIT_HOOK>
+          Shell command run at the end of initialization, may be repeated
+
+      --hook-timeout <HOOK_TIMEOUT>
+          Seconds each hook may run before it is killed
+
+      --hook-failure-policy <HOOK_FAILURE_POLICY>
+          What to do when a hook fails
+
+          Possible values:
+          - abort:    Stop initializing and mark the container as failed
+          - continue: Record the failure and keep running the remaining hooks
+
+      --init <INIT>
+          What runs as PID 1 inside the container
+          
+          [default: rtbox]
+
+          Possible values:
+          - rtbox:   rtbox init stays PID 1 and supervises the box
+          - systemd: The image's systemd becomes PID 1 and runs rtbox's setup as a oneshot unit
+
+      --ttl <TTL>
+          Let prune remove the container after this long, e.g. 12h or 7d
+
+      --volume <VOLUME>
+          Volume of this container as NAME:PATH, kept when the container is recreated, may be repeated
+
+      --shared-volume <SHARED_VOLUME>
+          Volume shared with every container using the same NAME as NAME:PATH, may be repeated
+
+  -h, --help
+          Print help (see a summary with '-h')
 EOF
 )"
 
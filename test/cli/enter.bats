@@ -10,13 +10,14 @@
   expected_output="$(cat <<EOF
 Enter into a new shell session inside a rtbox container
 
-Usage: rtbox enter <NAME>
+Usage: rtbox enter [OPTIONS] [NAME]
 
 Arguments:
-  <NAME>  Container to enter into
+  [NAME]  Container to enter into, defaults to the configured default box
 
 Options:
-  -h, --help  Print help information
+  -w, --workdir <WORKDIR>  Directory to start in, defaults to the current one when the container can see it
+  -h, --help               Print help
 EOF
 )"
 
@@ -13,14 +13,28 @@ A rust implementation of toolbx
 Usage: rtbox [OPTIONS] <COMMAND>
 
 Commands:
-  create  Create a rtbox container
-  rm      Remove a rtbox container
-  rmi     Remove a rtbox container image
-  run     Execute a command inside a rtbox container
-  enter   Enter into a new shell session inside a rtbox container
-  list    List all rtbox containers
-  export  Export an application, service or binary from a rtbox container to the host
-  help    Print this message or the help of the given subcommand(s)
+  create     Create a rtbox container
+  rm         Remove a rtbox container
+  rmi        Remove a rtbox container image
+  run        Execute a command inside a rtbox container
+  enter      Enter into a new shell session inside a rtbox container
+  list       List all rtbox containers
+  inspect    Show how a rtbox container was set up and its current state
+  export     Export an application, service or binary from a rtbox container to the host
+  adopt      Recreate a container created by toolbx so it is managed by rtbox
+  upgrade    Recreate a rtbox container on the latest version of its image, keeping its parameters
+  commit     Save a container as an image others can create rtbox containers from
+  clone      Copy a rtbox container into a new one with the same options
+  save       Save a rtbox container with its options and exports into an archive for another host
+  load       Recreate a rtbox container from an archive written by save
+  prune      Remove expired and unused containers, dangling toolbox images and orphaned exports
+  rename     Rename a rtbox container along with its hostname and exports
+  assemble   Create rtbox containers from a distrobox-assemble ini file
+  stop       Stop a rtbox container, letting init stop its services first
+  service    Manage the services supervised inside a rtbox container
+  volume     Manage the volumes rtbox containers keep package caches and toolchains in
+  host-exec  Run a command on the host from inside a rtbox container
+  help       Print this message or the help of the given subcommand(s)
 
 Options:
   -v, --verbose <VERBOSE>      
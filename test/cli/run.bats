@@ -10,14 +10,23 @@
   expected_output="$(cat <<EOF
 Execute a command inside a rtbox container
 
-Usage: rtbox run --container <CONTAINER> [CMD]...
+Usage: rtbox run [OPTIONS] [CMD]...
 
 Arguments:
   [CMD]...  Command to execute
 
 Options:
-  -c, --container <CONTAINER>  Container name
-  -h, --help                   Print help information
+  -c, --container <CONTAINER>  Container name, defaults to the configured default box
+  -i, --image <IMAGE>          Run in a temporary container created from this image instead
+      --rm                     Remove the temporary container once the command exits
+  -w, --workdir <WORKDIR>      Directory to run the command in, defaults to the current one when the container can see it
+  -u, --user <USER>            User to run the command as, defaults to the current one
+  -e, --env <ENV>              Set an environment variable as KEY=VALUE, a bare KEY takes the value from the host
+  -t, --tty                    Allocate a terminal even if stdin is not one
+  -T, --no-tty                 Never allocate a terminal
+  -d, --detach                 Run the command in the background and print its exec ID
+      --timeout <TIMEOUT>      Seconds after which the command and everything it started are killed
+  -h, --help                   Print help
 EOF
 )"
 